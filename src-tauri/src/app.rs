@@ -1,18 +1,45 @@
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::future::Future;
+use std::path::{Path, PathBuf};
 use std::result::Result;
 use std::u8;
 
-use crate::scanner::{Storage, StorageUpdater};
-use crate::task::{TaskLog, TaskLogData, TaskManager, TaskResolver};
+use crate::backend::Scope;
+use crate::desired::{DesiredState, Drift};
+use crate::effective::EffectiveEnv;
+use crate::expand::{DependencyGraph, Expander};
+use crate::export::{ExportOption, Exporter};
+use crate::inspect::{list_processes, read_environ, ProcessInfo, ProcessReport};
+use crate::launch::Launcher;
+use crate::lint::{Diagnostic, Linter};
+use crate::locate::{locate, Location};
+use crate::profile::{Profile, ProfileManager};
+use crate::scanner::{list_volumes, Categories, Storage, StorageUpdater, Volume, STORAGE_FILE};
+use crate::settings::Settings;
+use crate::shadow::ShadowReport;
+use crate::shim::{default_name, Shim, ShimManager};
+use crate::solver::{set_path, solve_path, PathPlan};
+use crate::suggest::{suggest_path_entries, Suggestion, MAX_SUGGESTIONS};
+use crate::task::{EnvDiff, ExternalChange, GroupLog, TaskLog, TaskLogData, TaskManager, TaskResolver};
+use crate::toolchain::{detect_toolchains, ToolInstalls};
+use crate::which::WhichReport;
 
 type EnvHashMap = HashMap<String, Vec<String>>;
 
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct SendState {
     env: EnvHashMap,
+    /// expanded values of the variables which reference others
+    expanded: EnvHashMap,
     dirty: bool,
+    active_profile: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FlushPreview {
+    diff: EnvDiff,
+    shadow: ShadowReport,
 }
 
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
@@ -21,8 +48,14 @@ pub struct TreeNode {
     abs_path: String,
     size: u64,
     scripts_count: u64,
+    /// files per category in the subtree, e.g. {"executable": 12, "library": 40}
+    categories: Categories,
     is_dir: bool,
     is_allowed: bool,
+    /// matched by the ignore rules, never scanned
+    is_ignored: bool,
+    /// only set on the roots, for the capacity bars
+    volume: Option<Volume>,
 }
 
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
@@ -57,10 +90,48 @@ impl Notification {
 }
 
 pub trait AppTaskAction {
-    fn flush(&mut self) -> Result<(), Box<dyn std::error::Error>>;
+    /// the notification mentions Process tasks, which are dropped instead of written
+    fn flush(&mut self) -> Result<Notification, Box<dyn std::error::Error>>;
     fn send_state(&self) -> SendState;
+    fn flush_preview(&self) -> FlushPreview;
     fn receive_state(&mut self, task: TaskLogData) -> ();
+    /// receive_state for callers outside the GUI, rejects tasks which do not apply to the pending env
+    fn try_receive_state(&mut self, task: TaskLogData) -> Result<(), String>;
     fn undo(&mut self) -> Notification;
+    fn external_changes(&self, live: &HashMap<Scope, EnvHashMap>) -> Vec<ExternalChange>;
+    /// move pending tasks onto the env changed by other programs
+    fn rebase(&mut self, live: HashMap<Scope, EnvHashMap>) -> Notification;
+}
+
+pub trait AppEnvAction {
+    fn export(&self, option: &ExportOption) -> String;
+    fn lint(&self) -> Vec<Diagnostic>;
+    fn fix_all(&mut self) -> Notification;
+    fn which(&self, command: &str) -> WhichReport;
+    fn shadow_report(&self) -> ShadowReport;
+    fn dependency_graph(&self) -> DependencyGraph;
+    /// variables which break if `variable` is deleted
+    fn dependents(&self, variable: &str) -> Vec<String>;
+    /// pending env of the machine scope
+    fn machine_env(&self) -> EnvHashMap;
+    /// machine scope merged with the pending user scope
+    fn effective_env(&self) -> EffectiveEnv;
+    /// runs processes with the pending effective env
+    fn launcher(&self) -> Launcher;
+    fn processes(&self) -> Vec<ProcessInfo>;
+    /// diff of a running process against the committed effective env
+    fn inspect_process(&self, pid: u32) -> Result<ProcessReport, String>;
+    /// drift of the pending env from a desired state file
+    fn check_desired(&self, path: &str) -> Result<Vec<Drift>, String>;
+    fn apply_desired(&mut self, path: &str) -> Notification;
+}
+
+pub trait AppProfileAction {
+    fn profiles(&self) -> Vec<Profile>;
+    fn save_profile(&mut self, profile: Profile) -> Notification;
+    fn delete_profile(&mut self, name: &str) -> Notification;
+    fn activate_profile(&mut self, name: &str) -> Notification;
+    fn deactivate_profile(&mut self) -> Notification;
 }
 
 pub trait AppFSTAction {
@@ -68,6 +139,31 @@ pub trait AppFSTAction {
     fn state(&mut self, option: Option<bool>) -> (bool, Notification);
     fn generater(&self) -> StorageUpdater;
     fn replace(&mut self, s: Storage); 
+    /// updater which rescans the folder only
+    fn subtree_generater(&self, path: &str) -> Result<StorageUpdater, String>;
+    /// scanned copies of `command`, with a task adding each to PATH
+    fn find_executable(&self, command: &str) -> Vec<Location>;
+    /// scanned directories worth adding to PATH, best first
+    fn suggest_path_entries(&self) -> Vec<Suggestion>;
+    /// JDK, Go, Android SDK and so on found in the scanned directories
+    fn toolchains(&self) -> Vec<ToolInstalls>;
+    /// the fewest PATH entries making `commands` available
+    fn solve_path(&self, commands: &[String]) -> PathPlan;
+    /// appends the `dirs` of a plan to the current user PATH
+    fn apply_path_plan(&mut self, dirs: &[String]) -> Notification;
+}
+
+pub trait AppShimAction {
+    fn shims(&self) -> Vec<Shim>;
+    /// `name` defaults to the file name of `target`, the shim directory is added to PATH once
+    fn create_shim(&mut self, target: &str, name: Option<&str>) -> Notification;
+    fn update_shim(&mut self, name: &str, target: &str) -> Notification;
+    fn remove_shim(&mut self, name: &str) -> Notification;
+}
+
+pub trait AppSettingAction {
+    fn settings(&self) -> Settings;
+    fn save_settings(&mut self, settings: Settings) -> Notification;
 }
 
 
@@ -75,50 +171,281 @@ pub trait AppFSTAction {
 pub struct AppState {
     tm: TaskManager,
     s: Storage,
+    profiles: ProfileManager,
+    shims: ShimManager,
+    settings: Settings,
+    data_dir: PathBuf,
 }
 
 impl AppState {
-    pub fn new() -> Self {
+    pub fn new(data_dir: PathBuf) -> Self {
         let mut tm = TaskManager::default();
         tm.init().unwrap();
 
-        Self { tm, s: Storage::load("output.csv") }
-    } 
+        Self {
+            tm,
+            s: Storage::load(data_dir.join(STORAGE_FILE).to_str().unwrap()),
+            profiles: ProfileManager::new(data_dir.clone()),
+            shims: ShimManager::new(&data_dir),
+            settings: Settings::load(&data_dir),
+            data_dir,
+        }
+    }
+    fn _committed_effective_env(&self) -> EnvHashMap {
+        EffectiveEnv::merge(
+            &self.tm.get_committed_env_of(Scope::Machine),
+            &self.tm.get_committed_env(),
+        )
+        .to_env()
+    }
     pub fn exit(&mut self) -> Result<(), Box<dyn std::error::Error>> {
         Ok(())
     }
 }
 
 impl AppTaskAction for AppState {
-    fn flush(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+    fn flush(&mut self) -> Result<Notification, Box<dyn std::error::Error>> {
+        let discarded = self.tm.pending_in(Scope::Process);
         self.tm.flush()?;
-        Ok(())
+        self.profiles.commit();
+        if discarded > 0 {
+            return Ok(Notification::warning(&format!(
+                "Flushed, {} change(s) of the Process scope only apply inside this app and were discarded",
+                discarded
+            )));
+        }
+        Ok(Notification::success("Flushed"))
     }
 
     fn send_state(&self) -> SendState {
         let env = self.tm.get_cur_env();
+        let expanded = Expander::new(&env).expand_env();
         let dirty = self.tm.is_dirty();
-        SendState { env, dirty }
+        let active_profile = self.profiles.active();
+        SendState {
+            env,
+            expanded,
+            dirty,
+            active_profile,
+        }
+    }
+
+    fn flush_preview(&self) -> FlushPreview {
+        let pending = self.tm.get_cur_env();
+        let committed = self.tm.get_committed_env();
+        // 遮蔽要看合并后的 PATH，用户的条目可能被 machine 的 git、node 挡住
+        let shadow = ShadowReport::new(&self.effective_env().to_env(), &self._committed_effective_env(), Some(&self.s));
+        FlushPreview {
+            diff: EnvDiff::between(&committed, &pending),
+            shadow,
+        }
     }
 
     fn receive_state(&mut self, task: TaskLogData) -> () {
         self.tm.add_task(task.into());
     }
 
+    fn try_receive_state(&mut self, task: TaskLogData) -> Result<(), String> {
+        let tasks: [TaskLog; 1] = [task.clone().into()];
+        for scope in [Scope::User, Scope::Machine, Scope::Process] {
+            let env = self.tm.get_cur_env_of(scope);
+            // ConsumeTask panics on conflicts, dont let it poison the state
+            let applied = std::panic::catch_unwind(|| TaskResolver::new(&env, &tasks).scope(scope).forward());
+            if applied.is_err() {
+                return Err(format!("Task does not apply to the pending {:?} variables", scope));
+            }
+        }
+        self.receive_state(task);
+        Ok(())
+    }
+
     fn undo(&mut self) -> Notification {
         let notification = match self.tm.try_undo() {
             Ok(msg) => Notification::success(&msg),
             Err(msg) => Notification::warning(&msg),
         };
+        // 撤销了 profile 的任务组，激活状态也要回退
+        self.profiles.sync(self.tm.pending());
         notification
     }
+
+    fn external_changes(&self, live: &HashMap<Scope, EnvHashMap>) -> Vec<ExternalChange> {
+        self.tm.external_changes(live)
+    }
+
+    fn rebase(&mut self, live: HashMap<Scope, EnvHashMap>) -> Notification {
+        let dropped = self.tm.rebase(live);
+        self.profiles.sync(self.tm.pending());
+        if dropped.is_empty() {
+            return Notification::success("Pending changes rebased onto the external changes");
+        }
+        let mut variables: Vec<&str> = dropped.iter().filter_map(|t| t.variable()).collect();
+        variables.sort();
+        variables.dedup();
+        Notification::warning(&format!(
+            "Rebased, {} pending changes of {} were dropped, they or their profile conflict with external changes",
+            dropped.len(),
+            variables.join(", ")
+        ))
+    }
+}
+
+impl AppEnvAction for AppState {
+    fn export(&self, option: &ExportOption) -> String {
+        let env = self.tm.get_cur_env();
+        Exporter::new(&env, option).export()
+    }
+
+    fn lint(&self) -> Vec<Diagnostic> {
+        let env = self.tm.get_cur_env();
+        Linter::new(&env, Some(&self.s)).lint()
+    }
+
+    fn fix_all(&mut self) -> Notification {
+        let env = self.tm.get_cur_env();
+        let (fixes, exhausted) = Linter::new(&env, Some(&self.s)).fix_all();
+        if fixes.is_empty() {
+            return Notification::info("Nothing to fix");
+        }
+        let n = fixes.len();
+        self.receive_state(TaskLogData::Group(GroupLog {
+            name: "fix lint diagnostics".to_string(),
+            tasks: fixes,
+        }));
+        if exhausted {
+            return Notification::warning(&format!(
+                "Applied {} fixes and stopped, some diagnostics are still fixable, run fix all again",
+                n
+            ));
+        }
+        Notification::success(&format!("Applied {} fixes, undo to revert them all", n))
+    }
+
+    fn check_desired(&self, path: &str) -> Result<Vec<Drift>, String> {
+        let desired = DesiredState::load(Path::new(path))?;
+        let env = self.tm.get_cur_env_of(desired.scope);
+        Ok(desired.check(&env))
+    }
+
+    fn apply_desired(&mut self, path: &str) -> Notification {
+        let desired = match DesiredState::load(Path::new(path)) {
+            Ok(d) => d,
+            Err(e) => return Notification::error(&e),
+        };
+        let env = self.tm.get_cur_env_of(desired.scope);
+        let tasks = desired.plan(&env);
+        if tasks.is_empty() {
+            return Notification::info("Already in the desired state");
+        }
+        let n = tasks.len();
+        self.receive_state(TaskLogData::Group(GroupLog {
+            name: format!("apply {}", path),
+            tasks,
+        }));
+        Notification::success(&format!("Applied {} changes, flush to write them", n))
+    }
+
+    fn which(&self, command: &str) -> WhichReport {
+        // Windows 上 PATH 是 machine 在前 user 在后拼起来的
+        let pending = self.effective_env().to_env();
+        let committed = self._committed_effective_env();
+        WhichReport::new(command, &pending, &committed)
+    }
+
+    fn shadow_report(&self) -> ShadowReport {
+        let pending = self.effective_env().to_env();
+        let committed = self._committed_effective_env();
+        ShadowReport::new(&pending, &committed, Some(&self.s))
+    }
+
+    fn dependency_graph(&self) -> DependencyGraph {
+        let env = self.tm.get_cur_env();
+        DependencyGraph::new(&env)
+    }
+
+    fn dependents(&self, variable: &str) -> Vec<String> {
+        self.dependency_graph().dependents(variable)
+    }
+
+    fn machine_env(&self) -> EnvHashMap {
+        self.tm.get_cur_env_of(Scope::Machine)
+    }
+
+    fn effective_env(&self) -> EffectiveEnv {
+        let machine = self.tm.get_cur_env_of(Scope::Machine);
+        let user = self.tm.get_cur_env();
+        EffectiveEnv::merge(&machine, &user)
+    }
+
+    fn launcher(&self) -> Launcher {
+        let pending = self.effective_env().to_env();
+        Launcher::new(&pending, &self._committed_effective_env())
+    }
+
+    fn processes(&self) -> Vec<ProcessInfo> {
+        list_processes()
+    }
+
+    fn inspect_process(&self, pid: u32) -> Result<ProcessReport, String> {
+        let process = list_processes()
+            .into_iter()
+            .find(|p| p.pid == pid)
+            .ok_or(format!("Process {} is not running", pid))?;
+        let process_env = read_environ(pid)?;
+        Ok(ProcessReport::new(process, &process_env, &self._committed_effective_env()))
+    }
 }
 
+impl AppProfileAction for AppState {
+    fn profiles(&self) -> Vec<Profile> {
+        self.profiles.list()
+    }
+
+    fn save_profile(&mut self, profile: Profile) -> Notification {
+        match self.profiles.save(&profile) {
+            Ok(_) => Notification::success(&format!("Profile '{}' saved", profile.name)),
+            Err(e) => Notification::error(&e.to_string()),
+        }
+    }
+
+    fn delete_profile(&mut self, name: &str) -> Notification {
+        if self.profiles.active().as_deref() == Some(name) {
+            return Notification::warning("Cannot delete the active profile, deactivate it first");
+        }
+        match self.profiles.delete(name) {
+            Ok(_) => Notification::success(&format!("Profile '{}' deleted", name)),
+            Err(e) => Notification::error(&e.to_string()),
+        }
+    }
+
+    fn activate_profile(&mut self, name: &str) -> Notification {
+        let env = self.tm.get_cur_env();
+        match self.profiles.activate(name, &env) {
+            Ok(task) => {
+                self.tm.add_task(task);
+                Notification::success(&format!("Profile '{}' activated", name))
+            }
+            Err(msg) => Notification::warning(&msg),
+        }
+    }
+
+    fn deactivate_profile(&mut self) -> Notification {
+        let env = self.tm.get_cur_env();
+        match self.profiles.deactivate(&env) {
+            Ok(task) => {
+                self.tm.add_task(task);
+                Notification::success("Profile deactivated")
+            }
+            Err(msg) => Notification::warning(&msg),
+        }
+    }
+}
 
 impl AppFSTAction for AppState {
     fn children(&self, abs_path: Option<&str>) -> Vec<TreeNode> {
-        let result = self.s.children(abs_path);
-        result.into_iter().map(|(abspath, node)| {
+        let result = self.s.children(abs_path, &self.settings.ignore_rules());
+        let volumes = if abs_path.is_none() { list_volumes() } else { vec![] };
+        result.into_iter().map(|(abspath, node, is_ignored)| {
             let abs_path = abspath.to_str().unwrap().to_string();
             TreeNode {
                 name: if let Some(name) = abspath.file_name() {
@@ -129,8 +456,11 @@ impl AppFSTAction for AppState {
                 abs_path,
                 size: node.size,
                 scripts_count: node.script_count,
+                categories: node.categories,
                 is_dir: abspath.is_dir(),
                 is_allowed: node.is_allowed,
+                is_ignored,
+                volume: volumes.iter().find(|v| v.mount_point == abspath).cloned(),
             }
         }).collect()
     }
@@ -155,10 +485,104 @@ impl AppFSTAction for AppState {
     fn generater(&self) -> StorageUpdater {
         let updater: StorageUpdater = self.s.clone().into();
         updater
+            .roots(&self.settings.scan_roots())
+            .ignore(self.settings.ignore_rules())
+            .classifier(self.settings.classifier())
+    }
+
+    fn subtree_generater(&self, path: &str) -> Result<StorageUpdater, String> {
+        if !Path::new(path).is_dir() {
+            return Err(format!("'{}' is not a directory", path));
+        }
+        Ok(self.generater())
+    }
+
+    fn find_executable(&self, command: &str) -> Vec<Location> {
+        let effective = self.effective_env().to_env();
+        locate(command, &self.s, &effective, &self.tm.get_cur_env())
+    }
+
+    fn suggest_path_entries(&self) -> Vec<Suggestion> {
+        let effective = self.effective_env().to_env();
+        suggest_path_entries(&self.s, &effective, &self.tm.get_cur_env(), MAX_SUGGESTIONS)
+    }
+
+    fn toolchains(&self) -> Vec<ToolInstalls> {
+        detect_toolchains(&self.s, &self.tm.get_cur_env_of(Scope::Machine), &self.tm.get_cur_env())
+    }
+
+    fn solve_path(&self, commands: &[String]) -> PathPlan {
+        let effective = self.effective_env().to_env();
+        solve_path(commands, &self.s, &effective)
+    }
+
+    fn apply_path_plan(&mut self, dirs: &[String]) -> Notification {
+        let task = match set_path(&self.tm.get_cur_env(), dirs) {
+            Some(t) => t,
+            None => return Notification::info("Every entry is on PATH already"),
+        };
+        match self.try_receive_state(task) {
+            Ok(_) => Notification::success(&format!("Added {} entries to PATH, review them before flushing", dirs.len())),
+            Err(msg) => Notification::warning(&msg),
+        }
     }
 
     fn replace(&mut self, s: Storage) {
-        s.dump("output.csv");
+        s.dump(self.data_dir.join(STORAGE_FILE).to_str().unwrap());
         self.s.replace(s);
     }
-}
\ No newline at end of file
+}
+
+impl AppShimAction for AppState {
+    fn shims(&self) -> Vec<Shim> {
+        self.shims.list()
+    }
+
+    fn create_shim(&mut self, target: &str, name: Option<&str>) -> Notification {
+        let name = match name.map(|n| n.to_string()).or_else(|| default_name(target)) {
+            Some(n) => n,
+            None => return Notification::warning(&format!("Cannot name a shim for '{}'", target)),
+        };
+        if let Err(e) = self.shims.create(&name, target) {
+            return Notification::warning(&e);
+        }
+        let effective = self.effective_env().to_env();
+        match self.shims.path_task(&self.tm.get_cur_env(), &effective) {
+            Some(task) => {
+                self.receive_state(task);
+                Notification::success(&format!("Shim '{}' created, the shim directory will be added to PATH", name))
+            }
+            None => Notification::success(&format!("Shim '{}' created", name)),
+        }
+    }
+
+    fn update_shim(&mut self, name: &str, target: &str) -> Notification {
+        match self.shims.update(name, target) {
+            Ok(_) => Notification::success(&format!("Shim '{}' updated", name)),
+            Err(e) => Notification::warning(&e),
+        }
+    }
+
+    fn remove_shim(&mut self, name: &str) -> Notification {
+        match self.shims.remove(name) {
+            Ok(_) => Notification::success(&format!("Shim '{}' removed", name)),
+            Err(e) => Notification::warning(&e),
+        }
+    }
+}
+
+impl AppSettingAction for AppState {
+    fn settings(&self) -> Settings {
+        self.settings.clone()
+    }
+
+    fn save_settings(&mut self, settings: Settings) -> Notification {
+        match settings.save(&self.data_dir) {
+            Ok(_) => {
+                self.settings = settings;
+                Notification::success("Settings saved")
+            }
+            Err(e) => Notification::error(&e),
+        }
+    }
+}
@@ -0,0 +1,360 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::task::EnvHashMap;
+
+/// separates multiple values of one variable
+#[cfg(windows)]
+pub const SEPARATOR: &str = ";";
+#[cfg(not(windows))]
+pub const SEPARATOR: &str = ":";
+
+/// where a variable lives, same as `[EnvironmentVariableTarget]` on Windows
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub enum Scope {
+    #[default]
+    User,
+    Machine,
+    /// environment of this process, never written back
+    Process,
+}
+
+#[derive(Debug)]
+pub enum BackendError {
+    /// writing the scope needs administrator or root
+    RequiresElevation(Scope),
+    Failed(String),
+}
+
+impl std::fmt::Display for BackendError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BackendError::RequiresElevation(scope) => {
+                write!(f, "Writing {:?} variables requires elevation, restart as administrator", scope)
+            }
+            BackendError::Failed(msg) => write!(f, "Failed to write variables: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for BackendError {}
+
+pub fn get_environment_variables(scope: Scope) -> EnvHashMap {
+    let data: HashMap<String, String> = match scope {
+        Scope::Process => std::env::vars().collect(),
+        _ => platform::read(scope),
+    };
+    to_env(data)
+}
+
+/// reads several stored scopes at once, on Windows this is a single powershell run
+pub fn get_stored_environment_variables(scopes: &[Scope]) -> HashMap<Scope, EnvHashMap> {
+    platform::read_all(scopes)
+        .into_iter()
+        .map(|(scope, data)| (scope, to_env(data)))
+        .collect()
+}
+
+fn to_env(data: HashMap<String, String>) -> EnvHashMap {
+    let mut env = HashMap::new();
+    env.extend(data.into_iter().map(|(k, v)| {
+        let values = split_value(&k, &v);
+        (k, values)
+    }));
+    env
+}
+
+/// updates: (variable, values), deletes: variable
+pub fn set_environment_variables(
+    scope: Scope,
+    updates: &[(String, Vec<String>)],
+    deletes: &[String],
+) -> Result<(), BackendError> {
+    if updates.is_empty() && deletes.is_empty() {
+        return Ok(());
+    }
+    match scope {
+        // previews only, nothing to write back
+        Scope::Process => Ok(()),
+        _ => platform::write(scope, updates, deletes),
+    }
+}
+
+/// variables holding a list of directories, e.g. PATH, PYTHONPATH, XDG_DATA_DIRS
+pub fn is_list_variable(name: &str) -> bool {
+    let upper = name.to_uppercase();
+    upper.ends_with("PATH") || upper.ends_with("DIRS") || upper == "PATHEXT"
+}
+
+/// only list variables are split, `http_proxy=http://host:8080` stays one value
+pub fn split_value(name: &str, value: &str) -> Vec<String> {
+    if !is_list_variable(name) {
+        return if value.trim().is_empty() { vec![] } else { vec![value.to_string()] };
+    }
+    value
+        .split(SEPARATOR)
+        .map(|s| s.to_string())
+        .filter(|x| if x.trim().is_empty() { false } else { true })
+        .collect()
+}
+
+/// a single quoted powershell string, nothing inside is expanded. powershell also takes
+/// the typographic quotes as single quotes, so they are doubled as well
+pub fn powershell_literal(s: &str) -> String {
+    let mut quoted = String::from("'");
+    for c in s.chars() {
+        if matches!(c, '\'' | '\u{2018}' | '\u{2019}' | '\u{201a}' | '\u{201b}') {
+            quoted.push(c);
+        }
+        quoted.push(c);
+    }
+    quoted.push('\'');
+    quoted
+}
+
+#[test]
+fn test_powershell_literal() {
+    assert_eq!(powershell_literal("C:\\jdk"), "'C:\\jdk'");
+    assert_eq!(powershell_literal("$HOME \"it's\""), "'$HOME \"it''s\"'");
+    assert_eq!(powershell_literal("a\u{2019}b"), "'a\u{2019}\u{2019}b'");
+}
+
+#[cfg(windows)]
+mod platform {
+    use std::collections::HashMap;
+    use std::os::windows::process::CommandExt as _;
+    use std::process::Command;
+
+    use super::{is_list_variable, powershell_literal as literal, BackendError, Scope};
+
+    // forces powershell to output UTF-8, or else it will output UTF-16, stdout cannot be decoded
+    const FORCE_UTF8: &str = r#"[console]::OutputEncoding = [System.Text.Encoding]::UTF8"#;
+
+    fn target(scope: Scope) -> &'static str {
+        match scope {
+            Scope::User => "[EnvironmentVariableTarget]::User",
+            Scope::Machine => "[EnvironmentVariableTarget]::Machine",
+            Scope::Process => "[EnvironmentVariableTarget]::Process",
+        }
+    }
+
+    fn powershell(script: &str) -> std::process::Output {
+        Command::new("powershell")
+            .arg(script)
+            .creation_flags(0x08000000) // CREATE_NO_WINDOW
+            .output()
+            .expect("failed to execute process")
+    }
+
+    pub fn read(scope: Scope) -> HashMap<String, String> {
+        let get_env = format!(
+            "[Environment]::GetEnvironmentVariables({}) | ConvertTo-Json",
+            target(scope)
+        );
+        let output = powershell(&[FORCE_UTF8, get_env.as_str()].join(";"));
+        let stdout = String::from_utf8(output.stdout).expect("Failed to decode UTF-8");
+        serde_json::from_str(&stdout).expect("Failed to deserialize JSON")
+    }
+
+    pub fn read_all(scopes: &[Scope]) -> HashMap<Scope, HashMap<String, String>> {
+        // @{ User = ...; Machine = ... }, one object per scope
+        let entries: Vec<String> = scopes
+            .iter()
+            .map(|s| format!("{:?} = [Environment]::GetEnvironmentVariables({})", s, target(*s)))
+            .collect();
+        let get_env = format!("@{{ {} }} | ConvertTo-Json", entries.join("; "));
+        let output = powershell(&[FORCE_UTF8, get_env.as_str()].join(";"));
+        let stdout = String::from_utf8(output.stdout).expect("Failed to decode UTF-8");
+        let mut all: HashMap<String, HashMap<String, String>> =
+            serde_json::from_str(&stdout).expect("Failed to deserialize JSON");
+        scopes
+            .iter()
+            .map(|s| (*s, all.remove(&format!("{:?}", s)).unwrap_or_default()))
+            .collect()
+    }
+
+    pub fn write(scope: Scope, updates: &[(String, Vec<String>)], deletes: &[String]) -> Result<(), BackendError> {
+        // stop at the first rejected write, so that the exit code tells
+        let mut tasks = vec!["$ErrorActionPreference = 'Stop'".to_string()];
+        for (k, v) in updates.iter() {
+            println!("update '{}': '{:?}'", k, v);
+            // an empty list needs the extra ;, or the variable will be removed.
+            // other values are read back as one value, so they are written as is
+            let value = if is_list_variable(k) { format!(";{}", v.join(";")) } else { v.join(";") };
+            tasks.push(format!(
+                "[Environment]::SetEnvironmentVariable({}, {}, {})",
+                literal(k),
+                literal(&value),
+                target(scope)
+            ));
+        }
+        for k in deletes.iter() {
+            println!("delete '{}'", k);
+            tasks.push(format!(
+                "[Environment]::SetEnvironmentVariable({}, $null, {})",
+                literal(k),
+                target(scope)
+            ));
+        }
+        let output = powershell(&tasks.join(";"));
+        if output.status.success() {
+            return Ok(());
+        }
+        let stderr = String::from_utf8_lossy(&output.stderr).to_string();
+        if stderr.contains("SecurityException") || stderr.contains("registry access is not allowed") {
+            return Err(BackendError::RequiresElevation(scope));
+        }
+        Err(BackendError::Failed(stderr))
+    }
+}
+
+#[cfg(not(windows))]
+mod platform {
+    use std::collections::HashMap;
+    use std::fs;
+    use std::io::ErrorKind;
+    use std::path::{Path, PathBuf};
+
+    use super::{BackendError, Scope};
+
+    const MACHINE_FILE: &str = "/etc/environment";
+    /// the only file of environment.d written by us
+    const USER_FILE: &str = "90-environmentor.conf";
+
+    pub fn user_dir() -> PathBuf {
+        let config = match std::env::var("XDG_CONFIG_HOME") {
+            Ok(c) if !c.is_empty() => PathBuf::from(c),
+            _ => PathBuf::from(std::env::var("HOME").unwrap_or_default()).join(".config"),
+        };
+        config.join("environment.d")
+    }
+
+    pub fn read(scope: Scope) -> HashMap<String, String> {
+        match scope {
+            Scope::Machine => parse(&fs::read_to_string(MACHINE_FILE).unwrap_or_default()),
+            _ => {
+                // later files override the former ones, see environment.d(5)
+                let mut files: Vec<PathBuf> = match fs::read_dir(user_dir()) {
+                    Ok(entries) => entries
+                        .filter_map(|e| e.ok())
+                        .map(|e| e.path())
+                        .filter(|p| p.extension().map_or(false, |ext| ext == "conf"))
+                        .collect(),
+                    Err(_) => vec![],
+                };
+                files.sort();
+                let mut env = HashMap::new();
+                for f in files {
+                    env.extend(parse(&fs::read_to_string(f).unwrap_or_default()));
+                }
+                env
+            }
+        }
+    }
+
+    pub fn read_all(scopes: &[Scope]) -> HashMap<Scope, HashMap<String, String>> {
+        scopes.iter().map(|s| (*s, read(*s))).collect()
+    }
+
+    pub fn write(scope: Scope, updates: &[(String, Vec<String>)], deletes: &[String]) -> Result<(), BackendError> {
+        let path = match scope {
+            Scope::Machine => PathBuf::from(MACHINE_FILE),
+            _ => user_dir().join(USER_FILE),
+        };
+        write_file(&path, scope, updates, deletes)
+    }
+
+    fn write_file(path: &Path, scope: Scope, updates: &[(String, Vec<String>)], deletes: &[String]) -> Result<(), BackendError> {
+        // 注释和认不出的行原样保留，只改动我们写的变量所在的行
+        let mut lines: Vec<String> = fs::read_to_string(&path)
+            .unwrap_or_default()
+            .lines()
+            .map(|l| l.to_string())
+            .collect();
+        let position = |lines: &[String], k: &str| lines.iter().rposition(|l| parse_line(l).map_or(false, |(key, _)| key == k));
+        for (k, v) in updates.iter() {
+            println!("update '{}': '{:?}'", k, v);
+            let line = format_line(k, &v.join(super::SEPARATOR));
+            match position(&lines, k) {
+                Some(i) => lines[i] = line,
+                None => lines.push(line),
+            }
+        }
+        for k in deletes.iter() {
+            println!("delete '{}'", k);
+            if position(&lines, k).is_none() {
+                return Err(BackendError::Failed(format!(
+                    "'{}' is not defined in {:?}, remove it where it is defined",
+                    k, path
+                )));
+            }
+            lines.retain(|l| parse_line(l).map_or(true, |(key, _)| key != *k));
+        }
+        let content: String = lines.iter().map(|l| format!("{}\n", l)).collect();
+        let to_backend_error = |e: std::io::Error| match e.kind() {
+            ErrorKind::PermissionDenied => BackendError::RequiresElevation(scope),
+            _ => BackendError::Failed(e.to_string()),
+        };
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(to_backend_error)?;
+        }
+        fs::write(path, content).map_err(to_backend_error)
+    }
+
+    fn parse(content: &str) -> HashMap<String, String> {
+        parse_ordered(content).into_iter().collect()
+    }
+
+    /// `KEY=value` lines, values may be quoted, comments start with '#'
+    fn parse_ordered(content: &str) -> Vec<(String, String)> {
+        content.lines().filter_map(parse_line).collect()
+    }
+
+    fn parse_line(line: &str) -> Option<(String, String)> {
+        let l = line.trim();
+        if l.is_empty() || l.starts_with('#') {
+            return None;
+        }
+        let l = l.strip_prefix("export ").unwrap_or(l);
+        let (k, v) = l.split_once('=')?;
+        let v = v.trim();
+        let v = if v.len() >= 2 && (v.starts_with('"') && v.ends_with('"') || v.starts_with('\'') && v.ends_with('\'')) {
+            v[1..v.len() - 1].replace("\\\"", "\"").replace(r"\\", r"\")
+        } else {
+            v.to_string()
+        };
+        Some((k.trim().to_string(), v))
+    }
+
+    fn format_line(k: &str, v: &str) -> String {
+        format!("{}=\"{}\"", k, v.replace('\\', r"\\").replace('"', "\\\""))
+    }
+
+    #[test]
+    fn test_parse_environment_file() {
+        let env = parse("# comment\nPATH=\"/usr/bin:/bin\"\nexport EDITOR=vim\nLANG='C'\n");
+        assert_eq!(env["PATH"], "/usr/bin:/bin");
+        assert_eq!(env["EDITOR"], "vim");
+        assert_eq!(env["LANG"], "C");
+    }
+
+    #[test]
+    fn test_write_keeps_other_lines() {
+        let dir = crate::testutil::TempDir::new("backend_write");
+        let file = dir.join(USER_FILE);
+        fs::write(&file, "# managed by hand\nEDITOR=vim\nnot a variable\nLANG=C\n").unwrap();
+        let updates = vec![("EDITOR".to_string(), vec!["nano".to_string()]), ("NEW".to_string(), vec!["1".to_string()])];
+        write_file(&file, Scope::User, &updates, &["LANG".to_string()]).unwrap();
+        let content = fs::read_to_string(&file).unwrap();
+        assert_eq!(content, "# managed by hand\nEDITOR=\"nano\"\nnot a variable\nNEW=\"1\"\n");
+    }
+}
+
+#[test]
+fn test_split_only_list_variables() {
+    let path = format!("/usr/bin{}/bin", SEPARATOR);
+    assert_eq!(split_value("PATH", &path), vec!["/usr/bin", "/bin"]);
+    let proxy = format!("http://host{}8080", SEPARATOR);
+    assert_eq!(split_value("http_proxy", &proxy), vec![proxy.clone()]);
+    assert!(split_value("EMPTY", "").is_empty());
+}
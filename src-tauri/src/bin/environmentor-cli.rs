@@ -0,0 +1,4 @@
+// a console program on purpose, the app itself is built for the windows subsystem
+fn main() {
+    std::process::exit(tauri_app_lib::cli::main())
+}
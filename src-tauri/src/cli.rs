@@ -0,0 +1,410 @@
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use crate::backend::{BackendError, Scope};
+use crate::desired::DesiredState;
+use crate::effective::existing_key;
+use crate::scanner::{Storage, StorageUpdater, STORAGE_FILE};
+use crate::settings::Settings;
+use crate::task::{
+    get_live_env, AddValueLog, AddVariableLog, DeleteValueLog, DeleteVariableLog, EnvDiff, EnvHashMap, GroupLog,
+    InsertValueLog, SetVariableLog, TaskLogData, TaskManager,
+};
+
+/// same as `identifier` in tauri.conf.json, so the data dir is shared with the app
+const IDENTIFIER: &str = "com.jw238.environmentor";
+const TASKS_FILE: &str = "tasks.json";
+/// flushed tasks kept in tasks.json, pending ones are always kept
+const MAX_HISTORY: usize = 1000;
+
+const USAGE: &str = "Usage: environmentor-cli [--json] [--scope user|machine] <command> [args]
+
+Commands:
+    list                      names of the pending variables
+    get <VAR>                 values of a variable
+    set <VAR> <VALUE>...      replace all values, the variable is created if missing
+    append <VAR> <VALUE>      add a value to the end
+    prepend <VAR> <VALUE>     add a value to the front
+    remove <VAR> [VALUE]      remove a value, or the whole variable
+    rename <OLD> <NEW>        move the values of a variable to a new name
+    diff                      pending changes which are not flushed yet
+    flush                     write pending changes to the system
+    undo                      drop the last pending change
+    history                   every task recorded
+    scan                      rescan the file system for executables
+    check <FILE>              report drift from a desired state file, fails on drift
+    apply <FILE>              add the changes reaching a desired state file
+
+Exit codes: 0 ok, 1 failed, 2 bad usage, 3 requires elevation";
+
+#[derive(Debug)]
+enum CliError {
+    Usage(String),
+    Failed(String),
+    RequiresElevation(String),
+}
+
+impl CliError {
+    fn code(&self) -> i32 {
+        match self {
+            CliError::Failed(_) => 1,
+            CliError::Usage(_) => 2,
+            CliError::RequiresElevation(_) => 3,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+struct Args {
+    command: String,
+    operands: Vec<String>,
+    json: bool,
+    scope: Scope,
+}
+
+/// entry of the `environmentor-cli` binary, returns the exit code
+pub fn main() -> i32 {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let result = parse_args(&args).and_then(|args| run(&args));
+    match result {
+        Ok(_) => 0,
+        Err(e) => {
+            match &e {
+                CliError::Usage(msg) => eprintln!("{}\n\n{}", msg, USAGE),
+                CliError::Failed(msg) | CliError::RequiresElevation(msg) => eprintln!("error: {}", msg),
+            }
+            e.code()
+        }
+    }
+}
+
+fn parse_args(args: &[String]) -> Result<Args, CliError> {
+    let mut json = false;
+    let mut scope = Scope::User;
+    let mut rest = vec![];
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--json" => json = true,
+            "--scope" => {
+                scope = match iter.next().map(|s| s.to_lowercase()).as_deref() {
+                    Some("user") => Scope::User,
+                    Some("machine") => Scope::Machine,
+                    other => return Err(CliError::Usage(format!("unknown scope {:?}", other))),
+                }
+            }
+            "-h" | "--help" => rest.insert(0, "help".to_string()),
+            _ => rest.push(arg.clone()),
+        }
+    }
+    if rest.is_empty() {
+        return Err(CliError::Usage("missing command".to_string()));
+    }
+    let command = rest.remove(0);
+    Ok(Args {
+        command,
+        operands: rest,
+        json,
+        scope,
+    })
+}
+
+fn run(args: &Args) -> Result<(), CliError> {
+    if args.command == "help" {
+        println!("{}", USAGE);
+        return Ok(());
+    }
+    let path = data_dir().join(TASKS_FILE);
+    let mut tm = load_task_manager(&path);
+    let env = tm.get_cur_env_of(args.scope);
+    let scope = args.scope;
+    let ops = &args.operands;
+
+    let task = match (args.command.as_str(), ops.len()) {
+        ("list", 0) => {
+            let mut names: Vec<&String> = env.keys().collect();
+            names.sort();
+            return print(args.json, &names, || names.iter().map(|n| n.to_string()).collect());
+        }
+        ("get", 1) => {
+            let values = env
+                .get(&key(&env, &ops[0]))
+                .ok_or(CliError::Failed(format!("variable '{}' not found", ops[0])))?;
+            return print(args.json, values, || values.clone());
+        }
+        ("diff", 0) => {
+            let diffs: HashMap<Scope, EnvDiff> = [Scope::User, Scope::Machine]
+                .into_iter()
+                .map(|s| (s, EnvDiff::between(&tm.get_committed_env_of(s), &tm.get_cur_env_of(s))))
+                .filter(|(_, d)| !d.is_empty())
+                .collect();
+            return print(args.json, &diffs, || {
+                diffs
+                    .iter()
+                    .flat_map(|(s, d)| {
+                        d.changes.iter().map(move |c| {
+                            format!("[{:?}] {}: {:?} -> {:?}", s, c.variable, c.old, c.new)
+                        })
+                    })
+                    .collect()
+            });
+        }
+        ("history", 0) => {
+            let history = tm.history();
+            return print(args.json, &history, || {
+                history
+                    .iter()
+                    .map(|t| format!("{} {}", t.timestamp, serde_json::to_string(&t.data).unwrap()))
+                    .collect()
+            });
+        }
+        ("scan", 0) => {
+            let settings = Settings::load(&data_dir());
+            let storage_file = data_dir().join(STORAGE_FILE);
+            let storage_file = storage_file.to_str().unwrap();
+            let updater: StorageUpdater = Storage::load(storage_file).into();
+            let storage = updater
+                .roots(&settings.scan_roots())
+                .ignore(settings.ignore_rules())
+                .classifier(settings.classifier())
+                .consume();
+            storage.dump(storage_file);
+            let scripts: u64 = storage
+                .children(None, &settings.ignore_rules())
+                .iter()
+                .map(|(_, n, _)| n.script_count)
+                .sum();
+            return print(args.json, &scripts, || vec![format!("{} scripts found", scripts)]);
+        }
+        ("flush", 0) => {
+            let discarded = tm.pending_in(Scope::Process);
+            if discarded > 0 {
+                eprintln!("{} change(s) of the Process scope are not written and will be discarded", discarded);
+            }
+            tm.flush().map_err(|e| match e.downcast_ref::<BackendError>() {
+                Some(BackendError::RequiresElevation(_)) => CliError::RequiresElevation(e.to_string()),
+                _ => CliError::Failed(e.to_string()),
+            })?;
+            save_task_manager(&tm, &path)?;
+            return Ok(());
+        }
+        ("undo", 0) => {
+            let msg = tm.try_undo().map_err(|e| CliError::Failed(e.to_string()))?;
+            save_task_manager(&tm, &path)?;
+            return print(args.json, &msg, || vec![msg.clone()]);
+        }
+        ("check", 1) => {
+            let desired = DesiredState::load(Path::new(&ops[0])).map_err(CliError::Failed)?;
+            let drifts = desired.check(&tm.get_cur_env_of(desired.scope));
+            print(args.json, &drifts, || drifts.iter().map(|d| format!("{:?}", d)).collect())?;
+            if drifts.is_empty() {
+                return Ok(());
+            }
+            return Err(CliError::Failed(format!("{} drifts found", drifts.len())));
+        }
+        ("apply", 1) => {
+            let desired = DesiredState::load(Path::new(&ops[0])).map_err(CliError::Failed)?;
+            let tasks = desired.plan(&tm.get_cur_env_of(desired.scope));
+            if tasks.is_empty() {
+                return Ok(());
+            }
+            TaskLogData::Group(GroupLog {
+                name: format!("apply {}", ops[0]),
+                tasks,
+            })
+        }
+        ("set", n) if n >= 2 => set_task(&env, scope, &ops[0], &ops[1..]),
+        ("append", 2) => append_task(&env, scope, &ops[0], &ops[1]),
+        ("prepend", 2) => prepend_task(&env, scope, &ops[0], &ops[1]),
+        ("remove", 1) => remove_task(&env, scope, &ops[0], None)?,
+        ("remove", 2) => remove_task(&env, scope, &ops[0], Some(&ops[1]))?,
+        ("rename", 2) => rename_task(&env, scope, &ops[0], &ops[1])?,
+        (command, _) => return Err(CliError::Usage(format!("bad arguments for '{}'", command))),
+    };
+    tm.add_task(task.into());
+    save_task_manager(&tm, &path)
+}
+
+/// JSON for scripts, one line per item for humans
+fn print<T: Serialize>(json: bool, value: &T, lines: impl Fn() -> Vec<String>) -> Result<(), CliError> {
+    if json {
+        println!("{}", serde_json::to_string_pretty(value).unwrap());
+    } else {
+        lines().iter().for_each(|l| println!("{}", l));
+    }
+    Ok(())
+}
+
+/// same as `app_data_dir()` of tauri
+fn data_dir() -> PathBuf {
+    let home = PathBuf::from(std::env::var("HOME").or(std::env::var("USERPROFILE")).unwrap_or_default());
+    let base = if cfg!(windows) {
+        std::env::var("APPDATA").map(PathBuf::from).unwrap_or(home.join("AppData").join("Roaming"))
+    } else if cfg!(target_os = "macos") {
+        home.join("Library").join("Application Support")
+    } else {
+        match std::env::var("XDG_DATA_HOME") {
+            Ok(d) if !d.is_empty() => PathBuf::from(d),
+            _ => home.join(".local").join("share"),
+        }
+    };
+    base.join(IDENTIFIER)
+}
+
+// 读取上次未 flush 的任务，并移到当前系统环境上
+fn load_task_manager(path: &PathBuf) -> TaskManager {
+    match TaskManager::load(path) {
+        Some(mut tm) => {
+            for task in tm.rebase(get_live_env()) {
+                eprintln!(
+                    "warning: dropped pending change of '{}', it was changed externally",
+                    task.variable().unwrap_or_default()
+                );
+            }
+            tm.trim_history(MAX_HISTORY);
+            tm
+        }
+        None => {
+            let mut tm = TaskManager::default();
+            tm.init().unwrap();
+            tm
+        }
+    }
+}
+
+fn save_task_manager(tm: &TaskManager, path: &PathBuf) -> Result<(), CliError> {
+    tm.dump(path)
+        .map_err(|e| CliError::Failed(format!("cannot save {:?}: {}", path, e)))
+}
+
+/// the casing `variable` already has in `env`, names are case-insensitive on Windows
+fn key(env: &EnvHashMap, variable: &str) -> String {
+    existing_key(env, variable).unwrap_or(variable.to_string())
+}
+
+/// tasks on a missing variable are grouped with its creation
+fn with_variable(env: &EnvHashMap, scope: Scope, name: &str, variable: &str, task: TaskLogData) -> TaskLogData {
+    if env.contains_key(variable) {
+        return task;
+    }
+    let add = TaskLogData::AddVariable(AddVariableLog {
+        variable: variable.to_string(),
+        scope,
+    });
+    TaskLogData::Group(GroupLog {
+        name: format!("{} {}", name, variable),
+        tasks: vec![add, task],
+    })
+}
+
+fn set_task(env: &EnvHashMap, scope: Scope, variable: &str, values: &[String]) -> TaskLogData {
+    let variable = &key(env, variable);
+    let task = TaskLogData::SetVariable(SetVariableLog {
+        variable: variable.to_string(),
+        old_values: env.get(variable).cloned().unwrap_or_default(),
+        new_values: values.to_vec(),
+        scope,
+    });
+    with_variable(env, scope, "set", variable, task)
+}
+
+fn append_task(env: &EnvHashMap, scope: Scope, variable: &str, value: &str) -> TaskLogData {
+    let variable = &key(env, variable);
+    let task = TaskLogData::AppendValue(AddValueLog {
+        variable: variable.to_string(),
+        value: value.to_string(),
+        scope,
+    });
+    with_variable(env, scope, "append", variable, task)
+}
+
+fn prepend_task(env: &EnvHashMap, scope: Scope, variable: &str, value: &str) -> TaskLogData {
+    let variable = &key(env, variable);
+    let task = TaskLogData::InsertValue(InsertValueLog {
+        variable: variable.to_string(),
+        index: 0,
+        value: value.to_string(),
+        scope,
+    });
+    with_variable(env, scope, "prepend", variable, task)
+}
+
+fn remove_task(env: &EnvHashMap, scope: Scope, variable: &str, value: Option<&String>) -> Result<TaskLogData, CliError> {
+    let variable = &key(env, variable);
+    let values = env
+        .get(variable)
+        .ok_or(CliError::Failed(format!("variable '{}' not found", variable)))?;
+    let value = match value {
+        Some(v) => v,
+        None => {
+            return Ok(TaskLogData::DelVariable(DeleteVariableLog {
+                variable: variable.to_string(),
+                values: values.clone(),
+                scope,
+            }))
+        }
+    };
+    let index = values
+        .iter()
+        .position(|v| v == value)
+        .ok_or(CliError::Failed(format!("'{}' not found in '{}'", value, variable)))?;
+    Ok(TaskLogData::DeleteValue(DeleteValueLog {
+        variable: variable.to_string(),
+        index,
+        value: value.clone(),
+        scope,
+    }))
+}
+
+fn rename_task(env: &EnvHashMap, scope: Scope, old: &str, new: &str) -> Result<TaskLogData, CliError> {
+    let old = &key(env, old);
+    // 只改大小写时 new 找到的就是 old 本身
+    if existing_key(env, new).map_or(false, |k| &k != old) {
+        return Err(CliError::Failed(format!("variable '{}' already exists", new)));
+    }
+    let values = env
+        .get(old)
+        .ok_or(CliError::Failed(format!("variable '{}' not found", old)))?;
+    let tasks = vec![
+        TaskLogData::DelVariable(DeleteVariableLog {
+            variable: old.to_string(),
+            values: values.clone(),
+            scope,
+        }),
+        TaskLogData::AddVariable(AddVariableLog {
+            variable: new.to_string(),
+            scope,
+        }),
+        TaskLogData::SetVariable(SetVariableLog {
+            variable: new.to_string(),
+            old_values: vec![],
+            new_values: values.clone(),
+            scope,
+        }),
+    ];
+    Ok(TaskLogData::Group(GroupLog {
+        name: format!("rename {} to {}", old, new),
+        tasks,
+    }))
+}
+
+#[test]
+fn test_cli_tasks() {
+    use crate::task::ConsumeTask;
+    let args: Vec<String> = ["--json", "append", "PATH", "/opt/bin"].iter().map(|s| s.to_string()).collect();
+    let args = parse_args(&args).unwrap();
+    assert!(args.json);
+    assert_eq!(args.command, "append");
+
+    let mut env = EnvHashMap::new();
+    env.insert("PATH".to_string(), vec!["/bin".to_string()]);
+    let mut map = env.clone();
+    prepend_task(&env, Scope::User, "PATH", "/opt/bin").forward(&mut map);
+    append_task(&env, Scope::User, "EDITOR", "vim").forward(&mut map);
+    rename_task(&map.clone(), Scope::User, "EDITOR", "VISUAL").unwrap().forward(&mut map);
+    assert_eq!(map["PATH"], vec!["/opt/bin", "/bin"]);
+    assert_eq!(map["VISUAL"], vec!["vim"]);
+    assert!(!map.contains_key("EDITOR"));
+    assert!(remove_task(&map, Scope::User, "PATH", Some(&"/usr/bin".to_string())).is_err());
+}
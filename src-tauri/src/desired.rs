@@ -0,0 +1,283 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::backend::Scope;
+use crate::effective::existing_key;
+use crate::lint::{is_path_variable, normalize};
+use crate::task::{push_task, AddVariableLog, EnvHashMap, SetVariableLog, TaskLogData};
+
+/// rules are applied again until none is violated, gives up on contradicting rules
+const MAX_ORDER_PASSES: usize = 32;
+
+/// a variable is either one value or a list of values
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(untagged)]
+pub enum DesiredValue {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl DesiredValue {
+    pub fn values(&self) -> Vec<String> {
+        match self {
+            DesiredValue::One(v) => vec![v.clone()],
+            DesiredValue::Many(v) => v.clone(),
+        }
+    }
+}
+
+/// `before` must come earlier in PATH than `after`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OrderRule {
+    pub before: String,
+    pub after: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PathRules {
+    #[serde(default)]
+    pub required: Vec<String>,
+    #[serde(default)]
+    pub forbidden: Vec<String>,
+    #[serde(default)]
+    pub order: Vec<OrderRule>,
+}
+
+/// the environment a machine should have, e.g.
+/// ```toml
+/// scope = "User"
+///
+/// [variables]
+/// JAVA_HOME = 'C:\jdk-17'
+///
+/// [path]
+/// required = ['%JAVA_HOME%\bin']
+/// forbidden = ['C:\jdk-8\bin']
+/// order = [{ before = '%JAVA_HOME%\bin', after = 'C:\Windows\system32' }]
+/// ```
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DesiredState {
+    #[serde(default)]
+    pub scope: Scope,
+    /// variables which must have exactly these values
+    #[serde(default)]
+    pub variables: BTreeMap<String, DesiredValue>,
+    #[serde(default)]
+    pub path: PathRules,
+}
+
+/// dont modify the names of these enum, compatiable with frontend
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum Drift {
+    MissingVariable { variable: String },
+    WrongValue { variable: String, expected: Vec<String>, actual: Vec<String> },
+    MissingEntry { entry: String },
+    ForbiddenEntry { entry: String, index: usize },
+    WrongOrder { before: String, after: String },
+}
+
+impl DesiredState {
+    pub fn parse(content: &str) -> Result<Self, String> {
+        toml::from_str(content).map_err(|e| e.to_string())
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let content = std::fs::read_to_string(path).map_err(|e| format!("cannot read {:?}: {}", path, e))?;
+        Self::parse(&content)
+    }
+
+    /// everything `env` does not satisfy, nothing is changed
+    pub fn check(&self, env: &EnvHashMap) -> Vec<Drift> {
+        let mut drifts = vec![];
+        for (k, expected) in self.variables.iter() {
+            let expected = expected.values();
+            match existing_key(env, k).and_then(|key| env.get(&key)) {
+                None => drifts.push(Drift::MissingVariable { variable: k.clone() }),
+                Some(actual) if actual != &expected => drifts.push(Drift::WrongValue {
+                    variable: k.clone(),
+                    expected,
+                    actual: actual.clone(),
+                }),
+                _ => (),
+            }
+        }
+        let entries = env.get(&path_key(env)).cloned().unwrap_or_default();
+        for entry in self.path.required.iter() {
+            if position(&entries, entry).is_none() {
+                drifts.push(Drift::MissingEntry { entry: entry.clone() });
+            }
+        }
+        for (index, entry) in entries.iter().enumerate() {
+            if self.path.forbidden.iter().any(|f| normalize(f) == normalize(entry)) {
+                drifts.push(Drift::ForbiddenEntry {
+                    entry: entry.clone(),
+                    index,
+                });
+            }
+        }
+        for rule in self.path.order.iter() {
+            if let (Some(b), Some(a)) = (position(&entries, &rule.before), position(&entries, &rule.after)) {
+                if b > a {
+                    drifts.push(Drift::WrongOrder {
+                        before: rule.before.clone(),
+                        after: rule.after.clone(),
+                    });
+                }
+            }
+        }
+        drifts
+    }
+
+    /// the tasks turning `env` into the desired state, empty if there is no drift
+    pub fn plan(&self, env: &EnvHashMap) -> Vec<TaskLogData> {
+        let scope = self.scope;
+        let mut tasks = vec![];
+        let mut map = env.clone();
+        for (k, expected) in self.variables.iter() {
+            let expected = expected.values();
+            // 沿用已有的大小写，Windows 上 `java_home` 就是 `JAVA_HOME`
+            let k = &match existing_key(&map, k) {
+                Some(key) => key,
+                None => {
+                    push_task(&mut tasks, &mut map, TaskLogData::AddVariable(AddVariableLog {
+                        variable: k.clone(),
+                        scope,
+                    }));
+                    k.clone()
+                }
+            };
+            let actual = map.get(k).unwrap().clone();
+            if actual != expected {
+                push_task(&mut tasks, &mut map, TaskLogData::SetVariable(SetVariableLog {
+                    variable: k.clone(),
+                    old_values: actual,
+                    new_values: expected,
+                    scope,
+                }));
+            }
+        }
+
+        let rules = &self.path;
+        if rules.required.is_empty() && rules.forbidden.is_empty() && rules.order.is_empty() {
+            return tasks;
+        }
+        let key = path_key(&map);
+        if !map.contains_key(&key) {
+            push_task(&mut tasks, &mut map, TaskLogData::AddVariable(AddVariableLog {
+                variable: key.clone(),
+                scope,
+            }));
+        }
+        // 从后往前删，前面的 index 不受影响
+        let entries = map.get(&key).unwrap().clone();
+        for (index, entry) in entries.iter().enumerate().rev() {
+            if rules.forbidden.iter().any(|f| normalize(f) == normalize(entry)) {
+                push_task(&mut tasks, &mut map, TaskLogData::delete_value(&key, index, entry, scope));
+            }
+        }
+        for entry in rules.required.iter() {
+            let entries = map.get(&key).unwrap();
+            if position(entries, entry).is_some() {
+                continue;
+            }
+            let index = self._insert_index(entry, entries);
+            push_task(&mut tasks, &mut map, TaskLogData::insert_value(&key, index, entry, scope));
+        }
+        for _ in 0..MAX_ORDER_PASSES {
+            let entries = map.get(&key).unwrap().clone();
+            let violated = rules.order.iter().find_map(|rule| {
+                match (position(&entries, &rule.before), position(&entries, &rule.after)) {
+                    (Some(b), Some(a)) if b > a => Some((b, a)),
+                    _ => None,
+                }
+            });
+            let (b, a) = match violated {
+                Some(v) => v,
+                None => break,
+            };
+            // move `before` right in front of `after`
+            let value = entries[b].clone();
+            push_task(&mut tasks, &mut map, TaskLogData::delete_value(&key, b, &value, scope));
+            push_task(&mut tasks, &mut map, TaskLogData::insert_value(&key, a, &value, scope));
+        }
+        tasks
+    }
+
+    // 新增的条目放在所有必须排在它后面的条目之前，没有约束就追加到末尾
+    fn _insert_index(&self, entry: &str, entries: &[String]) -> usize {
+        self.path
+            .order
+            .iter()
+            .filter(|rule| normalize(&rule.before) == normalize(entry))
+            .filter_map(|rule| position(entries, &rule.after))
+            .min()
+            .unwrap_or(entries.len())
+    }
+}
+
+/// the existing PATH variable with its casing, `PATH` if there is none
+fn path_key(env: &EnvHashMap) -> String {
+    env.keys()
+        .find(|k| is_path_variable(k))
+        .cloned()
+        .unwrap_or("PATH".to_string())
+}
+
+fn position(entries: &[String], entry: &str) -> Option<usize> {
+    entries.iter().position(|e| normalize(e) == normalize(entry))
+}
+
+#[test]
+fn test_plan_satisfies_check() {
+    use crate::task::ConsumeTask;
+
+    let desired = DesiredState::parse(
+        r#"
+        [variables]
+        JAVA_HOME = '/opt/jdk-17'
+        FLAGS = ['-a', '-b']
+
+        [path]
+        required = ['/opt/jdk-17/bin', '/opt/tools']
+        forbidden = ['/opt/jdk-8/bin']
+        order = [
+            { before = '/opt/jdk-17/bin', after = '/usr/bin' },
+            { before = '/usr/local/bin', after = '/usr/bin' },
+        ]
+        "#,
+    )
+    .unwrap();
+    let mut env = EnvHashMap::new();
+    env.insert("JAVA_HOME".to_string(), vec!["/opt/jdk-8".to_string()]);
+    env.insert(
+        "PATH".to_string(),
+        ["/opt/jdk-8/bin", "/usr/bin", "/usr/local/bin/"].iter().map(|s| s.to_string()).collect(),
+    );
+    assert_eq!(desired.check(&env).len(), 6);
+
+    let mut map = env.clone();
+    for task in desired.plan(&env) {
+        task.forward(&mut map);
+    }
+    assert!(desired.check(&map).is_empty());
+    assert_eq!(map["PATH"], vec!["/opt/jdk-17/bin", "/usr/local/bin/", "/usr/bin", "/opt/tools"]);
+    assert!(desired.plan(&map).is_empty());
+}
+
+#[cfg(windows)]
+#[test]
+fn test_plan_reuses_existing_casing() {
+    use crate::task::ConsumeTask;
+
+    let desired = DesiredState::parse("[variables]\njava_home = 'C:\\jdk17'\n").unwrap();
+    let mut env = EnvHashMap::new();
+    env.insert("JAVA_HOME".to_string(), vec!["C:\\jdk8".to_string()]);
+    assert!(matches!(desired.check(&env)[0], Drift::WrongValue { .. }));
+    let mut map = env.clone();
+    for task in desired.plan(&env) {
+        task.forward(&mut map);
+    }
+    assert_eq!(map.len(), 1);
+    assert_eq!(map["JAVA_HOME"], vec!["C:\\jdk17"]);
+}
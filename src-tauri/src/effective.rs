@@ -0,0 +1,114 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::backend::Scope;
+use crate::lint::is_path_variable;
+use crate::task::EnvHashMap;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ScopedValue {
+    pub value: String,
+    pub scope: Scope,
+}
+
+/// variable -> values annotated with the scope they come from
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct EffectiveEnv {
+    pub variables: HashMap<String, Vec<ScopedValue>>,
+}
+
+impl EffectiveEnv {
+    /// the env a newly started process sees.
+    /// PATH is machine followed by user on Windows, other variables of the user
+    /// scope override the machine ones. A user value referencing the variable
+    /// itself, e.g. `${PATH}` in environment.d, is replaced by the machine values.
+    pub fn merge(machine: &EnvHashMap, user: &EnvHashMap) -> Self {
+        let mut variables: HashMap<String, Vec<ScopedValue>> = HashMap::new();
+        for (k, values) in machine.iter() {
+            variables.insert(k.clone(), scoped(values, Scope::Machine));
+        }
+        for (k, values) in user.iter() {
+            // names are case insensitive on Windows, the user casing wins
+            let machine_key = variables.keys().find(|m| names_equal(m, k)).cloned();
+            let machine_values = match machine_key {
+                Some(m) => variables.remove(&m).unwrap(),
+                None => vec![],
+            };
+            let merged = if values.iter().any(|v| is_self_reference(v, k)) {
+                values
+                    .iter()
+                    .flat_map(|v| {
+                        if is_self_reference(v, k) {
+                            machine_values.clone()
+                        } else {
+                            scoped(&[v.clone()], Scope::User)
+                        }
+                    })
+                    .collect()
+            } else if cfg!(windows) && is_path_variable(k) {
+                let mut merged = machine_values;
+                merged.extend(scoped(values, Scope::User));
+                merged
+            } else {
+                scoped(values, Scope::User)
+            };
+            variables.insert(k.clone(), merged);
+        }
+        Self { variables }
+    }
+
+    pub fn to_env(&self) -> EnvHashMap {
+        self.variables
+            .iter()
+            .map(|(k, v)| (k.clone(), v.iter().map(|s| s.value.clone()).collect()))
+            .collect()
+    }
+}
+
+fn scoped(values: &[String], scope: Scope) -> Vec<ScopedValue> {
+    values
+        .iter()
+        .map(|v| ScopedValue {
+            value: v.clone(),
+            scope,
+        })
+        .collect()
+}
+
+/// variable names are case-insensitive on Windows
+pub fn names_equal(a: &str, b: &str) -> bool {
+    if cfg!(windows) {
+        a.eq_ignore_ascii_case(b)
+    } else {
+        a == b
+    }
+}
+
+/// the name `name` has in `env`, e.g. `Path` for `PATH` on Windows
+pub fn existing_key(env: &EnvHashMap, name: &str) -> Option<String> {
+    env.keys().find(|k| names_equal(k, name)).cloned()
+}
+
+fn is_self_reference(value: &str, name: &str) -> bool {
+    let value = value.trim();
+    [format!("${}", name), format!("${{{}}}", name), format!("%{}%", name)]
+        .iter()
+        .any(|r| names_equal(value, r))
+}
+
+#[test]
+fn test_merge_self_reference() {
+    let mut machine = EnvHashMap::new();
+    machine.insert("PATH".to_string(), vec!["/usr/bin".to_string(), "/bin".to_string()]);
+    machine.insert("LANG".to_string(), vec!["C".to_string()]);
+    let mut user = EnvHashMap::new();
+    user.insert("PATH".to_string(), vec!["/opt/bin".to_string(), "${PATH}".to_string()]);
+    user.insert("LANG".to_string(), vec!["en_US.UTF-8".to_string()]);
+
+    let effective = EffectiveEnv::merge(&machine, &user);
+    let path = &effective.variables["PATH"];
+    assert_eq!(path.len(), 3);
+    assert_eq!(path[0].scope, Scope::User);
+    assert_eq!(path[1].scope, Scope::Machine);
+    assert_eq!(effective.to_env()["LANG"], vec!["en_US.UTF-8"]);
+}
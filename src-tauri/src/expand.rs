@@ -0,0 +1,305 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+use crate::backend::SEPARATOR;
+use crate::effective::names_equal;
+use crate::task::EnvHashMap;
+
+/// names referenced by `value`, in `%VAR%` syntax, or `$VAR` and `${VAR}` off Windows
+pub fn references(value: &str) -> Vec<String> {
+    let chars: Vec<char> = value.chars().collect();
+    let mut names = vec![];
+    let mut i = 0;
+    while i < chars.len() {
+        match chars[i] {
+            '%' => {
+                // the closing '%' must come before any whitespace
+                if let Some(len) = chars[i + 1..].iter().position(|c| *c == '%' || c.is_whitespace()) {
+                    let end = i + 1 + len;
+                    if chars[end] == '%' && len > 0 {
+                        names.push(chars[i + 1..end].iter().collect());
+                        i = end + 1;
+                        continue;
+                    }
+                }
+            }
+            // Windows 不认 `$VAR`，PowerShell 里的 `$env:` 也不会出现在变量值里
+            '$' if cfg!(not(windows)) && chars.get(i + 1) == Some(&'{') => {
+                if let Some(len) = chars[i + 2..].iter().position(|c| *c == '}') {
+                    let end = i + 2 + len;
+                    if len > 0 {
+                        names.push(chars[i + 2..end].iter().collect());
+                    }
+                    i = end + 1;
+                    continue;
+                }
+            }
+            '$' if cfg!(not(windows)) && chars.get(i + 1).map_or(false, |c| c.is_ascii_alphabetic() || *c == '_') => {
+                let len = chars[i + 1..]
+                    .iter()
+                    .take_while(|c| c.is_ascii_alphanumeric() || **c == '_')
+                    .count();
+                names.push(chars[i + 1..i + 1 + len].iter().collect());
+                i += 1 + len;
+                continue;
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    names
+}
+
+/// replace every reference to `name` in `value` with `replacement`
+fn substitute(value: &str, name: &str, replacement: &str) -> String {
+    let value = value.replace(&format!("%{}%", name), replacement);
+    if cfg!(windows) {
+        return value;
+    }
+    let value = value.replace(&format!("${{{}}}", name), replacement);
+    // `$JAVA` 不能替换掉 `$JAVA_HOME` 的前半截
+    let pattern = format!("${}", name);
+    let mut result = String::new();
+    let mut rest = value.as_str();
+    while let Some(pos) = rest.find(&pattern) {
+        result.push_str(&rest[..pos]);
+        rest = &rest[pos + pattern.len()..];
+        if rest.starts_with(|c: char| c.is_ascii_alphanumeric() || c == '_') {
+            result.push_str(&pattern);
+        } else {
+            result.push_str(replacement);
+        }
+    }
+    result.push_str(rest);
+    result
+}
+
+/// variables of the given env first, then the inherited process env
+pub fn lookup(name: &str, env: &EnvHashMap) -> Option<String> {
+    for (k, v) in env.iter() {
+        if names_equal(k, name) {
+            return Some(v.join(SEPARATOR));
+        }
+    }
+    std::env::vars().find(|(k, _)| names_equal(k, name)).map(|(_, v)| v)
+}
+
+/// cache and cycle key of a variable, names only fold case on Windows
+fn key(name: &str) -> String {
+    if cfg!(windows) {
+        name.to_uppercase()
+    } else {
+        name.to_string()
+    }
+}
+
+/// expand all references recursively, None if any of them is undefined or cyclic
+pub fn expand(value: &str, env: &EnvHashMap) -> Option<String> {
+    let mut expander = Expander::new(env);
+    let (expanded, ok) = expander.expand_value(value, &mut vec![]);
+    if ok {
+        Some(expanded)
+    } else {
+        None
+    }
+}
+
+pub struct Expander<'a> {
+    env: &'a EnvHashMap,
+    cache: HashMap<String, Option<String>>,
+}
+
+impl<'a> Expander<'a> {
+    pub fn new(env: &'a EnvHashMap) -> Self {
+        Self {
+            env,
+            cache: HashMap::new(),
+        }
+    }
+
+    /// every variable of the env which contains references, expanded
+    pub fn expand_env(&mut self) -> EnvHashMap {
+        let mut result = EnvHashMap::new();
+        for (k, values) in self.env.iter() {
+            if values.iter().all(|v| references(v).is_empty()) {
+                continue;
+            }
+            let expanded = values
+                .iter()
+                .map(|v| self.expand_value(v, &mut vec![key(k)]).0)
+                .collect();
+            result.insert(k.clone(), expanded);
+        }
+        result
+    }
+
+    /// (expanded, ok), unresolvable references are kept as they are
+    fn expand_value(&mut self, value: &str, stack: &mut Vec<String>) -> (String, bool) {
+        let mut expanded = value.to_string();
+        let mut ok = true;
+        let mut names = references(value);
+        // `$JAVA_HOME` must be substituted before `$JAVA`
+        names.sort_by(|a, b| b.len().cmp(&a.len()));
+        for name in names {
+            match self._expand_variable(&name, stack) {
+                Some(v) => expanded = substitute(&expanded, &name, &v),
+                None => ok = false,
+            }
+        }
+        (expanded, ok)
+    }
+
+    fn _expand_variable(&mut self, name: &str, stack: &mut Vec<String>) -> Option<String> {
+        let key = key(name);
+        if stack.contains(&key) {
+            return None;
+        }
+        if let Some(v) = self.cache.get(&key) {
+            return v.clone();
+        }
+        let raw = lookup(name, self.env)?;
+        stack.push(key.clone());
+        let (expanded, ok) = self.expand_value(&raw, stack);
+        stack.pop();
+        let result = if ok { Some(expanded) } else { None };
+        self.cache.insert(key, result.clone());
+        result
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DependencyGraph {
+    /// variable -> variables it references
+    pub edges: HashMap<String, Vec<String>>,
+    /// each cycle as a list of variables, e.g. [A, B] for A -> B -> A
+    pub cycles: Vec<Vec<String>>,
+    /// (variable, reference) pairs where the reference is defined nowhere
+    pub undefined: Vec<(String, String)>,
+}
+
+impl DependencyGraph {
+    pub fn new(env: &EnvHashMap) -> Self {
+        let mut edges: HashMap<String, Vec<String>> = HashMap::new();
+        let mut undefined = vec![];
+        let mut keys: Vec<&String> = env.keys().collect();
+        keys.sort();
+        for k in keys {
+            let mut deps: Vec<String> = vec![];
+            for v in env.get(k).unwrap() {
+                for name in references(v) {
+                    let target = match env.keys().find(|k| names_equal(k, &name)) {
+                        Some(t) => t.clone(),
+                        None => {
+                            if lookup(&name, env).is_none() {
+                                undefined.push((k.clone(), name));
+                            }
+                            // inherited from the process, not part of the graph
+                            continue;
+                        }
+                    };
+                    if !deps.contains(&target) {
+                        deps.push(target);
+                    }
+                }
+            }
+            edges.insert(k.clone(), deps);
+        }
+        let cycles = find_cycles(&edges);
+        Self {
+            edges,
+            cycles,
+            undefined,
+        }
+    }
+
+    /// every variable which directly or transitively references `variable`
+    pub fn dependents(&self, variable: &str) -> Vec<String> {
+        let mut result: Vec<String> = vec![];
+        let mut stack = vec![variable.to_string()];
+        while let Some(target) = stack.pop() {
+            for (k, deps) in self.edges.iter() {
+                if deps.iter().any(|d| names_equal(d, &target)) && !result.contains(k) {
+                    result.push(k.clone());
+                    stack.push(k.clone());
+                }
+            }
+        }
+        result.retain(|k| !names_equal(k, variable));
+        result.sort();
+        result
+    }
+}
+
+fn find_cycles(edges: &HashMap<String, Vec<String>>) -> Vec<Vec<String>> {
+    let mut cycles = vec![];
+    let mut done: HashSet<&String> = HashSet::new();
+    let mut keys: Vec<&String> = edges.keys().collect();
+    keys.sort();
+    for start in keys {
+        let mut path: Vec<&String> = vec![];
+        _visit(start, edges, &mut path, &mut done, &mut cycles);
+    }
+    cycles
+}
+
+fn _visit<'a>(
+    node: &'a String,
+    edges: &'a HashMap<String, Vec<String>>,
+    path: &mut Vec<&'a String>,
+    done: &mut HashSet<&'a String>,
+    cycles: &mut Vec<Vec<String>>,
+) {
+    if let Some(pos) = path.iter().position(|n| *n == node) {
+        cycles.push(path[pos..].iter().map(|n| n.to_string()).collect());
+        return;
+    }
+    if done.contains(node) {
+        return;
+    }
+    path.push(node);
+    for next in edges.get(node).into_iter().flatten() {
+        _visit(next, edges, path, done, cycles);
+    }
+    path.pop();
+    done.insert(node);
+}
+
+#[test]
+fn test_references() {
+    assert_eq!(references("%JAVA_HOME%\\bin"), vec!["JAVA_HOME"]);
+    #[cfg(not(windows))]
+    assert_eq!(references("${HOME}/.cargo/bin:$GOPATH/bin"), vec!["HOME", "GOPATH"]);
+    #[cfg(windows)]
+    assert_eq!(references("$HOME\\bin"), Vec::<String>::new());
+    assert_eq!(references("100% sure"), Vec::<String>::new());
+    assert_eq!(references("cost $5"), Vec::<String>::new());
+}
+
+#[test]
+fn test_graph_cycles_and_dependents() {
+    let mut env = EnvHashMap::new();
+    env.insert("JAVA_HOME".to_string(), vec!["C:\\jdk".to_string()]);
+    env.insert("Path".to_string(), vec!["%JAVA_HOME%\\bin".to_string()]);
+    env.insert("A".to_string(), vec!["%B%".to_string()]);
+    env.insert("B".to_string(), vec!["%A%".to_string()]);
+    let graph = DependencyGraph::new(&env);
+    assert_eq!(graph.dependents("JAVA_HOME"), vec!["Path"]);
+    assert_eq!(graph.cycles, vec![vec!["A".to_string(), "B".to_string()]]);
+    let expanded = Expander::new(&env).expand_env();
+    assert_eq!(expanded["Path"], vec!["C:\\jdk\\bin"]);
+    assert_eq!(expanded["A"], vec!["%B%"]);
+}
+
+#[cfg(not(windows))]
+#[test]
+fn test_expand_unix_names() {
+    let mut env = EnvHashMap::new();
+    env.insert("JAVA".to_string(), vec!["/usr/bin/java".to_string()]);
+    env.insert("JAVA_HOME".to_string(), vec!["/opt/jdk".to_string()]);
+    env.insert("home".to_string(), vec!["/lower".to_string()]);
+    assert_eq!(substitute("$JAVA_HOME/bin", "JAVA", "x"), "$JAVA_HOME/bin");
+    assert_eq!(expand("$JAVA_HOME/bin:$JAVA", &env).as_deref(), Some("/opt/jdk/bin:/usr/bin/java"));
+    // 变量名区分大小写
+    assert_eq!(lookup("HOME", &env), std::env::var("HOME").ok());
+    assert_eq!(lookup("home", &env).as_deref(), Some("/lower"));
+}
@@ -0,0 +1,228 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::backend::{powershell_literal, SEPARATOR};
+use crate::expand::references;
+use crate::task::EnvHashMap;
+
+const REDACTED: &str = "********";
+
+/// dont modify the names of these enum, compatiable with frontend
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum ExportFormat {
+    Dotenv,
+    Json,
+    Sh,
+    Fish,
+    PowerShell,
+    Reg,
+    Dockerfile,
+    GithubEnv,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportOption {
+    pub format: ExportFormat,
+    /// only export these variables, export all if None
+    #[serde(default)]
+    pub variables: Option<Vec<String>>,
+    /// variables marked as secret by the user
+    #[serde(default)]
+    pub secrets: Vec<String>,
+    /// replace the values of secrets with a placeholder
+    #[serde(default)]
+    pub redact: bool,
+    /// joins multiple values of one variable, platform separator if None
+    #[serde(default)]
+    pub separator: Option<String>,
+}
+
+pub struct Exporter<'a> {
+    env: &'a EnvHashMap,
+    option: &'a ExportOption,
+}
+
+impl<'a> Exporter<'a> {
+    pub fn new(env: &'a EnvHashMap, option: &'a ExportOption) -> Self {
+        Self { env, option }
+    }
+
+    pub fn export(&self) -> String {
+        // 注册表里的列表总是用 ';'
+        let pairs = match self.option.format {
+            ExportFormat::Reg => self._pairs(";"),
+            _ => self._pairs(self.option.separator.as_deref().unwrap_or(SEPARATOR)),
+        };
+        match self.option.format {
+            ExportFormat::Dotenv => self._dotenv(&pairs),
+            ExportFormat::Json => self._json(&pairs),
+            ExportFormat::Sh => self._sh(&pairs),
+            ExportFormat::Fish => self._fish(&pairs),
+            ExportFormat::PowerShell => self._powershell(&pairs),
+            ExportFormat::Reg => self._reg(&pairs),
+            ExportFormat::Dockerfile => self._dockerfile(&pairs),
+            ExportFormat::GithubEnv => self._github_env(&pairs),
+        }
+    }
+
+    // 过滤、排序并拼接变量值，按需脱敏
+    fn _pairs(&self, separator: &str) -> Vec<(String, String)> {
+        let mut keys: Vec<&String> = match &self.option.variables {
+            Some(selected) => self.env.keys().filter(|k| selected.contains(k)).collect(),
+            None => self.env.keys().collect(),
+        };
+        keys.sort();
+        keys.into_iter()
+            .map(|k| {
+                let v = if self.option.redact && self.option.secrets.contains(k) {
+                    REDACTED.to_string()
+                } else {
+                    self.env.get(k).unwrap().join(separator)
+                };
+                (k.clone(), v)
+            })
+            .collect()
+    }
+
+    fn _dotenv(&self, pairs: &[(String, String)]) -> String {
+        pairs
+            .iter()
+            .map(|(k, v)| format!("{}=\"{}\"\n", k, escape_shell_double_quoted(v)))
+            .collect()
+    }
+
+    fn _json(&self, pairs: &[(String, String)]) -> String {
+        let map: BTreeMap<&String, &String> = pairs.iter().map(|(k, v)| (k, v)).collect();
+        serde_json::to_string_pretty(&map).unwrap()
+    }
+
+    fn _sh(&self, pairs: &[(String, String)]) -> String {
+        pairs
+            .iter()
+            .map(|(k, v)| format!("export {}='{}'\n", k, v.replace('\'', r"'\''")))
+            .collect()
+    }
+
+    fn _fish(&self, pairs: &[(String, String)]) -> String {
+        pairs
+            .iter()
+            .map(|(k, v)| {
+                let v = v.replace('\\', r"\\").replace('\'', r"\'");
+                format!("set -gx {} '{}'\n", k, v)
+            })
+            .collect()
+    }
+
+    fn _powershell(&self, pairs: &[(String, String)]) -> String {
+        pairs
+            .iter()
+            .map(|(k, v)| {
+                // `$env:ProgramFiles(x86)` 会被解析成 `$env:ProgramFiles` 加上 `(x86)`，所以要用 `${env:...}`
+                let k = k.replace('`', "``").replace('{', "`{").replace('}', "`}");
+                format!("${{env:{}}} = {}\n", k, powershell_literal(v))
+            })
+            .collect()
+    }
+
+    fn _reg(&self, pairs: &[(String, String)]) -> String {
+        let mut out = String::from("Windows Registry Editor Version 5.00\r\n\r\n");
+        out.push_str("[HKEY_CURRENT_USER\\Environment]\r\n");
+        for (k, v) in pairs {
+            // 引用了其他变量的值要写成 REG_EXPAND_SZ，否则 %JAVA_HOME% 不会被展开
+            let expandable = references(v).iter().any(|n| v.contains(&format!("%{}%", n)));
+            let value = if !expandable {
+                format!("\"{}\"", escape_double_quoted(v))
+            } else {
+                format!("hex(2):{}", reg_expand_sz(v))
+            };
+            out.push_str(&format!("\"{}\"={}\r\n", escape_double_quoted(k), value));
+        }
+        out
+    }
+
+    fn _dockerfile(&self, pairs: &[(String, String)]) -> String {
+        pairs
+            .iter()
+            .map(|(k, v)| format!("ENV {}=\"{}\"\n", k, escape_shell_double_quoted(v)))
+            .collect()
+    }
+
+    fn _github_env(&self, pairs: &[(String, String)]) -> String {
+        // heredoc delimiter keeps values with quotes or newlines intact
+        pairs
+            .iter()
+            .map(|(k, v)| {
+                format!(
+                    "{{\n  echo '{}<<ENVIRONMENTOR_EOF'\n  echo '{}'\n  echo 'ENVIRONMENTOR_EOF'\n}} >> \"$GITHUB_ENV\"\n",
+                    k,
+                    v.replace('\'', r"'\''")
+                )
+            })
+            .collect()
+    }
+}
+
+fn escape_double_quoted(s: &str) -> String {
+    s.replace('\\', r"\\").replace('"', "\\\"")
+}
+
+/// dotenv loaders and Dockerfile expand `$VAR` inside double quotes
+fn escape_shell_double_quoted(s: &str) -> String {
+    escape_double_quoted(s).replace('$', "\\$")
+}
+
+/// UTF-16LE with the trailing NUL, as comma separated hex bytes
+fn reg_expand_sz(s: &str) -> String {
+    s.encode_utf16()
+        .chain([0])
+        .flat_map(|u| u.to_le_bytes())
+        .map(|b| format!("{:02x}", b))
+        .collect::<Vec<String>>()
+        .join(",")
+}
+
+#[test]
+fn test_export_redact_and_filter() {
+    let mut env = EnvHashMap::new();
+    env.insert("PATH".to_string(), vec!["C:\\bin".to_string(), "D:\\it's".to_string()]);
+    env.insert("TOKEN".to_string(), vec!["abc".to_string()]);
+    env.insert("OTHER".to_string(), vec!["x".to_string()]);
+    let option = ExportOption {
+        format: ExportFormat::Sh,
+        variables: Some(vec!["PATH".to_string(), "TOKEN".to_string()]),
+        secrets: vec!["TOKEN".to_string()],
+        redact: true,
+        separator: Some(";".to_string()),
+    };
+    let out = Exporter::new(&env, &option).export();
+    assert_eq!(out, "export PATH='C:\\bin;D:\\it'\\''s'\nexport TOKEN='********'\n");
+}
+
+#[test]
+fn test_export_escapes() {
+    let mut env = EnvHashMap::new();
+    env.insert("Path".to_string(), vec!["%JAVA_HOME%\\bin".to_string(), "C:\\bin".to_string()]);
+    env.insert("PRICE".to_string(), vec!["$5 \"off\"".to_string()]);
+    let mut option = ExportOption {
+        format: ExportFormat::Dotenv,
+        variables: None,
+        secrets: vec![],
+        redact: false,
+        separator: Some(":".to_string()),
+    };
+    let out = Exporter::new(&env, &option).export();
+    assert!(out.starts_with("PRICE=\"\\$5 \\\"off\\\"\"\n"));
+
+    option.format = ExportFormat::Reg;
+    let out = Exporter::new(&env, &option).export();
+    // "%J" -> 25,00,4a,00，列表用 ';' (3b,00) 拼接并以 NUL 结尾
+    let path = out.lines().find(|l| l.starts_with("\"Path\"")).unwrap();
+    assert!(path.starts_with("\"Path\"=hex(2):25,00,4a,00"));
+    assert!(path.contains(",3b,00,") && path.ends_with(",00,00"));
+    assert!(out.contains("\"PRICE\"=\"$5 \\\"off\\\"\"\r\n"));
+
+    env.insert("ProgramFiles(x86)".to_string(), vec!["C:\\it's".to_string()]);
+    option.format = ExportFormat::PowerShell;
+    let out = Exporter::new(&env, &option).export();
+    assert!(out.contains("${env:ProgramFiles(x86)} = 'C:\\it''s'\n"));
+}
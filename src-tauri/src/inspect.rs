@@ -0,0 +1,258 @@
+use serde::{Deserialize, Serialize};
+
+use crate::backend::split_value;
+use crate::effective::names_equal;
+use crate::expand::Expander;
+use crate::task::{EnvDiff, EnvHashMap};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProcessInfo {
+    pub pid: u32,
+    pub name: String,
+}
+
+/// explains why a running process does not see the committed env
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProcessReport {
+    pub process: ProcessInfo,
+    /// old is the env of the process, new is the committed env.
+    /// variables the committed env does not know are left out
+    pub diff: EnvDiff,
+}
+
+impl ProcessReport {
+    pub fn new(process: ProcessInfo, process_env: &EnvHashMap, committed: &EnvHashMap) -> Self {
+        // processes only ever see expanded values
+        let mut committed = committed.clone();
+        committed.extend(Expander::new(&committed).expand_env());
+        // 用 committed 里的写法，Windows 上 `Path` 和 `PATH` 是同一个变量
+        let process_env: EnvHashMap = process_env
+            .iter()
+            .filter_map(|(k, v)| {
+                let key = committed.keys().find(|c| names_equal(c, k))?;
+                Some((key.clone(), v.clone()))
+            })
+            .collect();
+        Self {
+            process,
+            diff: EnvDiff::between(&process_env, &committed),
+        }
+    }
+
+    /// variables which are outdated or missing in the process
+    pub fn stale(&self) -> Vec<String> {
+        self.diff.changes.iter().map(|c| c.variable.clone()).collect()
+    }
+}
+
+pub fn list_processes() -> Vec<ProcessInfo> {
+    let mut processes = platform::list();
+    processes.sort_by_key(|p| p.pid);
+    processes
+}
+
+pub fn read_environ(pid: u32) -> Result<EnvHashMap, String> {
+    if pid == std::process::id() {
+        return Ok(std::env::vars().map(|(k, v)| {
+            let values = split_value(&k, &v);
+            (k, values)
+        }).collect());
+    }
+    platform::environ(pid)
+}
+
+#[cfg(windows)]
+mod platform {
+    use std::os::windows::process::CommandExt as _;
+    use std::process::Command;
+
+    use super::ProcessInfo;
+    use crate::backend::split_value;
+    use crate::task::EnvHashMap;
+
+    pub fn list() -> Vec<ProcessInfo> {
+        let output = match Command::new("tasklist")
+            .args(["/fo", "csv", "/nh"])
+            .creation_flags(0x08000000) // CREATE_NO_WINDOW
+            .output()
+        {
+            Ok(o) => o,
+            Err(_) => return vec![],
+        };
+        // "Image Name","PID","Session Name","Session#","Mem Usage"
+        csv::ReaderBuilder::new()
+            .has_headers(false)
+            .from_reader(output.stdout.as_slice())
+            .records()
+            .filter_map(|r| r.ok())
+            .filter_map(|r| {
+                Some(ProcessInfo {
+                    pid: r.get(1)?.parse().ok()?,
+                    name: r.get(0)?.to_string(),
+                })
+            })
+            .collect()
+    }
+
+    const PROCESS_QUERY_INFORMATION: u32 = 0x0400;
+    const PROCESS_VM_READ: u32 = 0x0010;
+    const PROCESS_BASIC_INFORMATION: u32 = 0;
+    // PEB.ProcessParameters, RTL_USER_PROCESS_PARAMETERS.Environment / EnvironmentSize
+    #[cfg(target_pointer_width = "64")]
+    const OFFSETS: (usize, usize, usize) = (0x20, 0x80, 0x3f0);
+    #[cfg(target_pointer_width = "32")]
+    const OFFSETS: (usize, usize, usize) = (0x10, 0x48, 0x290);
+    /// a broken size field should not make us read megabytes
+    const ENVIRONMENT_LIMIT: usize = 1 << 20;
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn OpenProcess(access: u32, inherit: i32, pid: u32) -> isize;
+        fn ReadProcessMemory(process: isize, address: usize, buffer: *mut u8, size: usize, read: *mut usize) -> i32;
+        fn CloseHandle(handle: isize) -> i32;
+    }
+
+    #[link(name = "ntdll")]
+    extern "system" {
+        fn NtQueryInformationProcess(process: isize, class: u32, info: *mut usize, len: u32, ret: *mut u32) -> i32;
+    }
+
+    struct Handle(isize);
+
+    impl Drop for Handle {
+        fn drop(&mut self) {
+            unsafe { CloseHandle(self.0) };
+        }
+    }
+
+    impl Handle {
+        fn read(&self, address: usize, buffer: &mut [u8]) -> Result<(), String> {
+            let mut read = 0;
+            let ok = unsafe { ReadProcessMemory(self.0, address, buffer.as_mut_ptr(), buffer.len(), &mut read) };
+            if ok == 0 || read != buffer.len() {
+                return Err(std::io::Error::last_os_error().to_string());
+            }
+            Ok(())
+        }
+
+        fn read_usize(&self, address: usize) -> Result<usize, String> {
+            let mut buffer = [0u8; std::mem::size_of::<usize>()];
+            self.read(address, &mut buffer)?;
+            Ok(usize::from_ne_bytes(buffer))
+        }
+    }
+
+    // 读目标进程 PEB 里的环境块，需要和目标进程同样的位数，权限不够时 OpenProcess 失败
+    pub fn environ(pid: u32) -> Result<EnvHashMap, String> {
+        let fail = |e: String| format!("Cannot read the environment of process {}: {}", pid, e);
+        let handle = unsafe { OpenProcess(PROCESS_QUERY_INFORMATION | PROCESS_VM_READ, 0, pid) };
+        if handle == 0 {
+            return Err(fail(std::io::Error::last_os_error().to_string()));
+        }
+        let handle = Handle(handle);
+        // PROCESS_BASIC_INFORMATION 的每个字段都按指针宽度对齐，PebBaseAddress 是第二个
+        let mut info = [0usize; 6];
+        let size = std::mem::size_of_val(&info) as u32;
+        let status = unsafe {
+            NtQueryInformationProcess(handle.0, PROCESS_BASIC_INFORMATION, info.as_mut_ptr(), size, std::ptr::null_mut())
+        };
+        if status != 0 {
+            return Err(fail(format!("NtQueryInformationProcess returned {:#x}", status)));
+        }
+        let (parameters_offset, environment_offset, size_offset) = OFFSETS;
+        let parameters = handle.read_usize(info[1] + parameters_offset).map_err(fail)?;
+        let environment = handle.read_usize(parameters + environment_offset).map_err(fail)?;
+        let size = handle.read_usize(parameters + size_offset).map_err(fail)?;
+        let mut block = vec![0u8; size.min(ENVIRONMENT_LIMIT)];
+        handle.read(environment, &mut block).map_err(fail)?;
+        let wide: Vec<u16> = block.chunks_exact(2).map(|c| u16::from_ne_bytes([c[0], c[1]])).collect();
+        Ok(parse_environ(&wide))
+    }
+
+    /// `K=V\0K=V\0\0` in UTF-16, entries like `=C:=C:\` are per-drive working directories
+    pub fn parse_environ(block: &[u16]) -> EnvHashMap {
+        String::from_utf16_lossy(block)
+            .split('\0')
+            .take_while(|pair| !pair.is_empty())
+            .filter(|pair| !pair.starts_with('='))
+            .filter_map(|pair| pair.split_once('='))
+            .map(|(k, v)| (k.to_string(), split_value(k, v)))
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_environ() {
+        let block: Vec<u16> = "=C:=C:\\Users\0Path=C:\\a;C:\\b\0TEMP=C:\\Temp\0\0garbage".encode_utf16().collect();
+        let env = parse_environ(&block);
+        assert_eq!(env.len(), 2);
+        assert_eq!(env["Path"], vec!["C:\\a", "C:\\b"]);
+    }
+}
+
+#[cfg(not(windows))]
+mod platform {
+    use std::fs;
+
+    use super::ProcessInfo;
+    use crate::backend::split_value;
+    use crate::task::EnvHashMap;
+
+    pub fn list() -> Vec<ProcessInfo> {
+        let entries = match fs::read_dir("/proc") {
+            Ok(entries) => entries,
+            Err(_) => return vec![],
+        };
+        entries
+            .filter_map(|e| e.ok())
+            .filter_map(|e| {
+                let pid: u32 = e.file_name().to_str()?.parse().ok()?;
+                let name = fs::read_to_string(e.path().join("comm")).ok()?;
+                Some(ProcessInfo {
+                    pid,
+                    name: name.trim().to_string(),
+                })
+            })
+            .collect()
+    }
+
+    /// `/proc/<pid>/environ` is NUL separated, only readable for processes of the same user
+    pub fn environ(pid: u32) -> Result<EnvHashMap, String> {
+        let data = fs::read(format!("/proc/{}/environ", pid))
+            .map_err(|e| format!("Cannot read the environment of process {}: {}", pid, e))?;
+        Ok(parse_environ(&data))
+    }
+
+    pub fn parse_environ(data: &[u8]) -> EnvHashMap {
+        String::from_utf8_lossy(data)
+            .split('\0')
+            .filter_map(|pair| pair.split_once('='))
+            .map(|(k, v)| (k.to_string(), split_value(k, v)))
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_environ() {
+        let env = parse_environ(b"PATH=/usr/bin:/bin\0HOME=/root\0\0");
+        assert_eq!(env["PATH"], vec!["/usr/bin", "/bin"]);
+        assert_eq!(env["HOME"], vec!["/root"]);
+    }
+}
+
+#[test]
+fn test_process_report_stale() {
+    let mut process_env = EnvHashMap::new();
+    process_env.insert("PATH".to_string(), vec!["/usr/bin".to_string()]);
+    process_env.insert("PWD".to_string(), vec!["/tmp".to_string()]);
+    process_env.insert("LANG".to_string(), vec!["C".to_string()]);
+    let mut committed = EnvHashMap::new();
+    committed.insert("PATH".to_string(), vec!["/opt/bin".to_string(), "/usr/bin".to_string()]);
+    committed.insert("LANG".to_string(), vec!["C".to_string()]);
+    committed.insert("EDITOR".to_string(), vec!["vim".to_string()]);
+
+    let process = ProcessInfo {
+        pid: 1,
+        name: "bash".to_string(),
+    };
+    let report = ProcessReport::new(process, &process_env, &committed);
+    assert_eq!(report.stale(), vec!["EDITOR", "PATH"]);
+}
@@ -0,0 +1,227 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::Read;
+use std::process::{Command, Stdio};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use crate::backend::SEPARATOR;
+use crate::effective::names_equal;
+use crate::expand::Expander;
+use crate::task::EnvHashMap;
+use crate::which::resolve;
+
+const DEFAULT_TIMEOUT_SECS: u64 = 30;
+/// how long the pipes are read after the child exits, a background grandchild may keep them open
+const PIPE_GRACE: Duration = Duration::from_millis(500);
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LaunchOption {
+    /// resolved against the pending PATH, e.g. `java`
+    pub command: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+    #[serde(default)]
+    pub cwd: Option<String>,
+    /// the process is killed after it, default 30s
+    #[serde(default)]
+    pub timeout_secs: Option<u64>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LaunchResult {
+    /// the file actually executed
+    pub program: String,
+    /// None if killed by a signal or the timeout
+    pub code: Option<i32>,
+    pub stdout: String,
+    pub stderr: String,
+    pub timed_out: bool,
+}
+
+/// runs processes with the pending env instead of the env of this process
+#[derive(Debug, Clone)]
+pub struct Launcher {
+    pending: EnvHashMap,
+    env: HashMap<String, String>,
+}
+
+impl Launcher {
+    /// `pending` and `committed` are effective envs, see `EffectiveEnv`.
+    /// the inherited env is kept, except variables pending to be deleted.
+    pub fn new(pending: &EnvHashMap, committed: &EnvHashMap) -> Self {
+        let expanded = Expander::new(pending).expand_env();
+        let mut env: HashMap<String, String> = std::env::vars().collect();
+        for k in committed.keys().filter(|k| !contains_name(pending, k)) {
+            remove_name(&mut env, k);
+        }
+        for (k, values) in pending.iter() {
+            let values = expanded.get(k).unwrap_or(values);
+            remove_name(&mut env, k);
+            env.insert(k.clone(), values.join(SEPARATOR));
+        }
+        Self {
+            pending: pending.clone(),
+            env,
+        }
+    }
+
+    pub fn env(&self) -> &HashMap<String, String> {
+        &self.env
+    }
+
+    pub fn run(&self, option: &LaunchOption) -> Result<LaunchResult, String> {
+        let program = self._program(&option.command);
+        let mut command = Command::new(&program);
+        command
+            .args(&option.args)
+            .env_clear()
+            .envs(&self.env)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        if let Some(cwd) = &option.cwd {
+            command.current_dir(cwd);
+        }
+        #[cfg(windows)]
+        {
+            use std::os::windows::process::CommandExt as _;
+            command.creation_flags(0x08000000); // CREATE_NO_WINDOW
+        }
+        let mut child = command
+            .spawn()
+            .map_err(|e| format!("Failed to launch '{}': {}", program, e))?;
+
+        // read both pipes in background, or a chatty child blocks on a full pipe
+        let out_buf = Arc::new(Mutex::new(vec![]));
+        let err_buf = Arc::new(Mutex::new(vec![]));
+        let out = read_pipe(child.stdout.take().unwrap(), out_buf.clone());
+        let err = read_pipe(child.stderr.take().unwrap(), err_buf.clone());
+
+        let timeout = Duration::from_secs(option.timeout_secs.unwrap_or(DEFAULT_TIMEOUT_SECS));
+        let start = Instant::now();
+        let mut timed_out = false;
+        let status = loop {
+            match child.try_wait().map_err(|e| e.to_string())? {
+                Some(status) => break status,
+                None if start.elapsed() > timeout => {
+                    timed_out = true;
+                    let _ = child.kill();
+                    break child.wait().map_err(|e| e.to_string())?;
+                }
+                None => std::thread::sleep(Duration::from_millis(20)),
+            }
+        };
+        // 不 join 读线程，`cmd /c start ...` 这类命令的孙进程会一直占着管道
+        let deadline = Instant::now() + PIPE_GRACE;
+        while !(out.is_finished() && err.is_finished()) && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        let text = |buf: &Arc<Mutex<Vec<u8>>>| String::from_utf8_lossy(&buf.lock().unwrap()).to_string();
+        Ok(LaunchResult {
+            program,
+            code: if timed_out { None } else { status.code() },
+            stdout: text(&out_buf),
+            stderr: text(&err_buf),
+            timed_out,
+        })
+    }
+
+    /// opens a new terminal window, not waited
+    pub fn open_terminal(&self, cwd: Option<&str>) -> Result<(), String> {
+        let mut command = terminal_command();
+        command.env_clear().envs(&self.env);
+        if let Some(cwd) = cwd {
+            command.current_dir(cwd);
+        }
+        command
+            .spawn()
+            .map(|_| ())
+            .map_err(|e| format!("Failed to open a terminal: {}", e))
+    }
+
+    // 按待提交的 PATH 解析命令，找不到就交给系统
+    fn _program(&self, command: &str) -> String {
+        if command.contains('/') || command.contains('\\') {
+            return command.to_string();
+        }
+        match resolve(command, &self.pending).winner {
+            Some(c) => c.file,
+            None => command.to_string(),
+        }
+    }
+}
+
+#[cfg(windows)]
+fn terminal_command() -> Command {
+    let mut command = Command::new("cmd");
+    command.args(["/c", "start", "cmd"]);
+    command
+}
+
+#[cfg(not(windows))]
+fn terminal_command() -> Command {
+    let terminal = std::env::var("TERMINAL").unwrap_or_else(|_| "x-terminal-emulator".to_string());
+    Command::new(terminal)
+}
+
+/// appends to `buf` as data arrives, so whatever was read is kept if the pipe never closes
+fn read_pipe<R: Read + Send + 'static>(mut pipe: R, buf: Arc<Mutex<Vec<u8>>>) -> JoinHandle<()> {
+    std::thread::spawn(move || {
+        let mut chunk = [0u8; 8192];
+        loop {
+            match pipe.read(&mut chunk) {
+                Ok(0) | Err(_) => break,
+                Ok(n) => buf.lock().unwrap().extend_from_slice(&chunk[..n]),
+            }
+        }
+    })
+}
+
+fn contains_name(env: &EnvHashMap, name: &str) -> bool {
+    env.keys().any(|k| names_equal(k, name))
+}
+
+fn remove_name(env: &mut HashMap<String, String>, name: &str) {
+    env.retain(|k, _| !names_equal(k, name));
+}
+
+#[cfg(not(windows))]
+#[test]
+fn test_launch_with_pending_env() {
+    let mut committed = EnvHashMap::new();
+    committed.insert("PATH".to_string(), vec!["/usr/bin".to_string(), "/bin".to_string()]);
+    committed.insert("ENVIRONMENTOR_OLD".to_string(), vec!["x".to_string()]);
+    let mut pending = committed.clone();
+    pending.remove("ENVIRONMENTOR_OLD");
+    pending.insert("ENVIRONMENTOR_HOME".to_string(), vec!["/opt/tool".to_string()]);
+    pending.insert("ENVIRONMENTOR_BIN".to_string(), vec!["${ENVIRONMENTOR_HOME}/bin".to_string()]);
+
+    let launcher = Launcher::new(&pending, &committed);
+    assert!(!launcher.env().contains_key("ENVIRONMENTOR_OLD"));
+    let option = LaunchOption {
+        command: "sh".to_string(),
+        args: vec!["-c".to_string(), "echo $ENVIRONMENTOR_BIN; exit 3".to_string()],
+        ..Default::default()
+    };
+    let result = launcher.run(&option).unwrap();
+    assert_eq!(result.stdout, "/opt/tool/bin\n");
+    assert_eq!(result.code, Some(3));
+}
+
+#[cfg(not(windows))]
+#[test]
+fn test_launch_returns_while_grandchild_holds_pipes() {
+    let launcher = Launcher::new(&EnvHashMap::new(), &EnvHashMap::new());
+    let option = LaunchOption {
+        command: "/bin/sh".to_string(),
+        args: vec!["-c".to_string(), "sleep 5 & echo started".to_string()],
+        ..Default::default()
+    };
+    let start = Instant::now();
+    let result = launcher.run(&option).unwrap();
+    assert!(start.elapsed() < Duration::from_secs(3));
+    assert_eq!(result.stdout, "started\n");
+    assert_eq!(result.code, Some(0));
+}
@@ -1,34 +1,94 @@
 mod app;
+mod backend;
+pub mod cli;
+mod desired;
+mod effective;
+mod expand;
+mod export;
+mod inspect;
+mod launch;
+mod lint;
+mod locate;
+mod profile;
+mod rpc;
 mod scanner;
+mod settings;
+mod shadow;
+mod shim;
+mod solver;
+mod suggest;
 mod task;
+#[cfg(test)]
+mod testutil;
+mod toolchain;
+mod which;
 
+use app::AppEnvAction;
 use app::AppFSTAction;
+use app::AppProfileAction;
+use app::AppSettingAction;
+use app::AppShimAction;
 use app::AppTaskAction;
+use app::FlushPreview;
+use app::Notification;
 use app::AppState;
 use app::SendState;
 use app::TreeNode;
+use desired::Drift;
+use effective::EffectiveEnv;
+use expand::DependencyGraph;
+use export::ExportOption;
+use inspect::{ProcessInfo, ProcessReport};
+use launch::{LaunchOption, LaunchResult};
+use lint::Diagnostic;
+use profile::Profile;
+use rpc::{remove_stale_token_file, Handler, RpcServer};
+use serde_json::Value;
+use std::sync::Arc;
 use scanner::Storage;
+use settings::Settings;
+use shadow::ShadowReport;
+use shim::Shim;
 use tauri::http::response;
+use tauri::RunEvent;
 use tauri::WindowEvent;
 use tauri::Wry;
 
 use std::fs;
 use std::path::PathBuf;
 use std::sync::Mutex;
+use std::time::Duration;
+use task::EnvHashMap;
+use task::ExternalChange;
 use task::TaskLogData;
+use task::get_live_env;
+use locate::Location;
+use solver::PathPlan;
+use suggest::Suggestion;
+use toolchain::ToolInstalls;
+use which::WhichReport;
 use tauri::menu::{Menu, MenuItem};
 use tauri::tray::TrayIconBuilder;
 use tauri::Emitter;
 use tauri::{AppHandle, Context, Manager, State, Window};
+
+const WATCH_INTERVAL: Duration = Duration::from_secs(5);
+const RPC_TOKEN_FILE: &str = "rpc.json";
+
 // #[tauri::command]
 // fn greet(ctx: Context, window: Window, state: State<AppState>, name: &str) -> String {
 //     format!("Hello, {}! You've been greeted from Rust!", name)
 // }
 
 #[tauri::command]
-async fn flush(state: State<'_, Mutex<AppState>>) -> tauri::Result<()> {
+async fn flush(app_handle: AppHandle, state: State<'_, Mutex<AppState>>) -> tauri::Result<()> {
     dbg!("flushing...");
-    state.lock().unwrap().flush().unwrap();
+    // e.g. writing machine variables without elevation, tasks stay pending
+    let notification = match state.lock().unwrap().flush() {
+        Ok(n) => n,
+        Err(e) => Notification::error(&e.to_string()),
+    };
+    app_handle.emit("notification", notification)?;
     Ok(())
 }
 
@@ -40,9 +100,35 @@ async fn send_state(state: State<'_, Mutex<AppState>>) -> tauri::Result<SendStat
 }
 
 #[tauri::command]
-async fn receive_state(state: State<'_, Mutex<AppState>>, task: TaskLogData) -> tauri::Result<()> {
+async fn flush_preview(state: State<'_, Mutex<AppState>>) -> tauri::Result<FlushPreview> {
+    dbg!("flush_preview");
+    let preview = state.lock().unwrap().flush_preview();
+    Ok(preview)
+}
+
+#[tauri::command]
+async fn receive_state(app_handle: AppHandle, state: State<'_, Mutex<AppState>>, task: TaskLogData) -> tauri::Result<()> {
     dbg!(&task);
-    state.lock().unwrap().receive_state(task);
+    // 前端的状态可能已经过时，不能让任务在引擎里 panic
+    let result = state.lock().unwrap().try_receive_state(task);
+    if let Err(msg) = result {
+        app_handle.emit("notification", Notification::warning(&format!("{}, reloaded", msg)))?;
+        app_handle.emit("state-changed", ())?;
+    }
+    Ok(())
+}
+
+/// tasks built by the backend earlier, e.g. "Add to PATH" of a search result.
+/// the env may have changed since, so they are checked before being queued
+#[tauri::command]
+async fn receive_task(app_handle: AppHandle, state: State<'_, Mutex<AppState>>, task: TaskLogData) -> tauri::Result<()> {
+    dbg!("receive_task");
+    let result = state.lock().unwrap().try_receive_state(task);
+    if let Err(msg) = result {
+        app_handle.emit("notification", Notification::warning(&format!("{}, refresh and try again", msg)))?;
+    }
+    // 无论成功与否，打开的面板都要按最新状态重新生成任务
+    app_handle.emit("state-changed", ())?;
     Ok(())
 }
 
@@ -56,6 +142,269 @@ async fn undo(app_handle: AppHandle, state: State<'_, Mutex<AppState>>) -> tauri
     Ok(())
 }
 
+#[tauri::command]
+async fn rebase(app_handle: AppHandle, state: State<'_, Mutex<AppState>>) -> tauri::Result<()> {
+    dbg!("rebase");
+    // reading the backend is slow, dont hold the lock meanwhile
+    let live = get_live_env();
+    let notification = state.lock().unwrap().rebase(live);
+    app_handle.emit("notification", notification)?;
+    Ok(())
+}
+
+#[tauri::command]
+async fn export_env(state: State<'_, Mutex<AppState>>, option: ExportOption) -> tauri::Result<String> {
+    dbg!("export_env");
+    let result = state.lock().unwrap().export(&option);
+    Ok(result)
+}
+
+#[tauri::command]
+async fn lint(state: State<'_, Mutex<AppState>>) -> tauri::Result<Vec<Diagnostic>> {
+    dbg!("lint");
+    let result = state.lock().unwrap().lint();
+    Ok(result)
+}
+
+#[tauri::command]
+async fn fix_all(app_handle: AppHandle, state: State<'_, Mutex<AppState>>) -> tauri::Result<()> {
+    dbg!("fix_all");
+    let notification = state.lock().unwrap().fix_all();
+    app_handle.emit("notification", notification)?;
+    Ok(())
+}
+
+#[tauri::command]
+async fn desired_check(
+    app_handle: AppHandle,
+    state: State<'_, Mutex<AppState>>,
+    path: &str,
+) -> tauri::Result<Vec<Drift>> {
+    dbg!("desired_check");
+    let result = state.lock().unwrap().check_desired(path);
+    match result {
+        Ok(drifts) => Ok(drifts),
+        Err(msg) => {
+            app_handle.emit("notification", Notification::error(&msg))?;
+            Ok(vec![])
+        }
+    }
+}
+
+#[tauri::command]
+async fn desired_apply(app_handle: AppHandle, state: State<'_, Mutex<AppState>>, path: &str) -> tauri::Result<()> {
+    dbg!("desired_apply");
+    let notification = state.lock().unwrap().apply_desired(path);
+    app_handle.emit("notification", notification)?;
+    Ok(())
+}
+
+#[tauri::command]
+async fn which(state: State<'_, Mutex<AppState>>, command: &str) -> tauri::Result<WhichReport> {
+    dbg!("which");
+    let result = state.lock().unwrap().which(command);
+    Ok(result)
+}
+
+#[tauri::command]
+async fn shadow_report(state: State<'_, Mutex<AppState>>) -> tauri::Result<ShadowReport> {
+    dbg!("shadow_report");
+    let result = state.lock().unwrap().shadow_report();
+    Ok(result)
+}
+
+#[tauri::command]
+async fn env_graph(state: State<'_, Mutex<AppState>>) -> tauri::Result<DependencyGraph> {
+    dbg!("env_graph");
+    let result = state.lock().unwrap().dependency_graph();
+    Ok(result)
+}
+
+#[tauri::command]
+async fn env_dependents(state: State<'_, Mutex<AppState>>, variable: &str) -> tauri::Result<Vec<String>> {
+    dbg!("env_dependents");
+    let result = state.lock().unwrap().dependents(variable);
+    Ok(result)
+}
+
+#[tauri::command]
+async fn machine_env(state: State<'_, Mutex<AppState>>) -> tauri::Result<EnvHashMap> {
+    dbg!("machine_env");
+    let result = state.lock().unwrap().machine_env();
+    Ok(result)
+}
+
+#[tauri::command]
+async fn effective_env(state: State<'_, Mutex<AppState>>) -> tauri::Result<EffectiveEnv> {
+    dbg!("effective_env");
+    let result = state.lock().unwrap().effective_env();
+    Ok(result)
+}
+
+#[tauri::command]
+async fn launch(
+    app_handle: AppHandle,
+    state: State<'_, Mutex<AppState>>,
+    option: LaunchOption,
+) -> tauri::Result<Option<LaunchResult>> {
+    dbg!("launch");
+    // dont hold the lock while the process runs, nor block the async runtime
+    let launcher = state.lock().unwrap().launcher();
+    let result = tauri::async_runtime::spawn_blocking(move || launcher.run(&option)).await?;
+    match result {
+        Ok(result) => Ok(Some(result)),
+        Err(msg) => {
+            app_handle.emit("notification", Notification::error(&msg))?;
+            Ok(None)
+        }
+    }
+}
+
+#[tauri::command]
+async fn open_terminal(app_handle: AppHandle, state: State<'_, Mutex<AppState>>, cwd: Option<String>) -> tauri::Result<()> {
+    dbg!("open_terminal");
+    let launcher = state.lock().unwrap().launcher();
+    if let Err(msg) = launcher.open_terminal(cwd.as_deref()) {
+        app_handle.emit("notification", Notification::error(&msg))?;
+    }
+    Ok(())
+}
+
+#[tauri::command]
+async fn processes(state: State<'_, Mutex<AppState>>) -> tauri::Result<Vec<ProcessInfo>> {
+    dbg!("processes");
+    let result = state.lock().unwrap().processes();
+    Ok(result)
+}
+
+#[tauri::command]
+async fn inspect_process(
+    app_handle: AppHandle,
+    state: State<'_, Mutex<AppState>>,
+    pid: u32,
+) -> tauri::Result<Option<ProcessReport>> {
+    dbg!("inspect_process");
+    let result = state.lock().unwrap().inspect_process(pid);
+    match result {
+        Ok(report) => Ok(Some(report)),
+        Err(msg) => {
+            app_handle.emit("notification", Notification::warning(&msg))?;
+            Ok(None)
+        }
+    }
+}
+
+#[tauri::command]
+async fn rpc_start(
+    app_handle: AppHandle,
+    server: State<'_, Mutex<Option<RpcServer>>>,
+    port: Option<u16>,
+) -> tauri::Result<Option<u16>> {
+    dbg!("rpc_start");
+    let mut server = server.lock().unwrap();
+    if let Some(s) = server.as_ref() {
+        return Ok(Some(s.port()));
+    }
+    let token_file = app_handle.path().app_data_dir()?.join(RPC_TOKEN_FILE);
+    match RpcServer::start(port.unwrap_or(0), token_file, rpc_handler(app_handle.clone())) {
+        Ok(s) => {
+            let port = s.port();
+            *server = Some(s);
+            Ok(Some(port))
+        }
+        Err(msg) => {
+            app_handle.emit("notification", Notification::error(&msg))?;
+            Ok(None)
+        }
+    }
+}
+
+#[tauri::command]
+async fn rpc_stop(server: State<'_, Mutex<Option<RpcServer>>>) -> tauri::Result<()> {
+    dbg!("rpc_stop");
+    if let Some(s) = server.lock().unwrap().take() {
+        s.stop();
+    }
+    Ok(())
+}
+
+#[tauri::command]
+async fn rpc_status(server: State<'_, Mutex<Option<RpcServer>>>) -> tauri::Result<Option<u16>> {
+    dbg!("rpc_status");
+    let port = server.lock().unwrap().as_ref().map(|s| s.port());
+    Ok(port)
+}
+
+#[tauri::command]
+async fn profile_list(state: State<'_, Mutex<AppState>>) -> tauri::Result<Vec<Profile>> {
+    dbg!("profile_list");
+    let result = state.lock().unwrap().profiles();
+    Ok(result)
+}
+
+#[tauri::command]
+async fn profile_save(app_handle: AppHandle, state: State<'_, Mutex<AppState>>, profile: Profile) -> tauri::Result<()> {
+    dbg!("profile_save");
+    let notification = state.lock().unwrap().save_profile(profile);
+    app_handle.emit("notification", notification)?;
+    Ok(())
+}
+
+#[tauri::command]
+async fn profile_delete(app_handle: AppHandle, state: State<'_, Mutex<AppState>>, name: &str) -> tauri::Result<()> {
+    dbg!("profile_delete");
+    let notification = state.lock().unwrap().delete_profile(name);
+    app_handle.emit("notification", notification)?;
+    Ok(())
+}
+
+#[tauri::command]
+async fn profile_activate(app_handle: AppHandle, state: State<'_, Mutex<AppState>>, name: &str) -> tauri::Result<()> {
+    dbg!("profile_activate");
+    let notification = state.lock().unwrap().activate_profile(name);
+    app_handle.emit("notification", notification)?;
+    Ok(())
+}
+
+#[tauri::command]
+async fn profile_deactivate(app_handle: AppHandle, state: State<'_, Mutex<AppState>>) -> tauri::Result<()> {
+    dbg!("profile_deactivate");
+    let notification = state.lock().unwrap().deactivate_profile();
+    app_handle.emit("notification", notification)?;
+    Ok(())
+}
+
+#[tauri::command]
+async fn shim_list(state: State<'_, Mutex<AppState>>) -> tauri::Result<Vec<Shim>> {
+    dbg!("shim_list");
+    let result = state.lock().unwrap().shims();
+    Ok(result)
+}
+
+#[tauri::command]
+async fn shim_create(app_handle: AppHandle, state: State<'_, Mutex<AppState>>, target: &str, name: Option<&str>) -> tauri::Result<()> {
+    dbg!("shim_create");
+    let notification = state.lock().unwrap().create_shim(target, name);
+    app_handle.emit("notification", notification)?;
+    Ok(())
+}
+
+#[tauri::command]
+async fn shim_update(app_handle: AppHandle, state: State<'_, Mutex<AppState>>, name: &str, target: &str) -> tauri::Result<()> {
+    dbg!("shim_update");
+    let notification = state.lock().unwrap().update_shim(name, target);
+    app_handle.emit("notification", notification)?;
+    Ok(())
+}
+
+#[tauri::command]
+async fn shim_remove(app_handle: AppHandle, state: State<'_, Mutex<AppState>>, name: &str) -> tauri::Result<()> {
+    dbg!("shim_remove");
+    let notification = state.lock().unwrap().remove_shim(name);
+    app_handle.emit("notification", notification)?;
+    Ok(())
+}
+
 #[tauri::command]
 async fn FST_children(state: State<'_, Mutex<AppState>>, abs_path: Option<&str>) -> tauri::Result<Vec<TreeNode>> {
     dbg!("FST_children");
@@ -63,6 +412,43 @@ async fn FST_children(state: State<'_, Mutex<AppState>>, abs_path: Option<&str>)
     Ok(result)
 }
 
+#[tauri::command]
+async fn FST_find_executable(state: State<'_, Mutex<AppState>>, command: &str) -> tauri::Result<Vec<Location>> {
+    dbg!("FST_find_executable");
+    let result = state.lock().unwrap().find_executable(command);
+    Ok(result)
+}
+
+#[tauri::command]
+async fn FST_suggest_path_entries(state: State<'_, Mutex<AppState>>) -> tauri::Result<Vec<Suggestion>> {
+    dbg!("FST_suggest_path_entries");
+    let result = state.lock().unwrap().suggest_path_entries();
+    Ok(result)
+}
+
+#[tauri::command]
+async fn FST_toolchains(state: State<'_, Mutex<AppState>>) -> tauri::Result<Vec<ToolInstalls>> {
+    dbg!("FST_toolchains");
+    let result = state.lock().unwrap().toolchains();
+    Ok(result)
+}
+
+#[tauri::command]
+async fn FST_solve_path(state: State<'_, Mutex<AppState>>, commands: Vec<String>) -> tauri::Result<PathPlan> {
+    dbg!("FST_solve_path");
+    let result = state.lock().unwrap().solve_path(&commands);
+    Ok(result)
+}
+
+#[tauri::command]
+async fn FST_apply_path_plan(app_handle: AppHandle, state: State<'_, Mutex<AppState>>, dirs: Vec<String>) -> tauri::Result<()> {
+    dbg!("FST_apply_path_plan");
+    let notification = state.lock().unwrap().apply_path_plan(&dirs);
+    app_handle.emit("notification", notification)?;
+    app_handle.emit("state-changed", ())?;
+    Ok(())
+}
+
 #[tauri::command]
 async fn FST_state(app_handle: AppHandle, state: State<'_, Mutex<AppState>>) -> tauri::Result<bool> {
     dbg!("FST_state");
@@ -89,11 +475,63 @@ async fn FST_scan(app_handle: AppHandle, state: State<'_, Mutex<AppState>>) -> t
     let updater = guard.generater();
     drop(guard);
 
-    let new_storage: Storage = updater.consume();
-    state.lock().unwrap().replace(new_storage);
-    
-    let (_, n) = state.lock().unwrap().state(Some(false));
+    run_scan(&app_handle, &state, move || updater.consume()).await
+}
+
+#[tauri::command]
+async fn FST_scan_subtree(app_handle: AppHandle, state: State<'_, Mutex<AppState>>, path: &str) -> tauri::Result<()> {
+    dbg!("FST_scan_subtree");
+    let (busy, n) = state.lock().unwrap().state(None);
+    if busy {
+        app_handle.emit("notification", n)?;
+        return Ok(());
+    }
+    let updater = match state.lock().unwrap().subtree_generater(path) {
+        Ok(u) => u,
+        Err(msg) => {
+            app_handle.emit("notification", Notification::warning(&msg))?;
+            return Ok(());
+        }
+    };
+    let (_, n) = state.lock().unwrap().state(Some(true));
     app_handle.emit("notification", n)?;
+
+    // dont hold the lock while scanning
+    let path = path.to_string();
+    run_scan(&app_handle, &state, move || updater.consume_subtree(&path)).await
+}
+
+/// walks off the async runtime, `updating` is reset even if the walk panics
+async fn run_scan<F>(app_handle: &AppHandle, state: &State<'_, Mutex<AppState>>, scan: F) -> tauri::Result<()>
+where
+    F: FnOnce() -> Storage + Send + 'static,
+{
+    let n = match tauri::async_runtime::spawn_blocking(scan).await {
+        Ok(new_storage) => {
+            state.lock().unwrap().replace(new_storage);
+            state.lock().unwrap().state(Some(false)).1
+        }
+        Err(e) => {
+            state.lock().unwrap().state(Some(false));
+            Notification::error(&format!("Scanning failed: {}", e))
+        }
+    };
+    app_handle.emit("notification", n)?;
+    Ok(())
+}
+
+#[tauri::command]
+async fn settings_get(state: State<'_, Mutex<AppState>>) -> tauri::Result<Settings> {
+    dbg!("settings_get");
+    let result = state.lock().unwrap().settings();
+    Ok(result)
+}
+
+#[tauri::command]
+async fn settings_save(app_handle: AppHandle, state: State<'_, Mutex<AppState>>, settings: Settings) -> tauri::Result<()> {
+    dbg!("settings_save");
+    let notification = state.lock().unwrap().save_settings(settings);
+    app_handle.emit("notification", notification)?;
     Ok(())
 }
 
@@ -102,7 +540,11 @@ pub fn run() {
     tauri::Builder::default()
         .plugin(tauri_plugin_dialog::init())
         .setup(|app| {
-            app.manage(Mutex::new(AppState::new()));
+            let data_dir = app.path().app_data_dir()?;
+            remove_stale_token_file(&data_dir.join(RPC_TOKEN_FILE));
+            app.manage(Mutex::new(AppState::new(data_dir)));
+            app.manage(Mutex::new(None::<RpcServer>));
+            spawn_env_watcher(app.handle().clone());
 
             // let quit_i = MenuItem::with_id(app, "quit", "Quit", true, None::<&str>)?;
             // let menu = Menu::with_items(app, &[&quit_i])?;
@@ -127,14 +569,142 @@ pub fn run() {
         .invoke_handler(tauri::generate_handler![
             flush,
             send_state,
+            flush_preview,
             receive_state,
+            receive_task,
             undo,
+            rebase,
+            export_env,
+            lint,
+            fix_all,
+            desired_check,
+            desired_apply,
+            which,
+            shadow_report,
+            env_graph,
+            env_dependents,
+            machine_env,
+            effective_env,
+            launch,
+            open_terminal,
+            processes,
+            inspect_process,
+            rpc_start,
+            rpc_stop,
+            rpc_status,
+            profile_list,
+            profile_save,
+            profile_delete,
+            profile_activate,
+            profile_deactivate,
+            shim_list,
+            shim_create,
+            shim_update,
+            shim_remove,
             FST_children,
             FST_scan,
-            FST_state
+            FST_scan_subtree,
+            FST_find_executable,
+            FST_suggest_path_entries,
+            FST_toolchains,
+            FST_solve_path,
+            FST_apply_path_plan,
+            FST_state,
+            settings_get,
+            settings_save
         ])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while running tauri application")
+        .run(|app_handle, event| {
+            // app.exit() does not drop managed state, the token file would be left behind
+            if let RunEvent::Exit = event {
+                if let Some(s) = app_handle.state::<Mutex<Option<RpcServer>>>().lock().unwrap().take() {
+                    s.stop();
+                }
+            }
+        });
+}
+
+/// the same operations as the tauri commands, for scripts driving this instance.
+/// the GUI is told to reload after every edit
+fn rpc_handler(app_handle: AppHandle) -> Arc<Handler> {
+    Arc::new(move |method: &str, params: Value| {
+        let state = app_handle.state::<Mutex<AppState>>();
+        let result = match method {
+            "send_state" => serde_json::to_value(state.lock().unwrap().send_state()),
+            "receive_state" => {
+                let task: TaskLogData = serde_json::from_value(params["task"].clone()).map_err(|e| e.to_string())?;
+                state.lock().unwrap().try_receive_state(task)?;
+                Ok(Value::Null)
+            }
+            "flush" => {
+                let notification = state.lock().unwrap().flush().map_err(|e| e.to_string())?;
+                let _ = app_handle.emit("notification", &notification);
+                serde_json::to_value(notification)
+            }
+            "undo" => {
+                let notification = state.lock().unwrap().undo();
+                let _ = app_handle.emit("notification", &notification);
+                serde_json::to_value(notification)
+            }
+            "FST_children" => {
+                let abs_path = params["abs_path"].as_str();
+                serde_json::to_value(state.lock().unwrap().children(abs_path))
+            }
+            "FST_find_executable" => {
+                let command = params["command"].as_str().ok_or("missing 'command'")?;
+                serde_json::to_value(state.lock().unwrap().find_executable(command))
+            }
+            "FST_suggest_path_entries" => serde_json::to_value(state.lock().unwrap().suggest_path_entries()),
+            "FST_toolchains" => serde_json::to_value(state.lock().unwrap().toolchains()),
+            "FST_solve_path" => {
+                let commands: Vec<String> = serde_json::from_value(params["commands"].clone()).map_err(|e| e.to_string())?;
+                serde_json::to_value(state.lock().unwrap().solve_path(&commands))
+            }
+            "FST_apply_path_plan" => {
+                let dirs: Vec<String> = serde_json::from_value(params["dirs"].clone()).map_err(|e| e.to_string())?;
+                serde_json::to_value(state.lock().unwrap().apply_path_plan(&dirs))
+            }
+            _ => return Err(format!("unknown method '{}'", method)),
+        }
+        .map_err(|e| e.to_string())?;
+        if method != "send_state" && method != "FST_children" && method != "FST_suggest_path_entries" && method != "FST_toolchains" && method != "FST_solve_path" {
+            let _ = app_handle.emit("state-changed", ());
+        }
+        Ok(result)
+    })
+}
+
+/// installers may change variables while the app is open, poll the backend
+/// and tell the frontend once the live env diverges from the base of pending tasks
+fn spawn_env_watcher(app_handle: AppHandle) {
+    std::thread::spawn(move || {
+        let mut last_seen: Vec<ExternalChange> = vec![];
+        let mut last_emitted: Vec<ExternalChange> = vec![];
+        loop {
+            std::thread::sleep(WATCH_INTERVAL);
+            // 读一次要起 powershell，窗口不在前台时没人看
+            let focused = app_handle
+                .webview_windows()
+                .values()
+                .any(|w| w.is_focused().unwrap_or(false));
+            if !focused {
+                continue;
+            }
+            let live = get_live_env();
+            let state = app_handle.state::<Mutex<AppState>>();
+            let changes = state.lock().unwrap().external_changes(&live);
+            // the same on two polls, so a flush or a half written change is not reported
+            // an empty list is emitted too, so that the frontend drops its rebase prompt
+            if changes == last_seen && changes != last_emitted {
+                app_handle
+                    .emit("env-changed-externally", &changes)
+                    .expect("failed to emit env-changed-externally");
+                last_emitted = changes.clone();
+            }
+            last_seen = changes;
+        }
+    });
 }
 
 pub fn handle_window_event(window: &tauri::Window, event: &tauri::WindowEvent) {
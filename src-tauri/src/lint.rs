@@ -0,0 +1,334 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::expand::{expand, lookup, references};
+use crate::scanner::Storage;
+use crate::backend::Scope;
+use crate::task::{ConsumeTask, EnvHashMap, TaskLogData, UpdateValueLog};
+
+/// the edit control of the Windows environment dialog truncates PATH here
+const PATH_UI_LIMIT: usize = 2047;
+/// maximum length of a single environment variable on Windows
+const VARIABLE_LIMIT: usize = 32767;
+/// fix_all gives up after this many rounds, in case fixes keep producing diagnostics
+const FIX_ROUNDS: usize = 10;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Info,
+    Warning,
+    Error,
+}
+
+/// dont modify the names of these enum, compatiable with frontend
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum LintKind {
+    NotExist,
+    NotDirectory,
+    Duplicate,
+    /// same directory written with different case or a trailing slash
+    SimilarDuplicate,
+    NoExecutable,
+    TooLong,
+    UnresolvedReference,
+    Whitespace,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub kind: LintKind,
+    pub variable: String,
+    /// None if the diagnostic is about the whole variable
+    pub index: Option<usize>,
+    pub message: String,
+    /// apply with receive_state to fix the diagnostic
+    pub fix: Option<TaskLogData>,
+}
+
+impl Diagnostic {
+    fn new(severity: Severity, kind: LintKind, variable: &str, index: Option<usize>, message: String) -> Self {
+        Self {
+            severity,
+            kind,
+            variable: variable.to_string(),
+            index,
+            message,
+            fix: None,
+        }
+    }
+    fn with_fix(mut self, fix: TaskLogData) -> Self {
+        self.fix = Some(fix);
+        self
+    }
+}
+
+pub struct Linter<'a> {
+    env: &'a EnvHashMap,
+    storage: Option<&'a Storage>,
+}
+
+impl<'a> Linter<'a> {
+    pub fn new(env: &'a EnvHashMap, storage: Option<&'a Storage>) -> Self {
+        Self { env, storage }
+    }
+
+    pub fn lint(&self) -> Vec<Diagnostic> {
+        let mut diagnostics = vec![];
+        let mut keys: Vec<&String> = self.env.keys().collect();
+        keys.sort();
+        for k in keys {
+            let values = self.env.get(k).unwrap();
+            self._lint_length(k, values, &mut diagnostics);
+            self._lint_values(k, values, &mut diagnostics);
+            if is_path_variable(k) {
+                self._lint_path(k, values, &mut diagnostics);
+            }
+        }
+        diagnostics
+    }
+
+    /// fixes of all diagnostics in applying order, and whether FIX_ROUNDS ran out
+    /// before the env stopped producing fixes.
+    pub fn fix_all(&self) -> (Vec<TaskLogData>, bool) {
+        let mut map = self.env.clone();
+        let mut fixes = vec![];
+        let mut diagnostics = self.lint();
+        // 一个修复可能带出新的诊断（去掉空格后变成重复项），所以每轮修完重新 lint
+        for _ in 0..FIX_ROUNDS {
+            let mut round = non_overlapping(diagnostics);
+            if round.is_empty() {
+                return (fixes, false);
+            }
+            // 从大下标往小删，前面的下标不受影响
+            round.sort_by(|a, b| a.0.cmp(&b.0).then(b.1.cmp(&a.1)));
+            for (_, _, fix) in round {
+                fix.forward(&mut map);
+                fixes.push(fix);
+            }
+            diagnostics = Linter::new(&map, self.storage).lint();
+        }
+        let exhausted = diagnostics.iter().any(|d| d.fix.is_some());
+        (fixes, exhausted)
+    }
+
+    fn _lint_length(&self, k: &str, values: &[String], diagnostics: &mut Vec<Diagnostic>) {
+        let len = values.join(";").chars().count();
+        if len > VARIABLE_LIMIT {
+            diagnostics.push(Diagnostic::new(
+                Severity::Error,
+                LintKind::TooLong,
+                k,
+                None,
+                format!("'{}' has {} characters, the limit is {}", k, len, VARIABLE_LIMIT),
+            ));
+        } else if is_path_variable(k) && len > PATH_UI_LIMIT {
+            diagnostics.push(Diagnostic::new(
+                Severity::Warning,
+                LintKind::TooLong,
+                k,
+                None,
+                format!("'{}' has {} characters, the system dialog truncates it at {}", k, len, PATH_UI_LIMIT),
+            ));
+        }
+    }
+
+    fn _lint_values(&self, k: &str, values: &[String], diagnostics: &mut Vec<Diagnostic>) {
+        for (i, v) in values.iter().enumerate() {
+            if v.trim() != v {
+                diagnostics.push(Diagnostic::new(
+                    Severity::Warning,
+                    LintKind::Whitespace,
+                    k,
+                    Some(i),
+                    format!("'{}' has leading or trailing whitespace", v),
+                ).with_fix(trim_value(k, i, v)));
+            }
+            for name in references(v) {
+                if lookup(&name, self.env).is_none() {
+                    diagnostics.push(Diagnostic::new(
+                        Severity::Warning,
+                        LintKind::UnresolvedReference,
+                        k,
+                        Some(i),
+                        format!("'{}' referenced in '{}' is not defined", name, v),
+                    ));
+                }
+            }
+        }
+    }
+
+    fn _lint_path(&self, k: &str, values: &[String], diagnostics: &mut Vec<Diagnostic>) {
+        // normalized entry -> first index
+        let mut seen_exact: HashMap<&str, usize> = HashMap::new();
+        let mut seen_similar: HashMap<String, usize> = HashMap::new();
+        // script_count 统计的是整棵子树，这里只看目录下直接的可执行文件
+        let by_dir = self.storage.map(|s| s.executables_by_dir());
+
+        for (i, v) in values.iter().enumerate() {
+            if let Some(first) = seen_exact.get(v.as_str()) {
+                diagnostics.push(Diagnostic::new(
+                    Severity::Warning,
+                    LintKind::Duplicate,
+                    k,
+                    Some(i),
+                    format!("'{}' duplicates entry {}", v, first),
+                ).with_fix(TaskLogData::delete_value(k, i, v, Scope::User)));
+                continue;
+            }
+            seen_exact.insert(v, i);
+            let similar = normalize(v);
+            if let Some(first) = seen_similar.get(&similar) {
+                diagnostics.push(Diagnostic::new(
+                    Severity::Warning,
+                    LintKind::SimilarDuplicate,
+                    k,
+                    Some(i),
+                    format!("'{}' is the same directory as entry {} '{}'", v, first, values[*first]),
+                ).with_fix(TaskLogData::delete_value(k, i, v, Scope::User)));
+                continue;
+            }
+            seen_similar.insert(similar, i);
+
+            // unresolved references are reported above, skip the file system checks
+            let expanded = match expand(v, self.env) {
+                Some(e) => e,
+                None => continue,
+            };
+            let path = Path::new(expanded.trim());
+            if !path.exists() {
+                diagnostics.push(Diagnostic::new(
+                    Severity::Warning,
+                    LintKind::NotExist,
+                    k,
+                    Some(i),
+                    format!("'{}' does not exist", v),
+                ).with_fix(TaskLogData::delete_value(k, i, v, Scope::User)));
+                continue;
+            }
+            if !path.is_dir() {
+                diagnostics.push(Diagnostic::new(
+                    Severity::Warning,
+                    LintKind::NotDirectory,
+                    k,
+                    Some(i),
+                    format!("'{}' is not a directory", v),
+                ).with_fix(TaskLogData::delete_value(k, i, v, Scope::User)));
+                continue;
+            }
+            // only scanned directories are reported, commands in subdirectories are not found by PATH
+            if let (Some(storage), Some(by_dir)) = (self.storage, by_dir.as_ref()) {
+                if storage.get(path.to_str().unwrap()).is_some() && !by_dir.contains_key(path) {
+                    diagnostics.push(Diagnostic::new(
+                        Severity::Info,
+                        LintKind::NoExecutable,
+                        k,
+                        Some(i),
+                        format!("'{}' holds no executables according to the last scan", v),
+                    ));
+                }
+            }
+        }
+    }
+}
+
+/// at most one fix per value; a fix of the whole variable only when nothing else in it is fixed
+fn non_overlapping(diagnostics: Vec<Diagnostic>) -> Vec<(String, Option<usize>, TaskLogData)> {
+    let mut round: Vec<(String, Option<usize>, TaskLogData)> = vec![];
+    for d in diagnostics {
+        let overlaps = round
+            .iter()
+            .any(|(v, i, _)| *v == d.variable && (*i == d.index || i.is_none() || d.index.is_none()));
+        if let (Some(fix), false) = (d.fix, overlaps) {
+            round.push((d.variable, d.index, fix));
+        }
+    }
+    round
+}
+
+fn trim_value(variable: &str, index: usize, value: &str) -> TaskLogData {
+    if value.trim().is_empty() {
+        return TaskLogData::delete_value(variable, index, value, Scope::User);
+    }
+    TaskLogData::ModifyValue(UpdateValueLog {
+        variable: variable.to_string(),
+        index,
+        old_value: value.to_string(),
+        new_value: value.trim().to_string(),
+        ..Default::default()
+    })
+}
+
+pub fn is_path_variable(name: &str) -> bool {
+    name.eq_ignore_ascii_case("PATH")
+}
+
+/// for comparing PATH entries, `C:\Bin\` equals `c:\bin` on Windows,
+/// other filesystems are case-sensitive so only the trailing separator is ignored
+pub fn normalize(value: &str) -> String {
+    let trimmed = value
+        .trim()
+        .trim_end_matches(|c| c == '\\' || c == '/')
+        .to_string();
+    // 只有 Windows 的文件系统不区分大小写
+    if cfg!(windows) {
+        trimmed.to_lowercase()
+    } else {
+        trimmed
+    }
+}
+
+#[test]
+fn test_lint_duplicates() {
+    let mut env = EnvHashMap::new();
+    env.insert(
+        "Path".to_string(),
+        vec!["C:\\Tools".to_string(), "C:\\Tools\\".to_string(), "C:\\Tools".to_string()],
+    );
+    let diagnostics = Linter::new(&env, None).lint();
+    assert!(diagnostics.iter().any(|d| d.kind == LintKind::SimilarDuplicate && d.index == Some(1)));
+    assert!(diagnostics.iter().any(|d| d.kind == LintKind::Duplicate && d.index == Some(2)));
+}
+
+#[test]
+fn test_lint_fix_all() {
+    let dir = std::env::temp_dir().to_str().unwrap().to_string();
+    let mut env = EnvHashMap::new();
+    env.insert(
+        "Path".to_string(),
+        vec![format!(" {}", dir), dir.clone(), "/not/exist/environmentor".to_string(), dir.clone()],
+    );
+    let mut map = env.clone();
+    let (fixes, exhausted) = Linter::new(&env, None).fix_all();
+    assert!(!exhausted);
+    for fix in fixes {
+        fix.forward(&mut map);
+    }
+    assert_eq!(map["Path"], vec![dir]);
+}
+
+#[cfg(not(windows))]
+#[test]
+fn test_lint_no_executable() {
+    use crate::scanner::{ScanRoot, StorageUpdater};
+    use crate::testutil::{executable, TempDir};
+
+    let root = TempDir::new("lint_no_executable");
+    executable(&root.join("app").join("bin").join("tool"));
+    let storage = StorageUpdater::from(Storage::default())
+        .roots(&[ScanRoot::new(root.to_str())])
+        .consume_subtree(root.to_str());
+    let app = root.join("app").to_str().unwrap().to_string();
+    let bin = root.join("app").join("bin").to_str().unwrap().to_string();
+    let mut env = EnvHashMap::new();
+    env.insert("PATH".to_string(), vec![app, bin]);
+    let diagnostics = Linter::new(&env, Some(&storage)).lint();
+    let no_executable: Vec<Option<usize>> = diagnostics
+        .iter()
+        .filter(|d| d.kind == LintKind::NoExecutable)
+        .map(|d| d.index)
+        .collect();
+    // app 的子目录里有 tool，但 app 本身没有
+    assert_eq!(no_executable, vec![Some(0)]);
+}
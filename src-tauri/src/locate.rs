@@ -0,0 +1,109 @@
+use serde::{Deserialize, Serialize};
+
+use crate::backend::Scope;
+use crate::expand::expand;
+use crate::lint::{is_path_variable, normalize};
+use crate::scanner::Storage;
+use crate::task::{AddValueLog, AddVariableLog, EnvHashMap, GroupLog, InsertValueLog, TaskLogData};
+use crate::which::{path_entries, pathext};
+
+/// a scanned file `command` would run
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Location {
+    pub file: String,
+    pub dir: String,
+    /// `dir` is an entry of the effective PATH
+    pub on_path: bool,
+    /// appends `dir` to the user PATH, None if it is on PATH already
+    pub add_to_path: Option<TaskLogData>,
+}
+
+/// every scanned directory containing `command`, e.g. the ffmpeg inside Format Factory.
+/// `effective` decides what is on PATH, the task edits `user`
+pub fn locate(command: &str, storage: &Storage, effective: &EnvHashMap, user: &EnvHashMap) -> Vec<Location> {
+    let on_path: Vec<String> = path_entries(effective)
+        .iter()
+        .filter_map(|e| expand(e, effective))
+        .map(|e| normalize(&e))
+        .collect();
+    storage
+        .find_executable(command, &pathext(effective))
+        .into_iter()
+        .filter_map(|file| {
+            let dir = file.parent()?.to_str()?.to_string();
+            let found = on_path.contains(&normalize(&dir));
+            Some(Location {
+                file: file.to_str()?.to_string(),
+                add_to_path: if found { None } else { Some(add_to_path(user, &dir)) },
+                dir,
+                on_path: found,
+            })
+        })
+        .collect()
+}
+
+pub fn add_to_path(user: &EnvHashMap, dir: &str) -> TaskLogData {
+    edit_path(user, "add", dir, |variable| {
+        TaskLogData::AppendValue(AddValueLog {
+            variable: variable.to_string(),
+            value: dir.to_string(),
+            scope: Scope::User,
+        })
+    })
+}
+
+/// like `add_to_path`, but `dir` wins over the existing entries
+pub fn prepend_to_path(user: &EnvHashMap, dir: &str) -> TaskLogData {
+    edit_path(user, "prepend", dir, |variable| {
+        TaskLogData::InsertValue(InsertValueLog {
+            variable: variable.to_string(),
+            index: 0,
+            value: dir.to_string(),
+            scope: Scope::User,
+        })
+    })
+}
+
+// 用户变量里没有 PATH 就先建一个，两个任务合成一组
+fn edit_path(user: &EnvHashMap, name: &str, dir: &str, task: impl Fn(&str) -> TaskLogData) -> TaskLogData {
+    match user.keys().find(|k| is_path_variable(k)) {
+        Some(k) => task(k),
+        None => TaskLogData::Group(GroupLog {
+            name: format!("{} {} to PATH", name, dir),
+            tasks: vec![
+                TaskLogData::AddVariable(AddVariableLog {
+                    variable: "PATH".to_string(),
+                    scope: Scope::User,
+                }),
+                task("PATH"),
+            ],
+        }),
+    }
+}
+
+#[cfg(not(windows))]
+#[test]
+fn test_locate_hidden_executable() {
+    use crate::scanner::{ScanRoot, StorageUpdater};
+    use crate::task::ConsumeTask;
+    use crate::testutil::{executable, TempDir};
+
+    let root = TempDir::new("locate");
+    let hidden = root.join("FormatFactory").join("encoder");
+    let ffmpeg = hidden.join("ffmpeg");
+    executable(&ffmpeg);
+    let root_str = root.to_str();
+    let storage = StorageUpdater::from(Storage::default())
+        .roots(&[ScanRoot::new(root_str)])
+        .consume_subtree(root_str);
+
+    let mut user = EnvHashMap::new();
+    user.insert("PATH".to_string(), vec!["/usr/bin".to_string()]);
+    let found = locate("ffmpeg", &storage, &user, &user);
+    assert_eq!(found.len(), 1);
+    assert!(!found[0].on_path);
+    let mut env = user.clone();
+    found[0].add_to_path.as_ref().unwrap().forward(&mut env);
+    assert_eq!(env["PATH"], vec!["/usr/bin".to_string(), hidden.to_str().unwrap().to_string()]);
+    assert!(locate("ffmpeg", &storage, &env, &env)[0].on_path);
+}
@@ -0,0 +1,392 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+
+use crate::effective::existing_key;
+use crate::task::{
+    push_task, AddValueLog, AddVariableLog, DeleteValueLog, DeleteVariableLog, EnvHashMap, GroupLog,
+    InsertValueLog, SetVariableLog, TaskLog, TaskLogData,
+};
+
+const PROFILE_DIR: &str = "profiles";
+const ACTIVE_FILE: &str = "active_profile.json";
+
+/// dont modify the names of these enum, compatiable with frontend
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum ProfileEntry {
+    /// replace all values of the variable
+    Set { variable: String, values: Vec<String> },
+    /// insert the value in front of the variable, e.g. `bin` into PATH
+    Prepend { variable: String, value: String },
+    Append { variable: String, value: String },
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Profile {
+    pub name: String,
+    pub entries: Vec<ProfileEntry>,
+}
+
+/// what an activated profile really changed, so that deactivation only removes these
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Activation {
+    pub name: String,
+    /// (variable, value, prepended)
+    added: Vec<(String, String, bool)>,
+    /// (variable, old_values, new_values), old_values is None if the variable did not exist
+    set: Vec<(String, Option<Vec<String>>, Vec<String>)>,
+    /// variables created by the profile
+    created: Vec<String>,
+}
+
+/// an activation or deactivation which is not flushed yet
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Transition {
+    /// the queued task group, the transition is gone once it is undone or dropped
+    task: TaskLog,
+    active: Option<Activation>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ProfileManager {
+    dir: PathBuf,
+    /// the activation as of the last flush, saved in ACTIVE_FILE
+    committed: Option<Activation>,
+    pending: Vec<Transition>,
+}
+
+impl ProfileManager {
+    pub fn new(data_dir: PathBuf) -> Self {
+        let dir = data_dir.join(PROFILE_DIR);
+        let _ = fs::create_dir_all(&dir);
+        let committed = fs::read_to_string(data_dir.join(ACTIVE_FILE))
+            .ok()
+            .and_then(|s| serde_json::from_str(&s).ok());
+        Self {
+            dir,
+            committed,
+            pending: vec![],
+        }
+    }
+
+    /// the active profile of the pending env
+    pub fn active(&self) -> Option<String> {
+        self._current().map(|a| a.name.clone())
+    }
+
+    /// drop the transitions whose task groups are no longer pending, e.g. after undo or rebase.
+    /// later transitions were built on top of a dropped one, so they are dropped too
+    pub fn sync(&mut self, pending: &[TaskLog]) {
+        let kept = self
+            .pending
+            .iter()
+            .take_while(|t| pending.iter().any(|p| same_task(p, &t.task)))
+            .count();
+        self.pending.truncate(kept);
+    }
+
+    /// the pending env is flushed, its activation becomes the committed one
+    pub fn commit(&mut self) {
+        self.committed = self._current().cloned();
+        self.pending.clear();
+        self._persist_active();
+    }
+
+    pub fn list(&self) -> Vec<Profile> {
+        let mut profiles: Vec<Profile> = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries
+                .filter_map(|e| e.ok())
+                .filter(|e| e.path().extension().map_or(false, |ext| ext == "json"))
+                .filter_map(|e| fs::read_to_string(e.path()).ok())
+                .filter_map(|s| serde_json::from_str(&s).ok())
+                .collect(),
+            Err(_) => vec![],
+        };
+        profiles.sort_by(|a, b| a.name.cmp(&b.name));
+        profiles
+    }
+
+    pub fn get(&self, name: &str) -> Option<Profile> {
+        let s = fs::read_to_string(self._file(name)).ok()?;
+        serde_json::from_str(&s).ok()
+    }
+
+    pub fn save(&self, profile: &Profile) -> Result<(), Box<dyn std::error::Error>> {
+        fs::create_dir_all(&self.dir)?;
+        fs::write(self._file(&profile.name), serde_json::to_string_pretty(profile)?)?;
+        Ok(())
+    }
+
+    pub fn delete(&self, name: &str) -> Result<(), Box<dyn std::error::Error>> {
+        fs::remove_file(self._file(name))?;
+        Ok(())
+    }
+
+    /// generate the task group which activates `name` on top of `env`,
+    /// the current active profile is deactivated in the same group.
+    /// the group should be queued as is, `sync` recognizes it by its timestamp
+    pub fn activate(&mut self, name: &str, env: &EnvHashMap) -> Result<TaskLog, String> {
+        let profile = match self.get(name) {
+            Some(p) => p,
+            None => return Err(format!("profile '{}' not found", name)),
+        };
+        let mut map = env.clone();
+        let mut tasks = self._deactivate_tasks(&mut map);
+        let mut activation = Activation {
+            name: profile.name.clone(),
+            ..Default::default()
+        };
+
+        for entry in profile.entries.iter() {
+            let name = match entry {
+                ProfileEntry::Set { variable, .. } => variable,
+                ProfileEntry::Prepend { variable, .. } => variable,
+                ProfileEntry::Append { variable, .. } => variable,
+            };
+            // Windows 上 `PATH` 要落到已有的 `Path` 上，不能再建一个
+            let variable = &match existing_key(&map, name) {
+                Some(key) => key,
+                None => {
+                    push_task(&mut tasks, &mut map, TaskLogData::AddVariable(AddVariableLog {
+                        variable: name.clone(),
+                        ..Default::default()
+                    }));
+                    activation.created.push(name.clone());
+                    name.clone()
+                }
+            };
+            let values = map.get(variable).unwrap().clone();
+            match entry {
+                ProfileEntry::Set { values: new_values, .. } => {
+                    let old_values = if activation.created.contains(variable) {
+                        None
+                    } else {
+                        Some(values.clone())
+                    };
+                    push_task(&mut tasks, &mut map, TaskLogData::SetVariable(SetVariableLog {
+                        variable: variable.clone(),
+                        old_values: values,
+                        new_values: new_values.clone(),
+                        ..Default::default()
+                    }));
+                    activation.set.push((variable.clone(), old_values, new_values.clone()));
+                }
+                ProfileEntry::Prepend { value, .. } => {
+                    if values.first() == Some(value) {
+                        continue;
+                    }
+                    push_task(&mut tasks, &mut map, TaskLogData::InsertValue(InsertValueLog {
+                        variable: variable.clone(),
+                        index: 0,
+                        value: value.clone(),
+                        ..Default::default()
+                    }));
+                    activation.added.push((variable.clone(), value.clone(), true));
+                }
+                ProfileEntry::Append { value, .. } => {
+                    if values.last() == Some(value) {
+                        continue;
+                    }
+                    push_task(&mut tasks, &mut map, TaskLogData::AppendValue(AddValueLog {
+                        variable: variable.clone(),
+                        value: value.clone(),
+                        ..Default::default()
+                    }));
+                    activation.added.push((variable.clone(), value.clone(), false));
+                }
+            }
+        }
+
+        let task: TaskLog = TaskLogData::Group(GroupLog {
+            name: format!("activate profile '{}'", profile.name),
+            tasks,
+        })
+        .into();
+        self.pending.push(Transition {
+            task: task.clone(),
+            active: Some(activation),
+        });
+        Ok(task)
+    }
+
+    /// generate the task group which removes what the active profile added,
+    /// values edited by the user after activation are left untouched.
+    pub fn deactivate(&mut self, env: &EnvHashMap) -> Result<TaskLog, String> {
+        let name = match self.active() {
+            Some(n) => n,
+            None => return Err("no active profile".to_string()),
+        };
+        let mut map = env.clone();
+        let tasks = self._deactivate_tasks(&mut map);
+        let task: TaskLog = TaskLogData::Group(GroupLog {
+            name: format!("deactivate profile '{}'", name),
+            tasks,
+        })
+        .into();
+        self.pending.push(Transition {
+            task: task.clone(),
+            active: None,
+        });
+        Ok(task)
+    }
+
+    fn _current(&self) -> Option<&Activation> {
+        match self.pending.last() {
+            Some(t) => t.active.as_ref(),
+            None => self.committed.as_ref(),
+        }
+    }
+
+    fn _deactivate_tasks(&self, map: &mut EnvHashMap) -> Vec<TaskLogData> {
+        let mut tasks = vec![];
+        let activation = match self._current() {
+            Some(a) => a,
+            None => return tasks,
+        };
+
+        for (variable, value, prepended) in activation.added.iter().rev() {
+            let values = match map.get(variable) {
+                Some(v) => v,
+                None => continue,
+            };
+            // prepended values are searched from the front, appended from the back
+            let index = if *prepended {
+                values.iter().position(|v| v == value)
+            } else {
+                values.iter().rposition(|v| v == value)
+            };
+            if let Some(index) = index {
+                push_task(&mut tasks, map, TaskLogData::DeleteValue(DeleteValueLog {
+                    variable: variable.clone(),
+                    index,
+                    value: value.clone(),
+                    ..Default::default()
+                }));
+            }
+        }
+        for (variable, old_values, new_values) in activation.set.iter().rev() {
+            if map.get(variable) != Some(new_values) {
+                continue;
+            }
+            if let Some(old_values) = old_values {
+                push_task(&mut tasks, map, TaskLogData::SetVariable(SetVariableLog {
+                    variable: variable.clone(),
+                    old_values: new_values.clone(),
+                    new_values: old_values.clone(),
+                    ..Default::default()
+                }));
+            }
+        }
+        for variable in activation.created.iter() {
+            let values = match map.get(variable) {
+                Some(v) => v.clone(),
+                None => continue,
+            };
+            let set_by_profile = activation
+                .set
+                .iter()
+                .any(|(k, _, new_values)| k == variable && new_values == &values);
+            if values.is_empty() || set_by_profile {
+                push_task(&mut tasks, map, TaskLogData::DelVariable(DeleteVariableLog {
+                    variable: variable.clone(),
+                    values,
+                    ..Default::default()
+                }));
+            }
+        }
+        tasks
+    }
+
+    fn _persist_active(&self) {
+        let path = self.dir.parent().unwrap().join(ACTIVE_FILE);
+        match &self.committed {
+            Some(a) => {
+                let _ = fs::write(path, serde_json::to_string_pretty(a).unwrap());
+            }
+            None => {
+                let _ = fs::remove_file(path);
+            }
+        }
+    }
+
+    fn _file(&self, name: &str) -> PathBuf {
+        let safe: String = name
+            .chars()
+            .map(|c| if c.is_alphanumeric() || " -_.".contains(c) { c } else { '_' })
+            .collect();
+        self.dir.join(format!("{}.json", safe))
+    }
+}
+
+// rebase 会重建 TaskLog，只比较时间戳不够
+fn same_task(a: &TaskLog, b: &TaskLog) -> bool {
+    a.timestamp == b.timestamp && serde_json::to_value(&a.data).ok() == serde_json::to_value(&b.data).ok()
+}
+
+
+#[test]
+fn test_profile_deactivate_after_edit() {
+    use crate::task::ConsumeTask;
+
+    let dir = crate::testutil::TempDir::new("profile");
+    let mut pm = ProfileManager::new(dir.path().to_path_buf());
+    pm.save(&Profile {
+        name: "Java 17".to_string(),
+        entries: vec![
+            ProfileEntry::Set { variable: "JAVA_HOME".to_string(), values: vec!["C:\\jdk17".to_string()] },
+            ProfileEntry::Prepend { variable: "Path".to_string(), value: "C:\\jdk17\\bin".to_string() },
+        ],
+    })
+    .unwrap();
+
+    let mut env = EnvHashMap::new();
+    env.insert("Path".to_string(), vec!["C:\\a".to_string()]);
+    let mut activated = env.clone();
+    let activation = pm.activate("Java 17", &env).unwrap();
+    activation.data.forward(&mut activated);
+    assert_eq!(activated["Path"], vec!["C:\\jdk17\\bin", "C:\\a"]);
+    assert_eq!(pm.active(), Some("Java 17".to_string()));
+
+    // the user edits PATH after the activation
+    activated.get_mut("Path").unwrap().insert(0, "C:\\b".to_string());
+    let mut deactivated = activated.clone();
+    let deactivation = pm.deactivate(&activated).unwrap();
+    deactivation.data.forward(&mut deactivated);
+    assert_eq!(deactivated["Path"], vec!["C:\\b", "C:\\a"]);
+    assert!(!deactivated.contains_key("JAVA_HOME"));
+    assert_eq!(pm.active(), None);
+
+    // undoing the deactivation, then the activation
+    pm.sync(&[activation.clone()]);
+    assert_eq!(pm.active(), Some("Java 17".to_string()));
+    pm.sync(&[]);
+    assert_eq!(pm.active(), None);
+    assert!(pm.deactivate(&env).is_err());
+
+    // only flushed activations survive a restart
+    pm.activate("Java 17", &env).unwrap();
+    assert_eq!(ProfileManager::new(dir.path().to_path_buf()).active(), None);
+    pm.commit();
+    assert_eq!(ProfileManager::new(dir.path().to_path_buf()).active(), Some("Java 17".to_string()));
+}
+
+#[cfg(windows)]
+#[test]
+fn test_profile_reuses_existing_casing() {
+    use crate::task::ConsumeTask;
+
+    let dir = crate::testutil::TempDir::new("profile_casing");
+    let mut pm = ProfileManager::new(dir.path().to_path_buf());
+    pm.save(&Profile {
+        name: "tools".to_string(),
+        entries: vec![ProfileEntry::Prepend { variable: "PATH".to_string(), value: "C:\\tools".to_string() }],
+    })
+    .unwrap();
+
+    let mut env = EnvHashMap::new();
+    env.insert("Path".to_string(), vec!["C:\\a".to_string()]);
+    let mut activated = env.clone();
+    pm.activate("tools", &env).unwrap().data.forward(&mut activated);
+    assert_eq!(activated.len(), 1);
+    assert_eq!(activated["Path"], vec!["C:\\tools", "C:\\a"]);
+}
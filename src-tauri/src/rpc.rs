@@ -0,0 +1,300 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{Ipv4Addr, Shutdown, SocketAddr, TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::inspect::list_processes;
+
+/// same codes as JSON-RPC 2.0
+const PARSE_ERROR: i64 = -32700;
+const METHOD_NOT_FOUND: i64 = -32601;
+const FAILED: i64 = -32000;
+const UNAUTHORIZED: i64 = -32001;
+const BUSY: i64 = -32002;
+/// idle connections are closed, so that they do not hold a thread forever
+const READ_TIMEOUT: Duration = Duration::from_secs(60);
+/// one thread per connection, more are refused
+const MAX_CONNECTIONS: usize = 8;
+
+/// written to the data dir while the server runs, clients read port and token from it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TokenFile {
+    pub port: u16,
+    pub token: String,
+    pub pid: u32,
+}
+
+/// one request per line, e.g.
+/// `{"jsonrpc": "2.0", "id": 1, "token": "...", "method": "flush", "params": {}}`
+#[derive(Debug, Clone, Deserialize)]
+struct Request {
+    #[serde(default)]
+    id: Value,
+    #[serde(default)]
+    token: String,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+/// (method, params) -> result, the error message is sent back to the client
+pub type Handler = dyn Fn(&str, Value) -> Result<Value, String> + Send + Sync;
+
+/// open connections by id, shut down when the server stops
+type Connections = Arc<Mutex<HashMap<usize, TcpStream>>>;
+
+/// opt-in JSON-RPC server on localhost, guarded by a per-session token
+pub struct RpcServer {
+    port: u16,
+    token_file: PathBuf,
+    stopped: Arc<AtomicBool>,
+    connections: Connections,
+}
+
+impl RpcServer {
+    /// port 0 picks a free port
+    pub fn start(port: u16, token_file: PathBuf, handler: Arc<Handler>) -> Result<Self, String> {
+        let listener = TcpListener::bind(SocketAddr::from((Ipv4Addr::LOCALHOST, port))).map_err(|e| e.to_string())?;
+        let port = listener.local_addr().map_err(|e| e.to_string())?.port();
+        let token = new_token()?;
+        write_token_file(
+            &token_file,
+            &TokenFile {
+                port,
+                token: token.clone(),
+                pid: std::process::id(),
+            },
+        )?;
+
+        let stopped = Arc::new(AtomicBool::new(false));
+        let _stopped = stopped.clone();
+        let connections: Connections = Arc::default();
+        let _connections = connections.clone();
+        std::thread::spawn(move || {
+            for (id, stream) in listener.incoming().enumerate() {
+                if _stopped.load(Ordering::SeqCst) {
+                    break;
+                }
+                let mut stream = match stream {
+                    Ok(s) => s,
+                    Err(_) => continue,
+                };
+                {
+                    let mut open = _connections.lock().unwrap();
+                    if open.len() >= MAX_CONNECTIONS {
+                        let _ = writeln!(stream, "{}", error(Value::Null, BUSY, "too many connections"));
+                        continue;
+                    }
+                    match stream.try_clone() {
+                        Ok(s) => open.insert(id, s),
+                        Err(_) => continue,
+                    };
+                }
+                let handler = handler.clone();
+                let token = token.clone();
+                let connections = _connections.clone();
+                std::thread::spawn(move || {
+                    serve(stream, &token, handler.as_ref());
+                    connections.lock().unwrap().remove(&id);
+                });
+            }
+            println!("[RpcServer] stopped");
+        });
+        println!("[RpcServer] listening on 127.0.0.1:{}", port);
+        Ok(Self {
+            port,
+            token_file,
+            stopped,
+            connections,
+        })
+    }
+
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    pub fn stop(self) {
+        drop(self);
+    }
+}
+
+impl Drop for RpcServer {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::SeqCst);
+        // wake up the blocking accept, so that the thread sees the flag
+        let _ = TcpStream::connect((Ipv4Addr::LOCALHOST, self.port));
+        // connections which are already authenticated must not outlive the server
+        for (_, stream) in self.connections.lock().unwrap().drain() {
+            let _ = stream.shutdown(Shutdown::Both);
+        }
+        let _ = std::fs::remove_file(&self.token_file);
+    }
+}
+
+fn serve(stream: TcpStream, token: &str, handler: &Handler) {
+    if stream.set_read_timeout(Some(READ_TIMEOUT)).is_err() {
+        return;
+    }
+    let mut writer = match stream.try_clone() {
+        Ok(w) => w,
+        Err(_) => return,
+    };
+    for line in BufReader::new(stream).lines() {
+        let line = match line {
+            Ok(l) => l,
+            Err(_) => break,
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        let response = respond(&line, token, handler);
+        if writeln!(writer, "{}", response).is_err() {
+            break;
+        }
+    }
+}
+
+fn respond(line: &str, token: &str, handler: &Handler) -> Value {
+    let request: Request = match serde_json::from_str(line) {
+        Ok(r) => r,
+        Err(e) => return error(Value::Null, PARSE_ERROR, &e.to_string()),
+    };
+    if !constant_time_eq(request.token.as_bytes(), token.as_bytes()) {
+        return error(request.id, UNAUTHORIZED, "invalid token");
+    }
+    match handler(&request.method, request.params) {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": request.id, "result": result }),
+        Err(msg) if msg.starts_with("unknown method") => error(request.id, METHOD_NOT_FOUND, &msg),
+        Err(msg) => error(request.id, FAILED, &msg),
+    }
+}
+
+fn error(id: Value, code: i64, message: &str) -> Value {
+    json!({ "jsonrpc": "2.0", "id": id, "error": { "code": code, "message": message } })
+}
+
+/// the time taken does not tell how many leading bytes of a guess are right
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b.iter()).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// removes the token file left by a session which did not shut down,
+/// the file of a running instance is kept
+pub fn remove_stale_token_file(path: &PathBuf) {
+    let file: TokenFile = match std::fs::read_to_string(path).ok().and_then(|c| serde_json::from_str(&c).ok()) {
+        Some(f) => f,
+        None => return,
+    };
+    if !list_processes().iter().any(|p| p.pid == file.pid) {
+        let _ = std::fs::remove_file(path);
+    }
+}
+
+/// 128 bits from the OS random source, hex encoded
+fn new_token() -> Result<String, String> {
+    let mut bytes = [0u8; 16];
+    random::fill(&mut bytes).map_err(|e| format!("cannot generate a token: {}", e))?;
+    Ok(bytes.iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+#[cfg(not(windows))]
+mod random {
+    use std::io::Read;
+
+    pub fn fill(buffer: &mut [u8]) -> std::io::Result<()> {
+        std::fs::File::open("/dev/urandom")?.read_exact(buffer)
+    }
+}
+
+#[cfg(windows)]
+mod random {
+    const BCRYPT_USE_SYSTEM_PREFERRED_RNG: u32 = 0x2;
+
+    #[link(name = "bcrypt")]
+    extern "system" {
+        fn BCryptGenRandom(algorithm: isize, buffer: *mut u8, size: u32, flags: u32) -> i32;
+    }
+
+    pub fn fill(buffer: &mut [u8]) -> std::io::Result<()> {
+        let status = unsafe { BCryptGenRandom(0, buffer.as_mut_ptr(), buffer.len() as u32, BCRYPT_USE_SYSTEM_PREFERRED_RNG) };
+        if status != 0 {
+            return Err(std::io::Error::other(format!("BCryptGenRandom returned {:#x}", status)));
+        }
+        Ok(())
+    }
+}
+
+/// only readable by the current user, the file never exists with looser permissions
+fn write_token_file(path: &PathBuf, content: &TokenFile) -> Result<(), String> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    }
+    // create_new 不会沿用旧文件的权限
+    let _ = std::fs::remove_file(path);
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(not(windows))]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(path).map_err(|e| e.to_string())?;
+    file.write_all(serde_json::to_string(content).unwrap().as_bytes())
+        .map_err(|e| e.to_string())
+}
+
+#[test]
+fn test_rpc_token_and_dispatch() {
+    let dir = crate::testutil::TempDir::new("rpc");
+    let token_file = dir.join("rpc.json");
+    let handler: Arc<Handler> = Arc::new(|method, params| match method {
+        "echo" => Ok(params),
+        _ => Err(format!("unknown method '{}'", method)),
+    });
+    let server = RpcServer::start(0, token_file.clone(), handler).unwrap();
+    let file: TokenFile = serde_json::from_str(&std::fs::read_to_string(&token_file).unwrap()).unwrap();
+    assert_eq!(file.port, server.port());
+
+    let stream = TcpStream::connect((Ipv4Addr::LOCALHOST, file.port)).unwrap();
+    let mut writer = stream.try_clone().unwrap();
+    let mut reader = BufReader::new(stream);
+    let mut call = |request: Value| {
+        writeln!(writer, "{}", request).unwrap();
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        serde_json::from_str::<Value>(&line).unwrap()
+    };
+    let ok = call(json!({ "id": 1, "token": file.token, "method": "echo", "params": [1] }));
+    assert_eq!(ok["result"], json!([1]));
+    let denied = call(json!({ "id": 2, "token": "guess", "method": "echo" }));
+    assert_eq!(denied["error"]["code"], UNAUTHORIZED);
+    let unknown = call(json!({ "id": 3, "token": file.token, "method": "nope" }));
+    assert_eq!(unknown["error"]["code"], METHOD_NOT_FOUND);
+
+    #[cfg(not(windows))]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        assert_eq!(std::fs::metadata(&token_file).unwrap().permissions().mode() & 0o777, 0o600);
+    }
+
+    server.stop();
+    assert!(!token_file.exists());
+    // the open connection is closed as well
+    let mut line = String::new();
+    assert_eq!(reader.read_line(&mut line).unwrap_or(0), 0);
+}
+
+#[test]
+fn test_constant_time_eq() {
+    assert!(constant_time_eq(b"abc", b"abc"));
+    assert!(!constant_time_eq(b"abc", b"abd"));
+    assert!(!constant_time_eq(b"abc", b"ab"));
+}
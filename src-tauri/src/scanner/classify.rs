@@ -0,0 +1,316 @@
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use std::sync::{Arc, OnceLock};
+
+use crate::which::split_pathext;
+#[cfg(windows)]
+use crate::which::DEFAULT_PATHEXT;
+
+/// not in PATHEXT but loaded or run by other means
+const EXTRA_EXTENSIONS: [&str; 3] = [".dll", ".ps1", ".sh"];
+/// enough for `#!/usr/bin/env python3 -u`
+const HEADER_LEN: usize = 128;
+
+/// built-in categories, custom classifiers may return any other name
+pub const EXECUTABLE: &str = "executable";
+pub const LIBRARY: &str = "library";
+pub const SCRIPT: &str = "script";
+pub const ARCHIVE: &str = "archive";
+pub const INSTALLER: &str = "installer";
+
+const NATIVE_EXTENSIONS: [&str; 2] = [".exe", ".com"];
+const LIBRARY_EXTENSIONS: [&str; 3] = [".dll", ".so", ".dylib"];
+const ARCHIVE_EXTENSIONS: [&str; 10] = [".zip", ".7z", ".rar", ".tar", ".gz", ".tgz", ".xz", ".bz2", ".zst", ".cab"];
+const INSTALLER_EXTENSIONS: [&str; 7] = [".msi", ".msix", ".appx", ".deb", ".rpm", ".pkg", ".dmg"];
+
+/// files of these categories count as `script_count`, they can be run from PATH
+pub fn is_runnable(category: &str) -> bool {
+    [EXECUTABLE, LIBRARY, SCRIPT].contains(&category)
+}
+
+/// files of these categories are commands, libraries are loaded from PATH but not typed in a shell
+pub fn is_command(category: &str) -> bool {
+    [EXECUTABLE, SCRIPT].contains(&category)
+}
+
+/// `libfoo.dll`, `libfoo.so.1`
+pub fn is_library(path: &Path) -> bool {
+    let name = match path.file_name().and_then(|n| n.to_str()) {
+        Some(n) => n.to_lowercase(),
+        None => return false,
+    };
+    LIBRARY_EXTENSIONS.iter().any(|e| name.ends_with(e)) || name.contains(".so.")
+}
+
+/// called by the walker for every file, directories are never passed
+pub trait FileClassifier: Send + Sync {
+    /// None if the file is not counted
+    fn category(&self, path: &Path) -> Option<String>;
+}
+
+/// dont modify the names of these enum, compatiable with frontend
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub enum Executable {
+    Pe,
+    Elf,
+    MachO,
+    /// `#!` with the interpreter name, e.g. `python3` for `#!/usr/bin/env python3`
+    Script { interpreter: String },
+    /// only the extension is known, e.g. `.bat`
+    Extension(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct Classifier {
+    /// lower case, with the dot
+    extensions: Vec<String>,
+}
+
+impl Classifier {
+    /// `pathext` like `.COM;.EXE;.BAT`
+    pub fn new(pathext: &str) -> Self {
+        let mut extensions = split_pathext(pathext);
+        extensions.extend(EXTRA_EXTENSIONS.iter().map(|e| e.to_string()));
+        Self { extensions }
+    }
+
+    /// PATHEXT of this process, other systems have no PATHEXT and rely on the exec bit
+    #[cfg(windows)]
+    pub fn from_env() -> Self {
+        Self::new(&std::env::var("PATHEXT").unwrap_or(DEFAULT_PATHEXT.to_string()))
+    }
+
+    #[cfg(not(windows))]
+    pub fn from_env() -> Self {
+        Self::new("")
+    }
+
+    /// None for directories and ordinary files
+    pub fn classify(&self, path: &Path) -> Option<Executable> {
+        let metadata = std::fs::metadata(path).ok()?;
+        if !metadata.is_file() {
+            return None;
+        }
+        let name = path.file_name()?.to_str()?.to_lowercase();
+        let extension = self.extensions.iter().find(|ext| name.ends_with(ext.as_str()));
+        // 有执行位才读文件头，FAT/NTFS 挂载的盘上所有文件都有执行位，所以还要看文件头。
+        // Windows 没有执行位，只读没有扩展名的文件头，认出去掉扩展名的 PE；
+        // 有扩展名的文件（.txt、.dll 等）一律不读，免得扫描时打开每个文件
+        if has_exec_bit(&metadata) || (cfg!(windows) && path.extension().is_none()) {
+            if let Some(e) = sniff(path) {
+                return Some(e);
+            }
+        }
+        extension.map(|ext| Executable::Extension(ext.clone()))
+    }
+}
+
+impl FileClassifier for Classifier {
+    fn category(&self, path: &Path) -> Option<String> {
+        let name = path.file_name()?.to_str()?.to_lowercase();
+        let has = |extensions: &[&str]| extensions.iter().any(|e| name.ends_with(e));
+        if is_library(path) {
+            return Some(LIBRARY.to_string());
+        }
+        if has(&INSTALLER_EXTENSIONS) {
+            return Some(INSTALLER.to_string());
+        }
+        if has(&ARCHIVE_EXTENSIONS) {
+            return Some(ARCHIVE.to_string());
+        }
+        let category = match self.classify(path)? {
+            Executable::Pe | Executable::Elf | Executable::MachO => EXECUTABLE,
+            Executable::Script { .. } => SCRIPT,
+            Executable::Extension(ext) if NATIVE_EXTENSIONS.contains(&ext.as_str()) => EXECUTABLE,
+            Executable::Extension(_) => SCRIPT,
+        };
+        Some(category.to_string())
+    }
+}
+
+/// a custom category by extension, e.g. `jar` for [".jar", ".war"]
+#[derive(Debug, Clone)]
+pub struct ExtensionClassifier {
+    category: String,
+    extensions: Vec<String>,
+}
+
+impl ExtensionClassifier {
+    pub fn new(category: &str, extensions: &[String]) -> Self {
+        Self {
+            category: category.to_string(),
+            extensions: extensions.iter().map(|e| e.to_lowercase()).collect(),
+        }
+    }
+}
+
+impl FileClassifier for ExtensionClassifier {
+    fn category(&self, path: &Path) -> Option<String> {
+        let name = path.file_name()?.to_str()?.to_lowercase();
+        self.extensions
+            .iter()
+            .any(|e| name.ends_with(e.as_str()))
+            .then(|| self.category.clone())
+    }
+}
+
+/// registered classifiers are asked first, the built-in one last
+pub struct Classifiers {
+    custom: Vec<Arc<dyn FileClassifier>>,
+    builtin: Classifier,
+}
+
+impl Default for Classifiers {
+    fn default() -> Self {
+        Self {
+            custom: vec![],
+            builtin: default_classifier().clone(),
+        }
+    }
+}
+
+impl Classifiers {
+    pub fn register(mut self, classifier: Arc<dyn FileClassifier>) -> Self {
+        self.custom.push(classifier);
+        self
+    }
+}
+
+impl FileClassifier for Classifiers {
+    fn category(&self, path: &Path) -> Option<String> {
+        self.custom
+            .iter()
+            .find_map(|c| c.category(path))
+            .or_else(|| self.builtin.category(path))
+    }
+}
+
+/// built from PATHEXT once, the scanner threads share it
+pub fn default_classifier() -> &'static Classifier {
+    static CLASSIFIER: OnceLock<Classifier> = OnceLock::new();
+    CLASSIFIER.get_or_init(Classifier::from_env)
+}
+
+#[cfg(not(windows))]
+fn has_exec_bit(metadata: &std::fs::Metadata) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    metadata.permissions().mode() & 0o111 != 0
+}
+
+#[cfg(windows)]
+fn has_exec_bit(_metadata: &std::fs::Metadata) -> bool {
+    false
+}
+
+fn sniff(path: &Path) -> Option<Executable> {
+    let mut header = Vec::with_capacity(HEADER_LEN);
+    File::open(path).ok()?.take(HEADER_LEN as u64).read_to_end(&mut header).ok()?;
+    from_header(&header)
+}
+
+pub fn from_header(header: &[u8]) -> Option<Executable> {
+    const MACHO_MAGICS: [[u8; 4]; 5] = [
+        [0xfe, 0xed, 0xfa, 0xce],
+        [0xfe, 0xed, 0xfa, 0xcf],
+        [0xce, 0xfa, 0xed, 0xfe],
+        [0xcf, 0xfa, 0xed, 0xfe],
+        // universal binary
+        [0xca, 0xfe, 0xba, 0xbe],
+    ];
+    if header.starts_with(b"MZ") {
+        return Some(Executable::Pe);
+    }
+    if header.starts_with(b"\x7fELF") {
+        return Some(Executable::Elf);
+    }
+    if MACHO_MAGICS.iter().any(|m| header.starts_with(m)) {
+        return Some(Executable::MachO);
+    }
+    let line = header.strip_prefix(b"#!")?;
+    let line = String::from_utf8_lossy(line);
+    let line = line.lines().next().unwrap_or_default();
+    let mut words = line.split_whitespace();
+    let mut program = words.next()?;
+    // `#!/usr/bin/env -S node --flags`
+    if program.ends_with("/env") {
+        program = words.find(|w| !w.starts_with('-'))?;
+    }
+    let interpreter = program.rsplit(['/', '\\']).next().unwrap_or(program);
+    Some(Executable::Script {
+        interpreter: interpreter.to_string(),
+    })
+}
+
+#[test]
+fn test_classify_header() {
+    assert_eq!(from_header(b"MZ\x90\x00"), Some(Executable::Pe));
+    assert_eq!(from_header(b"\x7fELF\x02\x01"), Some(Executable::Elf));
+    assert_eq!(from_header(&[0xcf, 0xfa, 0xed, 0xfe, 7]), Some(Executable::MachO));
+    assert_eq!(
+        from_header(b"#!/usr/bin/env -S python3 -u\nprint(1)"),
+        Some(Executable::Script {
+            interpreter: "python3".to_string()
+        })
+    );
+    assert_eq!(
+        from_header(b"#! /bin/sh\n"),
+        Some(Executable::Script {
+            interpreter: "sh".to_string()
+        })
+    );
+    assert_eq!(from_header(b"hello"), None);
+    assert_eq!(from_header(b"#!\n"), None);
+}
+
+#[test]
+fn test_classify_extension() {
+    let dir = crate::testutil::TempDir::new("classify");
+    let upper = dir.join("FOO.EXE");
+    std::fs::write(&upper, b"not really").unwrap();
+    let text = dir.join("notes.txt");
+    std::fs::write(&text, b"hello").unwrap();
+    let classifier = Classifier::new(".COM;.EXE");
+    assert_eq!(classifier.classify(&upper), Some(Executable::Extension(".exe".to_string())));
+    assert_eq!(classifier.classify(&text), None);
+    assert_eq!(classifier.classify(dir.path()), None);
+    #[cfg(not(windows))]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        // 改了名字的二进制也能认出来
+        let renamed = dir.join("tool.data");
+        std::fs::write(&renamed, b"\x7fELF\x02\x01\x01").unwrap();
+        std::fs::set_permissions(&renamed, std::fs::Permissions::from_mode(0o755)).unwrap();
+        assert_eq!(classifier.classify(&renamed), Some(Executable::Elf));
+    }
+    #[cfg(not(windows))]
+    {
+        // 没有 PATHEXT 的系统上 .bat 只是普通文件
+        let bat = dir.join("build.bat");
+        std::fs::write(&bat, b"@echo off").unwrap();
+        assert_eq!(Classifier::from_env().classify(&bat), None);
+    }
+    #[cfg(windows)]
+    {
+        // 只有没有扩展名的文件才读文件头
+        let bare = dir.join("tool");
+        std::fs::write(&bare, b"MZ\x90\x00").unwrap();
+        assert_eq!(classifier.classify(&bare), Some(Executable::Pe));
+        let data = dir.join("tool.data");
+        std::fs::write(&data, b"MZ\x90\x00").unwrap();
+        assert_eq!(classifier.classify(&data), None);
+    }
+}
+
+#[test]
+fn test_classifiers_category() {
+    let jar = Arc::new(ExtensionClassifier::new("jar", &[".JAR".to_string()]));
+    let classifiers = Classifiers::default().register(jar);
+    let category = |name: &str| classifiers.category(Path::new(name));
+    assert_eq!(category("/lib/app.jar").as_deref(), Some("jar"));
+    assert_eq!(category("/lib/libssl.so.3").as_deref(), Some(LIBRARY));
+    assert_eq!(category("/dl/setup.MSI").as_deref(), Some(INSTALLER));
+    assert_eq!(category("/dl/src.tar.gz").as_deref(), Some(ARCHIVE));
+    assert_eq!(category("/dl/readme.md"), None);
+}
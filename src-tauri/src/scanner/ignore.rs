@@ -0,0 +1,193 @@
+use std::path::Path;
+
+/// skipped everywhere unless negated by the user
+const COMMON_PRESET: [&str; 3] = ["node_modules", ".git", "target"];
+#[cfg(windows)]
+const OS_PRESET: [&str; 5] = [
+    "$*",
+    "Config.Msi",
+    "System Volume Information",
+    "/Windows/WinSxS",
+    "/Windows/Installer",
+];
+#[cfg(not(windows))]
+const OS_PRESET: [&str; 5] = ["/proc", "/sys", "/dev", "/run", "/tmp"];
+
+#[derive(Debug, Clone, PartialEq)]
+struct Pattern {
+    glob: String,
+    negated: bool,
+    /// starts with `/`, matched against the whole path without drive letter
+    anchored: bool,
+}
+
+impl Pattern {
+    fn parse(line: &str) -> Option<Self> {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+        let (negated, line) = match line.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, line),
+        };
+        let line = line.replace('\\', "/");
+        let line = line.trim_end_matches('/');
+        let (anchored, glob) = match line.strip_prefix('/') {
+            Some(rest) => (true, rest.to_string()),
+            None => (false, line.to_string()),
+        };
+        if glob.is_empty() {
+            return None;
+        }
+        Some(Self {
+            glob,
+            negated,
+            anchored,
+        })
+    }
+
+    /// `components` of the path, without root and drive letter
+    fn matches(&self, components: &[&str]) -> bool {
+        if self.anchored {
+            return glob_match(&self.glob, &components.join("/"));
+        }
+        // `node_modules` or `Windows/WinSxS` match the last components at any depth
+        (0..components.len()).any(|i| glob_match(&self.glob, &components[i..].join("/")))
+    }
+}
+
+/// gitignore-like rules, the last matching pattern wins and `!` re-includes, e.g.
+/// ```text
+/// node_modules
+/// !/home/me/project/node_modules
+/// /Windows/WinSxS
+/// *.tmp
+/// ```
+/// `*` and `?` stay inside one component, `**` crosses them
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct IgnoreRules {
+    patterns: Vec<Pattern>,
+}
+
+impl IgnoreRules {
+    pub fn new(lines: &[String]) -> Self {
+        Self {
+            patterns: lines.iter().filter_map(|l| Pattern::parse(l)).collect(),
+        }
+    }
+
+    /// the built-in patterns of this OS
+    pub fn preset_patterns() -> Vec<String> {
+        COMMON_PRESET
+            .iter()
+            .chain(OS_PRESET.iter())
+            .map(|s| s.to_string())
+            .collect()
+    }
+
+    /// built-in patterns followed by `lines`, so that users may negate them
+    pub fn preset(lines: &[String]) -> Self {
+        let mut all = Self::preset_patterns();
+        all.extend_from_slice(lines);
+        Self::new(&all)
+    }
+
+    /// only `path` itself, the walk never enters ignored directories anyway
+    pub fn matches(&self, path: &Path) -> bool {
+        let components = components(path);
+        self._matches(&components)
+    }
+
+    /// `path` or one of its ancestors is ignored
+    pub fn is_ignored(&self, path: &Path) -> bool {
+        let components = components(path);
+        (1..=components.len()).any(|n| self._matches(&components[..n]))
+    }
+
+    fn _matches(&self, components: &[&str]) -> bool {
+        let mut ignored = false;
+        for p in self.patterns.iter() {
+            if p.negated == ignored && p.matches(components) {
+                ignored = !p.negated;
+            }
+        }
+        ignored
+    }
+}
+
+// `C:\Windows\WinSxS` -> ["Windows", "WinSxS"]
+fn components(path: &Path) -> Vec<&str> {
+    use std::path::Component;
+    path.components()
+        .filter_map(|c| match c {
+            Component::Normal(s) => s.to_str(),
+            _ => None,
+        })
+        .collect()
+}
+
+fn glob_match(glob: &str, text: &str) -> bool {
+    #[cfg(windows)]
+    let (glob, text) = (glob.to_lowercase(), text.to_lowercase());
+    _glob_match(glob.as_bytes(), text.as_bytes())
+}
+
+// 回溯匹配，规则都很短，不需要编译成自动机
+fn _glob_match(glob: &[u8], text: &[u8]) -> bool {
+    match glob.first() {
+        None => text.is_empty(),
+        Some(b'*') if glob.get(1) == Some(&b'*') => {
+            // `**/` may also match nothing
+            let rest = &glob[2..];
+            let rest_no_slash = rest.strip_prefix(b"/").unwrap_or(rest);
+            if _glob_match(rest_no_slash, text) {
+                return true;
+            }
+            (0..text.len()).any(|i| _glob_match(rest, &text[i + 1..]))
+        }
+        Some(b'*') => {
+            let rest = &glob[1..];
+            for i in 0..=text.len() {
+                if _glob_match(rest, &text[i..]) {
+                    return true;
+                }
+                if i < text.len() && text[i] == b'/' {
+                    break;
+                }
+            }
+            false
+        }
+        Some(b'?') => !text.is_empty() && text[0] != b'/' && _glob_match(&glob[1..], &text[1..]),
+        Some(c) => text.first() == Some(c) && _glob_match(&glob[1..], &text[1..]),
+    }
+}
+
+#[test]
+fn test_ignore_rules() {
+    let lines: Vec<String> = [
+        "node_modules",
+        "!/work/keep/node_modules",
+        "/proc",
+        "$*",
+        "build/**/*.o",
+        "*.tmp",
+    ]
+    .iter()
+    .map(|s| s.to_string())
+    .collect();
+    let rules = IgnoreRules::new(&lines);
+    assert!(rules.matches(Path::new("/work/web/node_modules")));
+    assert!(!rules.matches(Path::new("/work/keep/node_modules")));
+    assert!(rules.matches(Path::new("/proc")));
+    assert!(!rules.matches(Path::new("/home/proc")));
+    assert!(rules.matches(Path::new("/mnt/$RECYCLE.BIN")));
+    assert!(rules.matches(Path::new("/src/build/a/b/x.o")));
+    assert!(rules.matches(Path::new("/src/build/x.o")));
+    assert!(!rules.matches(Path::new("/src/build/x.c")));
+    assert!(rules.matches(Path::new("/a/b.tmp")));
+    assert!(!rules.matches(Path::new("/a/b.tmp/c")));
+    // 祖先被忽略，子目录也算忽略
+    assert!(rules.is_ignored(Path::new("/a/b.tmp/c")));
+    assert!(!rules.is_ignored(Path::new("/work/keep/node_modules/x")));
+}
@@ -0,0 +1,124 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::classify::is_command;
+use super::NodeRecord;
+
+/// first line of the dumped file, older files also indexed libraries and are rebuilt
+const HEADER: &str = "# executable index v2";
+
+/// executable files by lower case file name, e.g. "ffmpeg.exe" -> ["D:\FormatFactory\FFModules\Encoder\ffmpeg.exe"]
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct ExecutableIndex {
+    names: BTreeMap<String, BTreeSet<String>>,
+}
+
+impl ExecutableIndex {
+    /// every file record classified as executable or script, libraries are left out
+    pub fn build(path_map: &HashMap<String, NodeRecord>) -> Self {
+        let dirs: HashSet<&Path> = path_map.keys().filter_map(|k| Path::new(k).parent()).collect();
+        let mut index = Self::default();
+        for (k, v) in path_map.iter() {
+            if v.categories.iter().any(|(c, n)| *n > 0 && is_command(c)) && !dirs.contains(Path::new(k)) {
+                index.insert(Path::new(k));
+            }
+        }
+        index
+    }
+
+    pub fn insert(&mut self, file: &Path) {
+        if let (Some(name), Some(file)) = (file.file_name().and_then(|n| n.to_str()), file.to_str()) {
+            self.names.entry(name.to_lowercase()).or_default().insert(file.to_string());
+        }
+    }
+
+    /// files `command` runs, with an extension of `pathext` on Windows, e.g. "ffmpeg" finds "FFMPEG.EXE"
+    pub fn find(&self, command: &str, pathext: &[String]) -> Vec<PathBuf> {
+        let lower = command.to_lowercase();
+        #[cfg(windows)]
+        let names: Vec<String> = std::iter::once(lower.clone())
+            .chain(pathext.iter().map(|ext| format!("{}{}", lower, ext)))
+            .collect();
+        #[cfg(not(windows))]
+        let names = {
+            let _ = pathext;
+            vec![lower]
+        };
+        let mut files: Vec<PathBuf> = names
+            .iter()
+            .filter_map(|n| self.names.get(n))
+            .flatten()
+            .map(PathBuf::from)
+            // 只有 Windows 不区分大小写
+            .filter(|f| cfg!(windows) || f.file_name().map_or(false, |n| n == command))
+            .collect();
+        files.sort();
+        files.dedup();
+        files
+    }
+
+    /// directory -> executable files directly inside it
+    pub fn by_directory(&self) -> BTreeMap<PathBuf, Vec<PathBuf>> {
+        let mut dirs: BTreeMap<PathBuf, Vec<PathBuf>> = BTreeMap::new();
+        for file in self.names.values().flatten().map(PathBuf::from) {
+            if let Some(parent) = file.parent() {
+                dirs.entry(parent.to_path_buf()).or_default().push(file.clone());
+            }
+        }
+        dirs
+    }
+
+    pub fn len(&self) -> usize {
+        self.names.values().map(|f| f.len()).sum()
+    }
+
+    /// one file per line
+    pub fn dump(&self, path: &Path) {
+        let content: Vec<&str> = std::iter::once(HEADER)
+            .chain(self.names.values().flatten().map(|f| f.as_str()))
+            .collect();
+        if let Err(e) = fs::write(path, content.join("\n")) {
+            println!("[ExecutableIndex] dump failed: {}", e);
+        }
+    }
+
+    pub fn load(path: &Path) -> Option<Self> {
+        let content = fs::read_to_string(path).ok()?;
+        let mut lines = content.lines();
+        if lines.next() != Some(HEADER) {
+            return None;
+        }
+        let mut index = Self::default();
+        for line in lines.filter(|l| !l.is_empty()) {
+            index.insert(Path::new(line));
+        }
+        Some(index)
+    }
+}
+
+#[test]
+fn test_executable_index() {
+    let mut path_map = HashMap::new();
+    let record = |category: &str, n| {
+        let mut categories = super::Categories::new();
+        categories.insert(category.to_string(), n);
+        NodeRecord::with(1, 0, n, true).categories(categories)
+    };
+    path_map.insert("/opt".to_string(), record("executable", 2));
+    path_map.insert("/opt/ff".to_string(), record("executable", 2));
+    path_map.insert("/opt/ff/ffmpeg".to_string(), record("executable", 1));
+    path_map.insert("/opt/ff/ffprobe".to_string(), record("script", 1));
+    path_map.insert("/opt/ff/libavcodec.so".to_string(), record("library", 1));
+    path_map.insert("/opt/ff/readme.txt".to_string(), record("text", 0));
+    let index = ExecutableIndex::build(&path_map);
+    assert_eq!(index.len(), 2);
+    assert_eq!(index.find("ffmpeg", &[]), vec![PathBuf::from("/opt/ff/ffmpeg")]);
+    assert!(index.find("ff", &[]).is_empty());
+
+    let dir = crate::testutil::TempDir::new("index");
+    let file = dir.join("index.txt");
+    index.dump(&file);
+    assert_eq!(ExecutableIndex::load(&file), Some(index));
+}
@@ -1,12 +1,24 @@
+mod classify;
+mod ignore;
+mod index;
 mod persist;
+mod roots;
 mod utils;
+mod volume;
 mod walk;
 
 use persist::Persist;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+pub use classify::{is_library, Classifier, Classifiers, Executable, ExtensionClassifier, FileClassifier};
+pub use ignore::IgnoreRules;
+pub use index::ExecutableIndex;
+pub use roots::{default_roots, ScanFilter, ScanRoot};
+pub use volume::{list_volumes, refresh_volumes, Volume, VolumeKind};
 
 #[tokio::test]
 async fn test_scan() {
@@ -16,12 +28,20 @@ async fn test_scan() {
     s1.dump("debug.csv");
 }
 
+/// scan results in the data dir, shared by the app and the cli
+pub const STORAGE_FILE: &str = "output.csv";
+
+/// category -> number of files in the subtree, e.g. {"executable": 12, "library": 40}
+pub type Categories = BTreeMap<String, u64>;
+
 #[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct NodeRecord {
     pub size: u64,
     pub last_modified: u64,
     pub script_count: u64,
     pub is_allowed: bool,
+    #[serde(default)]
+    pub categories: Categories,
 }
 impl NodeRecord {
     pub fn with(size: u64, last_modified: u64, script_count: u64, is_allowed: bool) -> Self {
@@ -30,14 +50,21 @@ impl NodeRecord {
             last_modified,
             script_count,
             is_allowed,
+            categories: Categories::new(),
         }
     }
+    pub fn categories(mut self, categories: Categories) -> Self {
+        self.categories = categories;
+        self
+    }
 }
 
 #[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct Storage {
     path_map: HashMap<String, NodeRecord>,
     pub updating: bool,
+    /// rebuilt after every scan, persisted next to the records
+    index: ExecutableIndex,
 }
 
 impl Storage {
@@ -47,62 +74,180 @@ impl Storage {
     pub fn load(path: &str) -> Self {
         Self::_load(path)
     }
-    /// return (abs_path, node_info, is_allowed)
-    pub fn children(&self, abs_path: Option<&str>) -> Vec<(PathBuf, NodeRecord)> {
+    /// return (abs_path, node_info, is_ignored)
+    /// None lists the default roots and the top most scanned directories.
+    /// ignored entries are kept, so that users can see why they have no size
+    pub fn children(&self, abs_path: Option<&str>, ignore: &IgnoreRules) -> Vec<(PathBuf, NodeRecord, bool)> {
         if let None = abs_path {
-            return utils::get_drives()
-                .iter()
+            let mut tops: Vec<String> = default_roots().into_iter().map(|r| r.path).collect();
+            tops.extend(
+                self.path_map
+                    .keys()
+                    .filter(|k| Path::new(k).parent().map_or(true, |p| !self.path_map.contains_key(p.to_str().unwrap())))
+                    .cloned(),
+            );
+            tops.sort();
+            tops.dedup();
+            return tops
+                .into_iter()
                 .map(|d| {
-                    let p: &str = d.to_str().unwrap();
-                    let record = if let Some(n) = self.path_map.get(p) {
+                    let record = if let Some(n) = self.path_map.get(&d) {
                         n.clone()
                     } else {
                         NodeRecord::default()
                     };
-                    (d.to_owned(), record)
+                    let path = PathBuf::from(d);
+                    let ignored = ignore.is_ignored(&path);
+                    (path, record, ignored)
                 })
                 .collect();
         }
         let abs_path = PathBuf::from(abs_path.unwrap());
+        let parent_ignored = ignore.is_ignored(&abs_path);
         let mut children = vec![];
         if let Ok(entries) = fs::read_dir(&abs_path) {
             let entries_iter = entries
                 .filter(|e| e.is_ok())
                 .map(|e| e.unwrap())
-                .filter(|e| !utils::treat_as_ignore(&e.path()))
                 .map(|e| e.path());
             for entry_pathbuf in entries_iter {
+                let ignored = parent_ignored || ignore.matches(&entry_pathbuf);
                 if let Some(n) = self.path_map.get(entry_pathbuf.to_str().unwrap()) {
-                    children.push((entry_pathbuf, n.clone()))
+                    children.push((entry_pathbuf, n.clone(), ignored))
                 } else {
-                    children.push((entry_pathbuf, NodeRecord::default()))
+                    children.push((entry_pathbuf, NodeRecord::default(), ignored))
                 }
             }
         }
         children
     }
+    pub fn get(&self, abs_path: &str) -> Option<&NodeRecord> {
+        self.path_map.get(abs_path)
+    }
+    /// scripts directly inside each of `dirs`, only scanned dirs are returned
+    pub fn scripts_in(&self, dirs: &[PathBuf]) -> HashMap<PathBuf, Vec<PathBuf>> {
+        let mut result: HashMap<PathBuf, Vec<PathBuf>> = dirs
+            .iter()
+            .filter(|d| d.to_str().map_or(false, |p| self.path_map.contains_key(p)))
+            .map(|d| (d.clone(), vec![]))
+            .collect();
+        for (k, v) in self.path_map.iter() {
+            if v.script_count == 0 {
+                continue;
+            }
+            let p = PathBuf::from(k);
+            if let Some(files) = p.parent().and_then(|parent| result.get_mut(parent)) {
+                files.push(p);
+            }
+        }
+        result
+    }
+    /// directory -> executable files directly inside it
+    pub fn executables_by_dir(&self) -> BTreeMap<PathBuf, Vec<PathBuf>> {
+        self.index.by_directory()
+    }
+    /// number of scanned entries directly inside each directory
+    pub fn entry_counts(&self) -> HashMap<PathBuf, usize> {
+        let mut counts: HashMap<PathBuf, usize> = HashMap::new();
+        for k in self.path_map.keys() {
+            if let Some(parent) = Path::new(k).parent() {
+                *counts.entry(parent.to_path_buf()).or_default() += 1;
+            }
+        }
+        counts
+    }
+    /// files named `command` anywhere in the scanned directories
+    pub fn find_executable(&self, command: &str, pathext: &[String]) -> Vec<PathBuf> {
+        self.index.find(command, pathext)
+    }
     pub fn replace(&mut self, s: Storage) {
         self.path_map = s.path_map;
+        self.index = s.index;
     }
 }
 
 pub struct StorageUpdater {
     pub(crate) path_map: HashMap<String, NodeRecord>,
+    filter: ScanFilter,
+    classifier: Arc<dyn FileClassifier>,
 }
 
 impl From<Storage> for StorageUpdater {
     fn from(s: Storage) -> Self {
-        Self { path_map: s.path_map }
+        // a new scan, volumes may have been mounted since the last one
+        refresh_volumes();
+        Self {
+            path_map: s.path_map,
+            filter: ScanFilter::new(&default_roots()).ignore(IgnoreRules::preset(&[])),
+            classifier: Arc::new(Classifiers::default()),
+        }
     }
 }
 
 impl Into<Storage> for StorageUpdater {
     fn into(self) -> Storage {
-        Storage { path_map: self.path_map, updating: false }
+        let index = ExecutableIndex::build(&self.path_map);
+        Storage {
+            path_map: self.path_map,
+            updating: false,
+            index,
+        }
     }
 }
 
 impl StorageUpdater {
+    /// scan these instead of the default roots
+    pub fn roots(mut self, roots: &[ScanRoot]) -> Self {
+        self.filter = self.filter.roots(roots);
+        self
+    }
+
+    /// replaces the built-in ignore rules
+    pub fn ignore(mut self, rules: IgnoreRules) -> Self {
+        self.filter = self.filter.ignore(rules);
+        self
+    }
+
+    /// replaces the built-in classifier, see `Classifiers` to keep it as fallback
+    pub fn classifier(mut self, classifier: Arc<dyn FileClassifier>) -> Self {
+        self.classifier = classifier;
+        self
+    }
+
+    /// rescan only `path` and merge it, the sizes of its ancestors are adjusted
+    pub fn consume_subtree(mut self, path: &str) -> Storage {
+        println!("[StorageUpdater] scan_subtree: {}", path);
+        let root = Path::new(path);
+        let old = self.path_map.get(path).cloned().unwrap_or_default();
+        let filter = self.filter.clone().start_at(root);
+        let scanned = walk::walk_scan(None, &filter, self.classifier.as_ref()).unwrap();
+        let new = scanned.path_map.get(path).cloned().unwrap_or_default();
+
+        self.path_map.retain(|k, _| !Path::new(k).starts_with(root));
+        self.path_map.extend(
+            scanned
+                .path_map
+                .into_iter()
+                .filter(|(k, _)| Path::new(k).starts_with(root)),
+        );
+        for ancestor in root.ancestors().skip(1) {
+            if let Some(r) = ancestor.to_str().and_then(|a| self.path_map.get_mut(a)) {
+                r.size = (r.size + new.size).saturating_sub(old.size);
+                r.script_count = (r.script_count + new.script_count).saturating_sub(old.script_count);
+                for (c, n) in new.categories.iter() {
+                    *r.categories.entry(c.clone()).or_default() += n;
+                }
+                for (c, n) in old.categories.iter() {
+                    if let Some(v) = r.categories.get_mut(c) {
+                        *v = v.saturating_sub(*n);
+                    }
+                }
+                r.categories.retain(|_, n| *n > 0);
+            }
+        }
+        self.into()
+    }
+
     pub fn consume(mut self) -> Storage {
         println!("[StorageUpdater] update start");
         let time1 = utils::now();
@@ -180,7 +325,36 @@ impl StorageUpdater {
     }
 
     fn _scna_with_cache(&mut self) {
-        let s = walk::walk_scan(Some(&self.path_map)).unwrap();
+        let s = walk::walk_scan(Some(&self.path_map), &self.filter, self.classifier.as_ref()).unwrap();
         self.path_map = s.path_map;
     }
 }
+
+#[test]
+fn test_consume_subtree() {
+    let root = crate::testutil::TempDir::new("subtree");
+    fs::create_dir_all(root.join("bin")).unwrap();
+    // 只有 Windows 按 PATHEXT 认扩展名，其他系统看执行位和文件头
+    let runnable = |name: &str, content: &str| {
+        let file = root.join("bin").join(name);
+        fs::write(&file, content).unwrap();
+        #[cfg(not(windows))]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&file, fs::Permissions::from_mode(0o755)).unwrap();
+        }
+    };
+    runnable("tool.exe", "MZ");
+    let root_str = root.to_str();
+
+    let s = StorageUpdater::from(Storage::default()).roots(&[ScanRoot::new(root_str)]).consume_subtree(root_str);
+    assert_eq!(s.get(root_str).unwrap().script_count, 1);
+
+    // rescanning replaces the old counts instead of adding to them
+    runnable("other.bat", "#!/bin/sh\n");
+    let s = StorageUpdater::from(s).consume_subtree(root.join("bin").to_str().unwrap());
+    assert_eq!(s.get(root_str).unwrap().script_count, 2);
+    let categories = &s.get(root_str).unwrap().categories;
+    assert_eq!(categories.get("executable"), Some(&1));
+    assert_eq!(categories.get("script"), Some(&1));
+}
@@ -1,11 +1,14 @@
 use std::collections::HashMap;
 use std::fs;
+use std::path::{Path, PathBuf};
 
 use crate::scanner::NodeRecord;
 
-use super::Storage;
+use super::{Categories, ExecutableIndex, Storage};
 
 type TyNodeRecord = (String, u64, u64, u64, bool);
+/// the categories column, missing in files written by older versions
+const CATEGORIES_COLUMN: usize = 5;
 
 pub trait Persist {
     fn _dump(&self, path: &str);
@@ -16,6 +19,9 @@ impl Persist for Storage {
     fn _dump(&self, path: &str) {
         let mut keys = self.path_map.keys().cloned().collect::<Vec<String>>();
         keys.sort();
+        if let Some(parent) = Path::new(path).parent() {
+            let _ = fs::create_dir_all(parent);
+        }
         let file = fs::File::options()
             .create(true)
             .write(true)
@@ -31,9 +37,11 @@ impl Persist for Storage {
                 v.last_modified.to_string(),
                 v.script_count.to_string(),
                 v.is_allowed.to_string(),
+                encode_categories(&v.categories),
             ])
             .unwrap();
         }
+        self.index.dump(&index_path(path));
     }
     fn _load(path: &str) -> Self {
         if fs::metadata(path).is_err() {
@@ -41,18 +49,72 @@ impl Persist for Storage {
             return Self::default();
         }
 
-        let m: HashMap<String, NodeRecord> = csv::Reader::from_path(path)
+        // _dump 不写表头
+        let m: HashMap<String, NodeRecord> = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .flexible(true)
+            .from_path(path)
             .unwrap()
-            .deserialize()
-            .map(|r: Result<TyNodeRecord, csv::Error>| {
-                let (k, v1, v3, v4, v5) = r.unwrap();
-                let v = NodeRecord::with(v1, v3, v4, v5);
+            .records()
+            .map(|r| {
+                let mut r = r.unwrap();
+                let categories = decode_categories(r.get(CATEGORIES_COLUMN).unwrap_or_default());
+                r.truncate(CATEGORIES_COLUMN);
+                let (k, v1, v3, v4, v5): TyNodeRecord = r.deserialize(None).unwrap();
+                let v = NodeRecord::with(v1, v3, v4, v5).categories(categories);
                 (k, v)
             })
             .collect();
 
         println!("[Storage] load: found {} records", m.len());
-        Self { path_map: m, updating: false }
+        // 旧版本没有索引文件，从记录里重建
+        let index = ExecutableIndex::load(&index_path(path)).unwrap_or_else(|| ExecutableIndex::build(&m));
+        Self {
+            path_map: m,
+            updating: false,
+            index,
+        }
     }
 }
 
+/// `output.csv` -> `output.index`
+fn index_path(path: &str) -> PathBuf {
+    Path::new(path).with_extension("index")
+}
+
+// executable=12;library=40
+fn encode_categories(categories: &Categories) -> String {
+    categories
+        .iter()
+        .map(|(c, n)| format!("{}={}", c, n))
+        .collect::<Vec<String>>()
+        .join(";")
+}
+
+fn decode_categories(s: &str) -> Categories {
+    s.split(';')
+        .filter_map(|pair| {
+            let (c, n) = pair.split_once('=')?;
+            Some((c.to_string(), n.parse().ok()?))
+        })
+        .collect()
+}
+
+#[test]
+fn test_persist_categories() {
+    let dir = crate::testutil::TempDir::new("persist");
+    let path = dir.join("persist.csv");
+    let path = path.to_str().unwrap();
+    // 旧版本没有 categories 这一列
+    fs::write(path, "/old,1,2,3,true\n").unwrap();
+    let mut s = Storage::_load(path);
+    assert_eq!(s.get("/old").unwrap().script_count, 3);
+
+    let mut categories = Categories::new();
+    categories.insert("executable".to_string(), 12);
+    categories.insert("library".to_string(), 40);
+    s.path_map.insert("/new".to_string(), NodeRecord::with(1, 2, 52, true).categories(categories.clone()));
+    s._dump(path);
+    let s = Storage::_load(path);
+    assert_eq!(s.get("/new").unwrap().categories, categories);
+}
@@ -0,0 +1,136 @@
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+use super::ignore::IgnoreRules;
+use super::volume::list_volumes;
+
+/// a directory to scan, include and exclude are paths relative to it or plain names
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct ScanRoot {
+    pub path: String,
+    /// only scan these sub directories, everything if empty
+    #[serde(default)]
+    pub include: Vec<String>,
+    /// skipped at any depth, e.g. `node_modules` or `Windows/WinSxS`
+    #[serde(default)]
+    pub exclude: Vec<String>,
+}
+
+impl ScanRoot {
+    pub fn new(path: &str) -> Self {
+        Self {
+            path: path.to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// where the walk starts
+    pub fn starts(&self) -> Vec<PathBuf> {
+        let root = PathBuf::from(&self.path);
+        if self.include.is_empty() {
+            return vec![root];
+        }
+        self.include.iter().map(|i| root.join(i)).collect()
+    }
+
+    fn excludes(&self, path: &Path) -> bool {
+        let relative = match path.strip_prefix(&self.path) {
+            Ok(r) => r,
+            Err(_) => return false,
+        };
+        self.exclude.iter().any(|e| {
+            let e = Path::new(e);
+            relative == e || path.file_name().map_or(false, |name| Path::new(name) == e)
+        })
+    }
+}
+
+/// decides which directories the walk enters
+#[derive(Debug, Clone, Default)]
+pub struct ScanFilter {
+    roots: Vec<ScanRoot>,
+    /// overrides the starts of the roots, for rescanning one folder
+    starts: Option<Vec<PathBuf>>,
+    ignore: IgnoreRules,
+}
+
+impl ScanFilter {
+    /// nothing is ignored, see `ignore`
+    pub fn new(roots: &[ScanRoot]) -> Self {
+        Self::default().roots(roots)
+    }
+
+    pub fn roots(mut self, roots: &[ScanRoot]) -> Self {
+        self.roots = roots.to_vec();
+        self
+    }
+
+    pub fn ignore(mut self, rules: IgnoreRules) -> Self {
+        self.ignore = rules;
+        self
+    }
+
+    pub fn start_at(mut self, path: &Path) -> Self {
+        self.starts = Some(vec![path.to_path_buf()]);
+        self
+    }
+
+    pub fn starts(&self) -> Vec<PathBuf> {
+        match &self.starts {
+            Some(s) => s.clone(),
+            None => self.roots.iter().flat_map(|r| r.starts()).collect(),
+        }
+    }
+
+    pub fn allows(&self, path: &Path) -> bool {
+        if self.ignore.matches(path) {
+            return false;
+        }
+        // another root is walked by itself, e.g. `/home` mounted below `/`
+        if self.roots.iter().any(|r| Path::new(&r.path) == path) {
+            return false;
+        }
+        // the innermost root owning the path decides
+        let owner = self
+            .roots
+            .iter()
+            .filter(|r| path.starts_with(&r.path))
+            .max_by_key(|r| r.path.len());
+        match owner {
+            Some(r) => !r.excludes(path),
+            None => true,
+        }
+    }
+}
+
+/// local volumes, pseudo and network file systems are skipped
+pub fn default_roots() -> Vec<ScanRoot> {
+    let mut roots: Vec<ScanRoot> = list_volumes()
+        .iter()
+        .filter(|v| v.scannable())
+        .filter_map(|v| v.mount_point.to_str())
+        .map(ScanRoot::new)
+        .collect();
+    // e.g. `/` is an overlay inside a container
+    #[cfg(not(windows))]
+    if !roots.iter().any(|r| r.path == "/") {
+        roots.insert(0, ScanRoot::new("/"));
+    }
+    roots
+}
+
+#[test]
+fn test_scan_filter() {
+    let mut root = ScanRoot::new("/data");
+    root.exclude = vec!["node_modules".to_string(), "cache/big".to_string()];
+    let filter = ScanFilter::new(&[ScanRoot::new("/"), root]);
+    assert!(filter.allows(Path::new("/data/src")));
+    assert!(!filter.allows(Path::new("/data/web/node_modules")));
+    assert!(!filter.allows(Path::new("/data/cache/big")));
+    assert!(filter.allows(Path::new("/data/web/cache/big")));
+    // walked as its own root
+    assert!(!filter.allows(Path::new("/data")));
+    assert!(filter.allows(Path::new("/usr/node_modules")));
+    let filter = filter.ignore(IgnoreRules::new(&["node_modules".to_string()]));
+    assert!(!filter.allows(Path::new("/usr/node_modules")));
+}
@@ -10,14 +10,13 @@ pub fn now() -> u64 {
         .as_secs()
 }
 
+/// 0 if the entry is gone, symlinks are not followed
 pub fn get_modified(path: &str) -> u64 {
-    fs::metadata(path)
-        .unwrap()
-        .modified()
-        .unwrap()
-        .duration_since(std::time::UNIX_EPOCH)
-        .unwrap()
-        .as_secs()
+    fs::symlink_metadata(path)
+        .and_then(|m| m.modified())
+        .ok()
+        .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+        .map_or(0, |d| d.as_secs())
 }
 
 pub fn treat_as_file(path: &PathBuf) -> bool {
@@ -31,37 +30,9 @@ pub fn treat_as_file(path: &PathBuf) -> bool {
     false
 }
 
-pub fn treat_as_ignore(path: &PathBuf) -> bool {
-    let filename = path.file_name().unwrap().to_str().unwrap().to_string();
-    if filename.starts_with("$") {
-        return true;
-    }
-    if filename.ends_with("Config.Msi") || filename.ends_with("System Volume Information") {
-        return true;
-    }
-    false
-}
-
-#[test]
-fn test_treat_as_ignore() {
-    let path = PathBuf::from("D:\\$RECYCLE.BIN");
-    assert_eq!(treat_as_ignore(&path), true);
-}
-
-pub fn treat_as_script(path: &PathBuf) -> bool {
-    const SCRIPT_EXTENSIONS: [&str; 5] = [".exe", ".dll", ".bat", ".vbs", ".ps1"];
-    let filename = path.to_str().unwrap().to_string();
-    for ext in SCRIPT_EXTENSIONS.iter() {
-        if filename.ends_with(ext) {
-            return true;
-        }
-    }
-    false
-}
-
 pub fn pure_walk(path: &PathBuf) -> Result<u64, Box<dyn std::error::Error>> {
     let mut stack: Vec<PathBuf> = vec![path.into()];
-    let mut size = fs::metadata(path)?.len();
+    let mut size = fs::symlink_metadata(path)?.len();
 
     while let Some(path) = stack.pop() {
         if let Ok(entries) = fs::read_dir(&path) {
@@ -69,8 +40,12 @@ pub fn pure_walk(path: &PathBuf) -> Result<u64, Box<dyn std::error::Error>> {
                 match entry {
                     Ok(entry) => {
                         let path = entry.path();
-                        size += fs::metadata(&path)?.len();
-                        if path.is_dir() {
+                        let metadata = match fs::symlink_metadata(&path) {
+                            Ok(m) => m,
+                            Err(_) => continue,
+                        };
+                        size += metadata.len();
+                        if metadata.is_dir() {
                             stack.push(path.to_owned());
                         }
                     }
@@ -88,10 +63,9 @@ pub fn get_drives() -> Vec<PathBuf> {
     let mut drives = vec![];
     for i in b'C'..=b'Z' {
         let drive = format!("{}:\\", i as char);
+        // letters may have gaps, e.g. C: and E: without D:
         if fs::metadata(&drive).is_ok() {
             drives.push(drive.into());
-        } else {
-            break;
         }
     }
     drives
@@ -0,0 +1,239 @@
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+/// dont modify the names of these enum, compatiable with frontend
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub enum VolumeKind {
+    #[default]
+    Local,
+    /// kernel and memory file systems, nothing installed there
+    Pseudo,
+    /// slow to walk, and may disappear during the scan
+    Network,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct Volume {
+    pub mount_point: PathBuf,
+    pub device: String,
+    pub fs_type: String,
+    pub kind: VolumeKind,
+    /// bytes, 0 if unknown
+    pub total: u64,
+    pub free: u64,
+}
+
+impl Volume {
+    /// only local volumes are scanned by default
+    pub fn scannable(&self) -> bool {
+        self.kind == VolumeKind::Local
+    }
+}
+
+const PSEUDO_FS: [&str; 25] = [
+    "proc", "sysfs", "devtmpfs", "devpts", "tmpfs", "cgroup", "cgroup2", "securityfs", "pstore", "debugfs",
+    "tracefs", "mqueue", "hugetlbfs", "fusectl", "configfs", "bpf", "autofs", "binfmt_misc", "nsfs", "squashfs",
+    "efivarfs", "ramfs", "overlay", "rpc_pipefs", "selinuxfs",
+];
+const NETWORK_FS: [&str; 11] = [
+    "nfs", "nfs4", "cifs", "smb3", "smbfs", "sshfs", "fuse.sshfs", "9p", "afs", "ceph", "glusterfs",
+];
+
+pub fn classify(fs_type: &str) -> VolumeKind {
+    let fs_type = fs_type.to_lowercase();
+    if PSEUDO_FS.contains(&fs_type.as_str()) {
+        VolumeKind::Pseudo
+    } else if NETWORK_FS.contains(&fs_type.as_str()) {
+        VolumeKind::Network
+    } else {
+        VolumeKind::Local
+    }
+}
+
+/// listing runs powershell on Windows, so it is read once per scan, see `refresh_volumes`
+static VOLUMES: Mutex<Option<Vec<Volume>>> = Mutex::new(None);
+
+/// every mounted volume, sorted by mount point
+pub fn list_volumes() -> Vec<Volume> {
+    VOLUMES.lock().unwrap().get_or_insert_with(read_volumes).clone()
+}
+
+/// forget the cached volumes, the next `list_volumes` reads them again
+pub fn refresh_volumes() {
+    *VOLUMES.lock().unwrap() = None;
+}
+
+fn read_volumes() -> Vec<Volume> {
+    let mut volumes = platform::list_volumes();
+    volumes.sort_by(|a, b| a.mount_point.cmp(&b.mount_point));
+    volumes.dedup_by(|a, b| a.mount_point == b.mount_point);
+    volumes
+}
+
+#[cfg(not(windows))]
+mod platform {
+    use super::{classify, Volume};
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::{Path, PathBuf};
+
+    pub fn list_volumes() -> Vec<Volume> {
+        let content = std::fs::read_to_string("/proc/self/mountinfo").unwrap_or_default();
+        content
+            .lines()
+            .filter_map(parse_mountinfo)
+            .map(|mut v| {
+                if v.scannable() {
+                    (v.total, v.free) = space(&v.mount_point).unwrap_or_default();
+                }
+                v
+            })
+            .collect()
+    }
+
+    /// `36 35 98:0 /mnt1 /mnt2 rw,noatime master:1 - ext3 /dev/root rw,errors=continue`,
+    /// the optional fields end with `-`, see proc(5)
+    pub(super) fn parse_mountinfo(line: &str) -> Option<Volume> {
+        let (left, right) = line.split_once(" - ")?;
+        let mount_point = left.split_whitespace().nth(4)?;
+        let mut right = right.split_whitespace();
+        let fs_type = right.next()?;
+        let device = right.next().unwrap_or("none");
+        Some(Volume {
+            mount_point: PathBuf::from(unescape(mount_point)),
+            device: unescape(device),
+            fs_type: fs_type.to_string(),
+            kind: classify(fs_type),
+            total: 0,
+            free: 0,
+        })
+    }
+
+    // 空格、tab、换行和反斜杠被转义成三位八进制，例如 \040
+    fn unescape(s: &str) -> String {
+        let bytes = s.as_bytes();
+        let mut result = vec![];
+        let mut i = 0;
+        while i < bytes.len() {
+            if bytes[i] == b'\\' && i + 4 <= bytes.len() && bytes[i + 1..i + 4].iter().all(|b| (b'0'..=b'7').contains(b)) {
+                let code = std::str::from_utf8(&bytes[i + 1..i + 4]).ok().and_then(|o| u8::from_str_radix(o, 8).ok());
+                if let Some(code) = code {
+                    result.push(code);
+                    i += 4;
+                    continue;
+                }
+            }
+            result.push(bytes[i]);
+            i += 1;
+        }
+        String::from_utf8_lossy(&result).to_string()
+    }
+
+    /// (total, free) in bytes, free is what an unprivileged user may use
+    fn space(path: &Path) -> Option<(u64, u64)> {
+        let path = CString::new(path.as_os_str().as_bytes()).ok()?;
+        let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+        if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } != 0 {
+            return None;
+        }
+        let block = stat.f_frsize as u64;
+        Some((stat.f_blocks as u64 * block, stat.f_bavail as u64 * block))
+    }
+}
+
+#[cfg(windows)]
+mod platform {
+    use super::{classify, Volume, VolumeKind};
+    use serde::Deserialize;
+    use std::os::windows::process::CommandExt;
+    use std::path::PathBuf;
+    use std::process::Command;
+
+    const CREATE_NO_WINDOW: u32 = 0x08000000;
+    /// Win32_LogicalDisk.DriveType
+    const DRIVE_NETWORK: u32 = 4;
+    const DRIVE_RAMDISK: u32 = 6;
+
+    #[derive(Debug, Deserialize)]
+    #[serde(rename_all = "PascalCase")]
+    struct LogicalDisk {
+        #[serde(rename = "DeviceID")]
+        device_id: String,
+        file_system: Option<String>,
+        size: Option<u64>,
+        free_space: Option<u64>,
+        drive_type: u32,
+        provider_name: Option<String>,
+    }
+
+    pub fn list_volumes() -> Vec<Volume> {
+        let script = "Get-CimInstance Win32_LogicalDisk | \
+            Select-Object DeviceID,FileSystem,Size,FreeSpace,DriveType,ProviderName | \
+            ConvertTo-Json -Compress";
+        let output = Command::new("powershell")
+            .args(["-NoProfile", "-Command", script])
+            .creation_flags(CREATE_NO_WINDOW)
+            .output();
+        let stdout = match output {
+            Ok(o) if o.status.success() => String::from_utf8_lossy(&o.stdout).to_string(),
+            // 拿不到就退回盘符探测，没有容量信息
+            _ => {
+                return crate::scanner::utils::get_drives()
+                    .into_iter()
+                    .map(|d| Volume {
+                        mount_point: d,
+                        ..Default::default()
+                    })
+                    .collect()
+            }
+        };
+        // a single disk is not wrapped in an array
+        let disks: Vec<LogicalDisk> = serde_json::from_str(&stdout)
+            .or_else(|_| serde_json::from_str::<LogicalDisk>(&stdout).map(|d| vec![d]))
+            .unwrap_or_default();
+        disks
+            .into_iter()
+            .map(|d| {
+                let fs_type = d.file_system.unwrap_or_default();
+                let kind = match d.drive_type {
+                    DRIVE_NETWORK => VolumeKind::Network,
+                    DRIVE_RAMDISK => VolumeKind::Pseudo,
+                    _ => classify(&fs_type),
+                };
+                Volume {
+                    mount_point: PathBuf::from(format!("{}\\", d.device_id)),
+                    device: d.provider_name.unwrap_or(d.device_id),
+                    fs_type,
+                    kind,
+                    total: d.size.unwrap_or_default(),
+                    free: d.free_space.unwrap_or_default(),
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(not(windows))]
+#[test]
+fn test_parse_mountinfo() {
+    let root = platform::parse_mountinfo("22 1 8:2 / / rw,relatime shared:1 - ext4 /dev/sda2 rw").unwrap();
+    assert_eq!(root.mount_point, PathBuf::from("/"));
+    assert_eq!(root.device, "/dev/sda2");
+    assert!(root.scannable());
+
+    let share = platform::parse_mountinfo("40 22 0:45 / /mnt/my\\040share rw - cifs //nas/share rw").unwrap();
+    assert_eq!(share.mount_point, PathBuf::from("/mnt/my share"));
+    assert_eq!(share.kind, VolumeKind::Network);
+
+    // an escape at the very end is decoded too
+    let tail = platform::parse_mountinfo("41 22 0:46 / /mnt/tail\\040 rw - ext4 /dev/sdb1 rw").unwrap();
+    assert_eq!(tail.mount_point, PathBuf::from("/mnt/tail "));
+    // a cut off escape is kept as is
+    let cut = platform::parse_mountinfo("42 22 0:47 / /mnt/a\\04 rw - ext4 /dev/sdc1 rw").unwrap();
+    assert_eq!(cut.mount_point, PathBuf::from("/mnt/a\\04"));
+
+    let proc = platform::parse_mountinfo("23 22 0:21 / /proc rw,nosuid - proc proc rw").unwrap();
+    assert_eq!(proc.kind, VolumeKind::Pseudo);
+    assert!(platform::parse_mountinfo("garbage").is_none());
+}
@@ -6,10 +6,10 @@ use std::path::PathBuf;
 use std::sync::{Arc, Condvar, Mutex, RwLock};
 use std::{fs, thread};
 
-use super::utils::{
-    get_drives, get_modified, pure_walk, treat_as_file, treat_as_ignore, treat_as_script,
-};
-use super::{NodeRecord, Storage};
+use super::classify::{is_runnable, Classifiers, FileClassifier};
+use super::roots::{ScanFilter, ScanRoot};
+use super::utils::{get_modified, pure_walk, treat_as_file};
+use super::{Categories, ExecutableIndex, NodeRecord, Storage};
 
 // C: 123.02s, 1442000
 // D: 45.07s, 864000
@@ -23,7 +23,8 @@ async fn test_walk_scan() {
     // 145.19s
     // let s = single_thread_walk(Some(&s2.path_map)).unwrap();
     // 45.07s
-    let s = multi_thread_walk(Some(&s2.path_map)).unwrap();
+    let filter = ScanFilter::new(&[ScanRoot::new(TEST_DRIVE)]);
+    let s = multi_thread_walk(Some(&s2.path_map), &filter, &Classifiers::default()).unwrap();
 
     dbg!(s.path_map[TEST_DRIVE].size);
     // D
@@ -33,11 +34,35 @@ async fn test_walk_scan() {
 
 pub fn walk_scan(
     cache: Option<&HashMap<String, NodeRecord>>,
+    filter: &ScanFilter,
+    classifier: &dyn FileClassifier,
 ) -> Result<Storage, Box<dyn std::error::Error>> {
-    // let s = single_thread_walk(cache).unwrap();
-    let s = multi_thread_walk(cache).unwrap();
+    // let s = single_thread_walk(cache, filter, classifier).unwrap();
+    let s = multi_thread_walk(cache, filter, classifier).unwrap();
     Ok(s)
 }
+
+/// (script_count, categories) of one entry, directories are not classified
+fn classify(classifier: &dyn FileClassifier, path: &PathBuf) -> (u64, Categories) {
+    let mut categories = Categories::new();
+    if fs::symlink_metadata(path).map_or(true, |m| m.is_dir()) {
+        return (0, categories);
+    }
+    match classifier.category(path) {
+        Some(c) => {
+            let scripts = if is_runnable(&c) { 1 } else { 0 };
+            categories.insert(c, 1);
+            (scripts, categories)
+        }
+        None => (0, categories),
+    }
+}
+
+fn merge(into: &mut Categories, add: &Categories) {
+    for (c, n) in add.iter() {
+        *into.entry(c.clone()).or_default() += n;
+    }
+}
 // ==================== single thread ====================
 #[derive(Debug, Default)]
 struct _Storage {
@@ -50,6 +75,7 @@ impl _Storage {
         Storage {
             path_map: self._map,
             updating: false,
+            index: ExecutableIndex::default(),
         }
     }
     pub fn load_cache(&mut self, cache: &HashMap<String, NodeRecord>) {
@@ -57,7 +83,7 @@ impl _Storage {
             self._map.insert(k.to_owned(), v.clone());
         }
     }
-    pub fn accumulate(&mut self, path: &PathBuf, add_size: u64, add_scripts: u64) {
+    pub fn accumulate(&mut self, path: &PathBuf, add_size: u64, add_scripts: u64, add_categories: &Categories) {
         // 先尝试添加自己
         let path_string = path.to_str().unwrap().to_string();
         let last_modified = get_modified(&path_string);
@@ -67,16 +93,17 @@ impl _Storage {
                 e.size += add_size;
                 e.last_modified = last_modified;
                 e.script_count += add_scripts;
+                merge(&mut e.categories, add_categories);
                 e.is_allowed = true;
             })
-            .or_insert(NodeRecord::with(add_size, last_modified, add_scripts, true));
+            .or_insert(NodeRecord::with(add_size, last_modified, add_scripts, true).categories(add_categories.clone()));
 
         // recursive call parent to accumulate the size
         let parent = match path.parent() {
             Some(p) => p,
             None => return, // root has no parent
         };
-        self.accumulate(&parent.to_path_buf(), add_size, add_scripts);
+        self.accumulate(&parent.to_path_buf(), add_size, add_scripts, add_categories);
         if self._map.len() % 10000 == 0 {
             println!("accumulate: {} records", self._map.len());
         }
@@ -90,6 +117,7 @@ impl _Storage {
                 e.size = 0;
                 e.last_modified = last_modified;
                 e.script_count = 0;
+                e.categories.clear();
                 e.is_allowed = false;
             })
             .or_insert(NodeRecord::with(0, last_modified, 0, false));
@@ -99,6 +127,8 @@ impl _Storage {
 // 递归改循环
 fn single_thread_walk(
     cache: Option<&HashMap<String, NodeRecord>>,
+    filter: &ScanFilter,
+    classifier: &dyn FileClassifier,
 ) -> Result<Storage, Box<dyn std::error::Error>> {
     let readonly_cache = match cache {
         Some(c) => c,
@@ -107,45 +137,43 @@ fn single_thread_walk(
     let mut storage = _Storage::default();
     storage.load_cache(readonly_cache);
 
-    #[cfg(not(debug_assertions))]
-    let mut stack: Vec<PathBuf> = get_drives();
-    #[cfg(debug_assertions)]
-    let mut stack: Vec<PathBuf> = vec![TEST_DRIVE.into()];
+    let mut stack: Vec<PathBuf> = filter.starts();
 
     while let Some(__path) = stack.pop() {
         // 如果是缓存的目录，则直接累加
         if let Some(v) = readonly_cache.get(__path.to_str().unwrap()) {
-            storage.accumulate(&__path, v.size, v.script_count);
+            storage.accumulate(&__path, v.size, v.script_count, &v.categories);
             continue;
         }
         if let Ok(entries) = fs::read_dir(&__path) {
             let entries_iter = entries
                 .filter(|e| e.is_ok())
                 .map(|e| e.unwrap())
-                .filter(|e| !treat_as_ignore(&e.path()))
-                .map(|e| e.path());
+                .map(|e| e.path())
+                .filter(|p| filter.allows(p));
             for entry_pathbuf in entries_iter {
                 // 如果是缓存的目录，则直接累加
                 if let Some(v) = readonly_cache.get(entry_pathbuf.to_str().unwrap()) {
-                    storage.accumulate(&entry_pathbuf, v.size, v.script_count);
+                    storage.accumulate(&entry_pathbuf, v.size, v.script_count, &v.categories);
                     continue;
                 }
+                // 不跟随符号链接，悬空的链接和 `X11 -> .` 这样的环都会出错
+                let metadata = match fs::symlink_metadata(&entry_pathbuf) {
+                    Ok(m) => m,
+                    Err(_) => continue,
+                };
                 // 如果是目录，则递归遍历
                 let _size = if treat_as_file(&entry_pathbuf) {
-                    pure_walk(&entry_pathbuf)?
+                    pure_walk(&entry_pathbuf).unwrap_or(0)
                 } else {
                     // common file or directory
-                    if entry_pathbuf.is_dir() {
+                    if metadata.is_dir() {
                         stack.push(entry_pathbuf.to_owned());
                     }
-                    fs::metadata(&entry_pathbuf)?.len()
+                    metadata.len()
                 };
-                let _script = if treat_as_script(&entry_pathbuf) {
-                    1
-                } else {
-                    0
-                };
-                storage.accumulate(&entry_pathbuf, _size, _script);
+                let (_script, _categories) = classify(classifier, &entry_pathbuf);
+                storage.accumulate(&entry_pathbuf, _size, _script, &_categories);
             }
         } else {
             println!("Failed to open directory: {:?}", &__path);
@@ -165,7 +193,7 @@ type RowLockStorage = DashMap<String, NodeRecord>;
 struct _RowLockStorageHelper;
 
 impl _RowLockStorageHelper {
-    pub fn accumulate(map: Arc<RowLockStorage>, path: &PathBuf, add_size: u64, add_scripts: u64) {
+    pub fn accumulate(map: Arc<RowLockStorage>, path: &PathBuf, add_size: u64, add_scripts: u64, add_categories: &Categories) {
         // 先尝试添加自己
         // println!("accumulate: {:?}", path);
         let path_string = path.to_str().unwrap().to_string();
@@ -179,9 +207,10 @@ impl _RowLockStorageHelper {
                 e.size += add_size;
                 e.last_modified = last_modified;
                 e.script_count += add_scripts;
+                merge(&mut e.categories, add_categories);
                 e.is_allowed = true;
             })
-            .or_insert(NodeRecord::with(add_size, last_modified, add_scripts, true));
+            .or_insert(NodeRecord::with(add_size, last_modified, add_scripts, true).categories(add_categories.clone()));
         // println!("accumulate end: {:?}", path);
         // recursive call parent to accumulate the size
         let parent = match path.parent() {
@@ -199,7 +228,7 @@ impl _RowLockStorageHelper {
             }
         }
 
-        _RowLockStorageHelper::accumulate(map, &parent.to_path_buf(), add_size, add_scripts);
+        _RowLockStorageHelper::accumulate(map, &parent.to_path_buf(), add_size, add_scripts, add_categories);
     }
     pub fn load_cache(map: &mut RowLockStorage, cache: &HashMap<String, NodeRecord>) {
         // let mut guard = map.write().unwrap();
@@ -223,6 +252,7 @@ impl _RowLockStorageHelper {
                 e.size = 0;
                 e.last_modified = last_modified;
                 e.script_count = 0;
+                e.categories.clear();
                 e.is_allowed = false;
             })
             .or_insert(NodeRecord::with(0, last_modified, 0, false));
@@ -233,12 +263,15 @@ impl _RowLockStorageHelper {
         Storage {
             path_map: _map,
             updating: false,
+            index: ExecutableIndex::default(),
         }
     }
 }
 
 fn multi_thread_walk(
     cache: Option<&HashMap<String, NodeRecord>>,
+    filter: &ScanFilter,
+    classifier: &dyn FileClassifier,
 ) -> Result<Storage, Box<dyn std::error::Error>> {
     let cache = match cache {
         Some(c) => c,
@@ -247,10 +280,7 @@ fn multi_thread_walk(
     let cache = cache.clone();
 
     const THREADS: usize = 8;
-    #[cfg(not(debug_assertions))]
-    let roots: Vec<PathBuf> = get_drives();
-    #[cfg(debug_assertions)]
-    let roots: Vec<PathBuf> = vec![TEST_DRIVE.into()];
+    let roots: Vec<PathBuf> = filter.starts();
     let q_walk = SegQueue::new();
     for root in roots {
         q_walk.push(root);
@@ -307,6 +337,7 @@ fn multi_thread_walk(
                             &__path,
                             v.size,
                             v.script_count,
+                            &v.categories,
                         );
                         continue;
                     }
@@ -315,8 +346,8 @@ fn multi_thread_walk(
                         let entries = entries
                             .filter(|e| e.is_ok())
                             .map(|e| e.unwrap())
-                            .filter(|e| !treat_as_ignore(&e.path()))
-                            .map(|e| e.path());
+                            .map(|e| e.path())
+                            .filter(|p| filter.allows(p));
                         for entry_pathbuf in entries {
                             // println!("Thread {} traverse: {:?}", t_index, &entry_pathbuf);
                             // cache hitted
@@ -328,33 +359,36 @@ fn multi_thread_walk(
                                     &entry_pathbuf,
                                     v.size,
                                     v.script_count,
+                                    &v.categories,
                                 );
                                 continue;
                             }
                             // println!("Thread {} no cache hitted: {:?}", t_index, &entry_pathbuf);
                             // no cache hitted
+                            // 不跟随符号链接，悬空的链接和 `X11 -> .` 这样的环都会出错
+                            let metadata = match fs::symlink_metadata(&entry_pathbuf) {
+                                Ok(m) => m,
+                                Err(_) => continue,
+                            };
                             let _size = if treat_as_file(&entry_pathbuf) {
-                                pure_walk(&entry_pathbuf).unwrap()
+                                pure_walk(&entry_pathbuf).unwrap_or(0)
                             } else {
                                 // common file or directory
-                                if entry_pathbuf.is_dir() {
+                                if metadata.is_dir() {
                                     let (lock, cvar) = &*_q_walk;
                                     lock.lock().unwrap().push(entry_pathbuf.to_owned());
                                     // _q_walk.push(entry_pathbuf.to_owned());
                                 }
-                                fs::metadata(&entry_pathbuf).unwrap().len()
-                            };
-                            let _script = if treat_as_script(&entry_pathbuf) {
-                                1
-                            } else {
-                                0
+                                metadata.len()
                             };
+                            let (_script, _categories) = classify(classifier, &entry_pathbuf);
                             // println!("Thread {} accumulate: {:?}", t_index, &entry_pathbuf);
                             _RowLockStorageHelper::accumulate(
                                 Arc::clone(&_row_lock_storage),
                                 &entry_pathbuf,
                                 _size,
                                 _script,
+                                &_categories,
                             );
                             // println!("Thread {} accumulate end: {:?}", t_index, &entry_pathbuf);
                         }
@@ -376,3 +410,19 @@ fn multi_thread_walk(
     }
     unreachable!()
 }
+
+#[cfg(not(windows))]
+#[test]
+fn test_walk_skips_broken_links() {
+    use crate::testutil::TempDir;
+    use std::os::unix::fs::symlink;
+
+    let root = TempDir::new("walk_links");
+    fs::write(root.join("file"), "1234").unwrap();
+    symlink(root.join("missing"), root.join("dangling")).unwrap();
+    symlink(".", root.join("X11")).unwrap();
+    let filter = ScanFilter::new(&[ScanRoot::new(root.to_str())]);
+    let s = walk_scan(None, &filter, &Classifiers::default()).unwrap();
+    assert!(s.path_map.contains_key(root.join("file").to_str().unwrap()));
+    assert!(!s.path_map.keys().any(|k| k.contains("X11/")));
+}
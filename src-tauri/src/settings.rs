@@ -0,0 +1,61 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::scanner::{default_roots, Classifiers, ExtensionClassifier, FileClassifier, IgnoreRules, ScanRoot};
+
+const SETTINGS_FILE: &str = "settings.json";
+
+/// settings of the backend, the frontend keeps its own in localStorage
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Settings {
+    /// drive letters or mount points if empty
+    #[serde(default)]
+    pub scan_roots: Vec<ScanRoot>,
+    /// gitignore-like patterns after the built-in ones, `!` re-includes
+    #[serde(default)]
+    pub ignore: Vec<String>,
+    /// custom file categories by extension, e.g. {"jar": [".jar", ".war"]}
+    #[serde(default)]
+    pub categories: BTreeMap<String, Vec<String>>,
+}
+
+impl Settings {
+    /// defaults if the file does not exist or is broken
+    pub fn load(data_dir: &Path) -> Self {
+        fs::read_to_string(data_dir.join(SETTINGS_FILE))
+            .ok()
+            .and_then(|s| serde_json::from_str(&s).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self, data_dir: &Path) -> Result<(), String> {
+        fs::create_dir_all(data_dir).map_err(|e| e.to_string())?;
+        let content = serde_json::to_string_pretty(self).unwrap();
+        fs::write(data_dir.join(SETTINGS_FILE), content).map_err(|e| e.to_string())
+    }
+
+    pub fn scan_roots(&self) -> Vec<ScanRoot> {
+        if self.scan_roots.is_empty() {
+            return default_roots();
+        }
+        self.scan_roots.clone()
+    }
+
+    pub fn ignore_rules(&self) -> IgnoreRules {
+        IgnoreRules::preset(&self.ignore)
+    }
+
+    /// the custom categories before the built-in ones
+    pub fn classifier(&self) -> Arc<dyn FileClassifier> {
+        let classifiers = self
+            .categories
+            .iter()
+            .fold(Classifiers::default(), |c, (name, extensions)| {
+                c.register(Arc::new(ExtensionClassifier::new(name, extensions)))
+            });
+        Arc::new(classifiers)
+    }
+}
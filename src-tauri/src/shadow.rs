@@ -0,0 +1,175 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::scanner::Storage;
+use crate::task::EnvHashMap;
+use crate::expand::expand;
+use crate::which::{is_executable, path_entries, pathext, Candidate};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Shadowing {
+    /// command name, lower case on Windows
+    pub name: String,
+    pub winner: Candidate,
+    pub shadowed: Vec<Candidate>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ShadowChange {
+    pub name: String,
+    pub committed: Option<Candidate>,
+    pub pending: Option<Candidate>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ShadowReport {
+    /// commands found in more than one PATH entry of the pending env
+    pub shadowings: Vec<Shadowing>,
+    /// commands which resolve differently once the pending env is flushed
+    pub changes: Vec<ShadowChange>,
+}
+
+impl ShadowReport {
+    pub fn new(pending: &EnvHashMap, committed: &EnvHashMap, storage: Option<&Storage>) -> Self {
+        let pending = candidates(pending, storage);
+        let committed = candidates(committed, storage);
+
+        let mut shadowings: Vec<Shadowing> = pending
+            .iter()
+            .filter(|(_, c)| c.len() > 1)
+            .map(|(name, c)| Shadowing {
+                name: name.clone(),
+                winner: c[0].clone(),
+                shadowed: c[1..].to_vec(),
+            })
+            .collect();
+        shadowings.sort_by(|a, b| a.name.cmp(&b.name));
+
+        // newly reachable commands are not a change of resolution
+        let mut changes: Vec<ShadowChange> = committed
+            .iter()
+            .filter_map(|(name, c)| {
+                let before = c.first();
+                let after = pending.get(name).and_then(|c| c.first());
+                if before.map(|c| &c.file) == after.map(|c| &c.file) {
+                    return None;
+                }
+                Some(ShadowChange {
+                    name: name.clone(),
+                    committed: before.cloned(),
+                    pending: after.cloned(),
+                })
+            })
+            .collect();
+        changes.sort_by(|a, b| a.name.cmp(&b.name));
+
+        Self { shadowings, changes }
+    }
+}
+
+/// command name -> candidates in PATH order
+fn candidates(env: &EnvHashMap, storage: Option<&Storage>) -> HashMap<String, Vec<Candidate>> {
+    let pathext = pathext(env);
+    let entries: Vec<(usize, String, PathBuf)> = path_entries(env)
+        .into_iter()
+        .enumerate()
+        .filter_map(|(i, entry)| {
+            let dir = expand(&entry, env)?;
+            let dir = PathBuf::from(dir.trim());
+            Some((i, entry, dir))
+        })
+        .collect();
+    let dirs: Vec<PathBuf> = entries.iter().map(|(_, _, d)| d.clone()).collect();
+    let scanned = match storage {
+        Some(s) => s.scripts_in(&dirs),
+        None => HashMap::new(),
+    };
+
+    let mut map: HashMap<String, Vec<Candidate>> = HashMap::new();
+    for (index, entry, dir) in entries.iter() {
+        let files = match scanned.get(dir) {
+            Some(files) => files.clone(),
+            None => list_dir(dir),
+        };
+        let mut names: Vec<(String, PathBuf)> = files
+            .into_iter()
+            .filter_map(|f| command_name(&f, &pathext).map(|n| (n, f)))
+            .collect();
+        // python.exe wins python.bat within one directory
+        names.sort_by_key(|(_, f)| ext_rank(f, &pathext));
+        for (name, file) in names {
+            let list = map.entry(name).or_default();
+            if list.last().map_or(false, |c| c.index == *index) {
+                continue;
+            }
+            list.push(Candidate {
+                entry: entry.clone(),
+                index: *index,
+                file: file.to_str().unwrap().to_string(),
+            });
+        }
+    }
+    map
+}
+
+fn list_dir(dir: &Path) -> Vec<PathBuf> {
+    match fs::read_dir(dir) {
+        Ok(entries) => entries.filter_map(|e| e.ok()).map(|e| e.path()).collect(),
+        Err(_) => vec![],
+    }
+}
+
+/// the name typed in a shell to run `file`, None if it is not a command
+#[cfg(windows)]
+fn command_name(file: &Path, pathext: &[String]) -> Option<String> {
+    let ext = format!(".{}", file.extension()?.to_str()?.to_lowercase());
+    if !pathext.contains(&ext) || !is_executable(file) {
+        return None;
+    }
+    Some(file.file_stem()?.to_str()?.to_lowercase())
+}
+
+#[cfg(not(windows))]
+fn command_name(file: &Path, _pathext: &[String]) -> Option<String> {
+    if !is_executable(file) {
+        return None;
+    }
+    Some(file.file_name()?.to_str()?.to_string())
+}
+
+fn ext_rank(file: &Path, pathext: &[String]) -> usize {
+    let ext = match file.extension().and_then(|e| e.to_str()) {
+        Some(e) => format!(".{}", e.to_lowercase()),
+        None => return 0,
+    };
+    pathext.iter().position(|e| e == &ext).unwrap_or(pathext.len())
+}
+
+#[cfg(not(windows))]
+#[test]
+fn test_shadow_report_changes() {
+    use std::os::unix::fs::PermissionsExt;
+    let root = crate::testutil::TempDir::new("shadow");
+    for d in ["a", "b"] {
+        fs::create_dir_all(root.join(d)).unwrap();
+        let f = root.join(d).join("git");
+        fs::write(&f, "#!/bin/sh\n").unwrap();
+        fs::set_permissions(&f, fs::Permissions::from_mode(0o755)).unwrap();
+    }
+    let entries: Vec<String> = ["a", "b"]
+        .iter()
+        .map(|d| root.join(d).to_str().unwrap().to_string())
+        .collect();
+    let mut committed = EnvHashMap::new();
+    committed.insert("PATH".to_string(), entries);
+    let mut pending = committed.clone();
+    pending.get_mut("PATH").unwrap().reverse();
+
+    let report = ShadowReport::new(&pending, &committed, None);
+    assert_eq!(report.shadowings.len(), 1);
+    assert_eq!(report.shadowings[0].shadowed.len(), 1);
+    assert_eq!(report.changes.len(), 1);
+    assert_eq!(report.changes[0].name, "git");
+}
@@ -0,0 +1,201 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::expand::expand;
+use crate::lint::normalize;
+use crate::locate::add_to_path;
+use crate::task::{EnvHashMap, TaskLogData};
+use crate::which::path_entries;
+
+const SHIM_DIR: &str = "shims";
+/// the line recording the target, shims without it are not ours
+#[cfg(windows)]
+const TARGET_MARKER: &str = "rem environmentor shim: ";
+#[cfg(not(windows))]
+const TARGET_MARKER: &str = "# environmentor shim: ";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Shim {
+    /// the command typed in a shell
+    pub name: String,
+    pub file: String,
+    pub target: String,
+    /// the target has been moved or uninstalled
+    pub broken: bool,
+}
+
+/// one directory on PATH with a forwarding script per executable,
+/// instead of adding a whole app directory for one binary
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ShimManager {
+    dir: PathBuf,
+}
+
+impl ShimManager {
+    pub fn new(data_dir: &Path) -> Self {
+        Self {
+            dir: data_dir.join(SHIM_DIR),
+        }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn list(&self) -> Vec<Shim> {
+        let mut shims: Vec<Shim> = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries.filter_map(|e| e.ok()).filter_map(|e| read_shim(&e.path())).collect(),
+            Err(_) => vec![],
+        };
+        shims.sort_by(|a, b| a.name.cmp(&b.name));
+        shims
+    }
+
+    pub fn get(&self, name: &str) -> Option<Shim> {
+        read_shim(&self._file(name))
+    }
+
+    /// shims whose targets have disappeared
+    pub fn broken(&self) -> Vec<Shim> {
+        self.list().into_iter().filter(|s| s.broken).collect()
+    }
+
+    pub fn create(&self, name: &str, target: &str) -> Result<Shim, String> {
+        if self._file(name).exists() {
+            return Err(format!("shim '{}' already exists", name));
+        }
+        self._write(name, target)
+    }
+
+    /// point an existing shim at another target, e.g. after upgrading the app
+    pub fn update(&self, name: &str, target: &str) -> Result<Shim, String> {
+        if self.get(name).is_none() {
+            return Err(format!("shim '{}' not found", name));
+        }
+        self._write(name, target)
+    }
+
+    pub fn remove(&self, name: &str) -> Result<(), String> {
+        if self.get(name).is_none() {
+            return Err(format!("shim '{}' not found", name));
+        }
+        fs::remove_file(self._file(name)).map_err(|e| e.to_string())
+    }
+
+    /// appends the shim directory to the user PATH, None if it is on PATH already
+    pub fn path_task(&self, user: &EnvHashMap, effective: &EnvHashMap) -> Option<TaskLogData> {
+        let dir = self.dir.to_str()?;
+        let found = path_entries(effective)
+            .iter()
+            .filter_map(|e| expand(e, effective))
+            .any(|e| normalize(&e) == normalize(dir));
+        if found {
+            None
+        } else {
+            Some(add_to_path(user, dir))
+        }
+    }
+
+    fn _write(&self, name: &str, target: &str) -> Result<Shim, String> {
+        // 文件名里不能出现的字符，`<>|` 等在 .cmd 里还会被当成重定向
+        if name.is_empty() || name.contains(|c: char| "/\\:<>|\"*?".contains(c) || c.is_control()) {
+            return Err(format!("'{}' is not a valid command name", name));
+        }
+        if !Path::new(target).is_file() {
+            return Err(format!("'{}' is not a file", target));
+        }
+        fs::create_dir_all(&self.dir).map_err(|e| e.to_string())?;
+        let file = self._file(name);
+        fs::write(&file, script(target)).map_err(|e| e.to_string())?;
+        #[cfg(not(windows))]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&file, fs::Permissions::from_mode(0o755)).map_err(|e| e.to_string())?;
+        }
+        read_shim(&file).ok_or(format!("failed to write shim '{}'", name))
+    }
+
+    fn _file(&self, name: &str) -> PathBuf {
+        if cfg!(windows) {
+            self.dir.join(format!("{}.cmd", name))
+        } else {
+            self.dir.join(name)
+        }
+    }
+}
+
+/// the command name a shim for `target` gets by default, e.g. "ffmpeg" for "ffmpeg.exe"
+pub fn default_name(target: &str) -> Option<String> {
+    let path = Path::new(target);
+    let name = if cfg!(windows) { path.file_stem()? } else { path.file_name()? };
+    Some(name.to_str()?.to_string())
+}
+
+// 参数和退出码原样传递
+#[cfg(windows)]
+fn script(target: &str) -> String {
+    // .cmd 里 % 需要写成 %%
+    let escaped = target.replace('%', "%%");
+    format!(
+        "@echo off\r\n{}{}\r\n\"{}\" %*\r\nexit /b %ERRORLEVEL%\r\n",
+        TARGET_MARKER, target, escaped
+    )
+}
+
+#[cfg(not(windows))]
+fn script(target: &str) -> String {
+    let quoted = format!("'{}'", target.replace('\'', "'\\''"));
+    format!("#!/bin/sh\n{}{}\nexec {} \"$@\"\n", TARGET_MARKER, target, quoted)
+}
+
+fn read_shim(file: &Path) -> Option<Shim> {
+    let content = fs::read_to_string(file).ok()?;
+    let target = content.lines().find_map(|l| l.strip_prefix(TARGET_MARKER))?.to_string();
+    let name = if cfg!(windows) {
+        file.extension().filter(|ext| ext.eq_ignore_ascii_case("cmd"))?;
+        file.file_stem()?
+    } else {
+        file.file_name()?
+    };
+    Some(Shim {
+        name: name.to_str()?.to_string(),
+        file: file.to_str()?.to_string(),
+        broken: !Path::new(&target).is_file(),
+        target,
+    })
+}
+
+#[cfg(not(windows))]
+#[test]
+fn test_shim_forwarding() {
+    use crate::testutil::{script, TempDir};
+    use std::process::Command;
+
+    let root = TempDir::new("shim");
+    let target = root.join("it's app").join("tool");
+    script(&target, "#!/bin/sh\necho \"$#:$1\"\nexit 3\n");
+    let target = target.to_str().unwrap();
+
+    let shims = ShimManager::new(root.path());
+    let shim = shims.create("tool", target).unwrap();
+    assert!(shims.create("tool", target).is_err());
+    for name in ["a>b", "a|b", "a\"b", "a*", "a?"] {
+        assert!(shims.create(name, target).is_err());
+    }
+    let output = Command::new(&shim.file).arg("a b").arg("c").output().unwrap();
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "2:a b\n");
+    assert_eq!(output.status.code(), Some(3));
+
+    let mut env = EnvHashMap::new();
+    env.insert("PATH".to_string(), vec!["/usr/bin".to_string()]);
+    assert!(shims.path_task(&env, &env).is_some());
+    env.get_mut("PATH").unwrap().push(shims.dir().to_str().unwrap().to_string());
+    assert!(shims.path_task(&env, &env).is_none());
+
+    assert!(shims.broken().is_empty());
+    fs::remove_file(target).unwrap();
+    assert_eq!(shims.broken()[0].name, "tool");
+    shims.remove("tool").unwrap();
+    assert!(shims.list().is_empty());
+}
@@ -0,0 +1,200 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::path::PathBuf;
+
+use crate::backend::Scope;
+use crate::lint::is_path_variable;
+use crate::scanner::Storage;
+use crate::shadow::{ShadowReport, Shadowing};
+use crate::suggest::{available_commands, command_name};
+use crate::task::{AddVariableLog, EnvHashMap, GroupLog, SetVariableLog, TaskLogData};
+use crate::which::{pathext, resolve};
+
+/// PATH entries to add so that every wanted command resolves
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PathPlan {
+    /// appended to the user PATH in this order
+    pub dirs: Vec<String>,
+    /// command -> the directory added for it
+    pub covered: BTreeMap<String, String>,
+    /// commands the current PATH resolves already
+    pub available: Vec<String>,
+    /// commands not found in the scanned directories
+    pub missing: Vec<String>,
+    /// shadowings which only exist after the change
+    pub shadowings: Vec<Shadowing>,
+    /// one line per new shadowing, shown to the user
+    pub explanations: Vec<String>,
+}
+
+/// the fewest scanned directories covering `commands`, picked greedily by how many
+/// commands they add; ties go to the directory bringing in fewer clashing names
+pub fn solve_path(commands: &[String], storage: &Storage, effective: &EnvHashMap) -> PathPlan {
+    let pathext = pathext(effective);
+    let mut plan = PathPlan::default();
+    let mut wanted: Vec<String> = vec![];
+    for command in commands.iter().map(|c| c.trim()).filter(|c| !c.is_empty()) {
+        if plan.available.iter().chain(wanted.iter()).any(|c| c == command) {
+            continue;
+        }
+        if resolve(command, effective).winner.is_some() {
+            plan.available.push(command.to_string());
+        } else {
+            wanted.push(command.to_string());
+        }
+    }
+
+    // directory -> wanted commands it provides
+    let mut providers: BTreeMap<PathBuf, HashSet<String>> = BTreeMap::new();
+    for command in wanted.iter() {
+        let dirs: Vec<PathBuf> = storage
+            .find_executable(command, &pathext)
+            .iter()
+            .filter_map(|f| f.parent().map(|p| p.to_path_buf()))
+            .collect();
+        if dirs.is_empty() {
+            plan.missing.push(command.clone());
+        }
+        for dir in dirs {
+            providers.entry(dir).or_default().insert(command.clone());
+        }
+    }
+    let by_dir = storage.executables_by_dir();
+    let on_path = available_commands(effective);
+    // dir -> (its command names, how many of them PATH provides already), computed once
+    let names: BTreeMap<&PathBuf, (HashSet<String>, usize)> = providers
+        .keys()
+        .map(|dir| {
+            let names: HashSet<String> = by_dir
+                .get(dir)
+                .map(|files| files.iter().filter_map(|f| command_name(f, &pathext)).collect())
+                .unwrap_or_default();
+            let clashes = names.iter().filter(|n| on_path.contains(*n)).count();
+            (dir, (names, clashes))
+        })
+        .collect();
+
+    let mut uncovered: HashSet<String> = providers.values().flatten().cloned().collect();
+    // 已选目录里新增的命令，再出现一次就是遮蔽
+    let mut reachable: HashSet<String> = HashSet::new();
+    while !uncovered.is_empty() {
+        let best = providers
+            .iter()
+            .map(|(dir, provides)| {
+                let gain = provides.intersection(&uncovered).count();
+                let (names, on_path_clashes) = &names[dir];
+                let clashes = on_path_clashes + names.iter().filter(|n| reachable.contains(*n)).count();
+                (dir, gain, clashes)
+            })
+            .filter(|(_, gain, _)| *gain > 0)
+            .min_by(|a, b| b.1.cmp(&a.1).then(a.2.cmp(&b.2)).then(a.0.cmp(b.0)));
+        let (dir, _, _) = match best {
+            Some(b) => b,
+            None => break,
+        };
+        let dir_str = match dir.to_str() {
+            Some(d) => d.to_string(),
+            None => break,
+        };
+        for command in providers[dir].iter().filter(|c| uncovered.contains(*c)) {
+            plan.covered.insert(command.clone(), dir_str.clone());
+        }
+        uncovered.retain(|c| !providers[dir].contains(c));
+        reachable.extend(names[dir].0.iter().filter(|n| !on_path.contains(*n)).cloned());
+        plan.dirs.push(dir_str);
+    }
+    if plan.dirs.is_empty() {
+        return plan;
+    }
+
+    let mut pending = effective.clone();
+    let key = pending.keys().find(|k| is_path_variable(k)).cloned().unwrap_or("PATH".to_string());
+    pending.entry(key).or_default().extend(plan.dirs.iter().cloned());
+    let added: HashSet<&String> = plan.dirs.iter().collect();
+    plan.shadowings = ShadowReport::new(&pending, effective, Some(storage))
+        .shadowings
+        .into_iter()
+        .filter(|s| added.contains(&s.winner.entry) || s.shadowed.iter().any(|c| added.contains(&c.entry)))
+        .collect();
+    plan.explanations = plan
+        .shadowings
+        .iter()
+        .map(|s| {
+            let hidden: Vec<&str> = s.shadowed.iter().map(|c| c.file.as_str()).collect();
+            format!("'{}' will run {}, hiding {}", s.name, s.winner.file, hidden.join(", "))
+        })
+        .collect();
+    plan
+}
+
+/// appends `dirs` to the user PATH as one SetVariable, reviewed before flush.
+/// built from `user` when the plan is applied, since PATH may have been edited after solving
+pub fn set_path(user: &EnvHashMap, dirs: &[String]) -> Option<TaskLogData> {
+    let scope = Scope::User;
+    let (key, old_values) = match user.iter().find(|(k, _)| is_path_variable(k)) {
+        Some((k, v)) => (k.clone(), v.clone()),
+        None => ("PATH".to_string(), vec![]),
+    };
+    let mut new_values = old_values.clone();
+    for dir in dirs {
+        if !new_values.contains(dir) {
+            new_values.push(dir.clone());
+        }
+    }
+    if new_values == old_values {
+        return None;
+    }
+    let set = TaskLogData::SetVariable(SetVariableLog {
+        variable: key.clone(),
+        old_values,
+        new_values,
+        scope,
+    });
+    if user.contains_key(&key) {
+        return Some(set);
+    }
+    Some(TaskLogData::Group(GroupLog {
+        name: format!("add {} entries to PATH", dirs.len()),
+        tasks: vec![TaskLogData::AddVariable(AddVariableLog { variable: key, scope }), set],
+    }))
+}
+
+#[cfg(not(windows))]
+#[test]
+fn test_solve_path() {
+    use crate::scanner::{ScanRoot, StorageUpdater};
+    use crate::task::ConsumeTask;
+    use crate::testutil::{executable, TempDir};
+    use std::fs;
+
+    let root = TempDir::new("solver");
+    executable(&root.join("onpath").join("git"));
+    // toolbox 同时提供 ffmpeg 和 ffprobe，应该只选它一个
+    executable(&root.join("ff").join("ffmpeg"));
+    executable(&root.join("toolbox").join("ffmpeg"));
+    executable(&root.join("toolbox").join("ffprobe"));
+    executable(&root.join("toolbox").join("git"));
+    let root_str = root.to_str();
+    let storage = StorageUpdater::from(Storage::default())
+        .roots(&[ScanRoot::new(root_str)])
+        .consume_subtree(root_str);
+
+    let mut env = EnvHashMap::new();
+    env.insert("PATH".to_string(), vec![root.join("onpath").to_str().unwrap().to_string()]);
+    let commands: Vec<String> = ["git", "ffmpeg", "ffprobe", "nope"].iter().map(|s| s.to_string()).collect();
+    let plan = solve_path(&commands, &storage, &env);
+    let toolbox = root.join("toolbox").to_str().unwrap().to_string();
+    assert_eq!(plan.dirs, vec![toolbox.clone()]);
+    assert_eq!(plan.available, vec!["git"]);
+    assert_eq!(plan.missing, vec!["nope"]);
+    assert_eq!(plan.covered["ffprobe"], toolbox);
+    // toolbox/git 被 onpath/git 遮蔽
+    assert_eq!(plan.shadowings.len(), 1);
+    assert_eq!(plan.shadowings[0].name, "git");
+
+    let mut after = env.clone();
+    set_path(&env, &plan.dirs).unwrap().forward(&mut after);
+    assert_eq!(after["PATH"].last(), Some(&toolbox));
+    assert!(solve_path(&commands, &storage, &after).dirs.is_empty());
+    assert!(set_path(&after, &plan.dirs).is_none());
+}
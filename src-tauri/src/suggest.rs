@@ -0,0 +1,199 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+
+use crate::expand::expand;
+use crate::lint::normalize;
+use crate::locate::{add_to_path, prepend_to_path};
+#[cfg(not(windows))]
+use crate::scanner::is_library;
+use crate::scanner::Storage;
+use crate::task::{EnvHashMap, TaskLogData};
+#[cfg(not(windows))]
+use crate::which::is_executable;
+use crate::which::{path_entries, pathext};
+
+pub const MAX_SUGGESTIONS: usize = 20;
+
+/// directory names which usually hold commands only
+const BIN_NAMES: [&str; 6] = ["bin", "sbin", "cmd", "scripts", "tools", "shims"];
+/// siblings of `bin` in a prefix layout, e.g. `jdk-17/{bin,lib,include}`
+const LAYOUT_SIBLINGS: [&str; 4] = ["lib", "include", "share", "libexec"];
+/// commands users go looking for
+const KNOWN_TOOLS: [&str; 20] = [
+    "java", "javac", "node", "npm", "python", "pip", "go", "cargo", "rustc", "git", "ffmpeg", "gcc", "clang",
+    "cmake", "dotnet", "php", "ruby", "perl", "mvn", "adb",
+];
+
+const DENSITY_WEIGHT: f64 = 2.0;
+const BIN_NAME_BONUS: f64 = 1.0;
+const LAYOUT_BONUS: f64 = 1.0;
+const KNOWN_TOOL_BONUS: f64 = 1.0;
+const MAX_KNOWN_TOOLS: usize = 3;
+const NEW_COMMAND_WEIGHT: f64 = 1.0;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Suggestion {
+    pub dir: String,
+    pub score: f64,
+    pub executables: usize,
+    /// commands which are not available from PATH yet
+    pub new_commands: Vec<String>,
+    /// why it is suggested, shown to the user
+    pub reasons: Vec<String>,
+    pub append: TaskLogData,
+    pub prepend: TaskLogData,
+}
+
+/// scanned directories with executables which are not on PATH, best first.
+/// directories adding no new command are left out
+pub fn suggest_path_entries(storage: &Storage, effective: &EnvHashMap, user: &EnvHashMap, limit: usize) -> Vec<Suggestion> {
+    let pathext = pathext(effective);
+    let dirs_on_path: Vec<String> = path_entries(effective)
+        .iter()
+        .filter_map(|e| expand(e, effective))
+        .map(|e| e.trim().to_string())
+        .collect();
+    let normalized: HashSet<String> = dirs_on_path.iter().map(|d| normalize(d)).collect();
+    let available = available_commands(effective);
+    let entry_counts = storage.entry_counts();
+
+    let mut suggestions: Vec<Suggestion> = storage
+        .executables_by_dir()
+        .into_iter()
+        .filter(|(dir, _)| dir.to_str().map_or(false, |d| !normalized.contains(&normalize(d))))
+        .filter_map(|(dir, files)| {
+            let mut commands: Vec<String> = files.iter().filter_map(|f| command_name(f, &pathext)).collect();
+            commands.sort();
+            commands.dedup();
+            let new_commands: Vec<String> = commands.iter().filter(|c| !available.contains(*c)).cloned().collect();
+            if new_commands.is_empty() {
+                return None;
+            }
+            let mut score = 0.0;
+            let mut reasons = vec![];
+
+            let entries = entry_counts.get(&dir).copied().unwrap_or(files.len()).max(files.len());
+            let density = files.len() as f64 / entries as f64;
+            score += density * DENSITY_WEIGHT;
+            reasons.push(format!("{} of {} entries are executables", files.len(), entries));
+
+            let name = dir.file_name().and_then(|n| n.to_str()).unwrap_or_default().to_lowercase();
+            if BIN_NAMES.contains(&name.as_str()) {
+                score += BIN_NAME_BONUS;
+                reasons.push(format!("named '{}'", name));
+            }
+            let parent = dir.parent();
+            let siblings: Vec<&str> = LAYOUT_SIBLINGS
+                .iter()
+                .filter(|s| parent.map_or(false, |p| p.join(s).to_str().map_or(false, |p| storage.get(p).is_some())))
+                .copied()
+                .collect();
+            if !siblings.is_empty() {
+                score += LAYOUT_BONUS;
+                reasons.push(format!("toolchain layout next to {}", siblings.join(", ")));
+            }
+            let known: Vec<&str> = KNOWN_TOOLS
+                .iter()
+                .filter(|t| new_commands.iter().any(|c| c == *t))
+                .copied()
+                .collect();
+            if !known.is_empty() {
+                score += KNOWN_TOOL_BONUS * known.len().min(MAX_KNOWN_TOOLS) as f64;
+                reasons.push(format!("provides {}", known.join(", ")));
+            }
+            score += NEW_COMMAND_WEIGHT * (1.0 + new_commands.len() as f64).ln();
+            reasons.push(format!("{} of {} commands are not on PATH yet", new_commands.len(), commands.len()));
+
+            let dir = dir.to_str()?.to_string();
+            Some(Suggestion {
+                append: add_to_path(user, &dir),
+                prepend: prepend_to_path(user, &dir),
+                dir,
+                score,
+                executables: files.len(),
+                new_commands,
+                reasons,
+            })
+        })
+        .collect();
+    suggestions.sort_by(|a, b| b.score.total_cmp(&a.score).then(a.dir.cmp(&b.dir)));
+    suggestions.truncate(limit);
+    suggestions
+}
+
+/// command names the PATH of `env` provides, one directory listing per entry
+pub fn available_commands(env: &EnvHashMap) -> HashSet<String> {
+    let pathext = pathext(env);
+    path_entries(env)
+        .iter()
+        .filter_map(|e| expand(e, env))
+        .flat_map(|d| list_dir(Path::new(d.trim())))
+        .filter_map(|f| command_name(&f, &pathext))
+        .collect()
+}
+
+fn list_dir(dir: &Path) -> Vec<std::path::PathBuf> {
+    match fs::read_dir(dir) {
+        Ok(entries) => entries.filter_map(|e| e.ok()).map(|e| e.path()).collect(),
+        Err(_) => vec![],
+    }
+}
+
+/// the name typed in a shell, the file is not checked
+#[cfg(windows)]
+pub fn command_name(file: &Path, pathext: &[String]) -> Option<String> {
+    let ext = format!(".{}", file.extension()?.to_str()?.to_lowercase());
+    if !pathext.contains(&ext) {
+        return None;
+    }
+    Some(file.file_stem()?.to_str()?.to_lowercase())
+}
+
+/// only files with the execute bit, `libc.so.6` is not a command even if it has one
+#[cfg(not(windows))]
+pub fn command_name(file: &Path, _pathext: &[String]) -> Option<String> {
+    if is_library(file) || !is_executable(file) {
+        return None;
+    }
+    Some(file.file_name()?.to_str()?.to_string())
+}
+
+#[cfg(not(windows))]
+#[test]
+fn test_suggest_ranking() {
+    use crate::scanner::{ScanRoot, StorageUpdater};
+    use crate::testutil::{executable, TempDir};
+
+    let root = TempDir::new("suggest");
+    executable(&root.join("jdk").join("bin").join("java"));
+    executable(&root.join("jdk").join("bin").join("javac"));
+    fs::create_dir_all(root.join("jdk").join("lib")).unwrap();
+    fs::write(root.join("jdk").join("lib").join("rt.jar"), "").unwrap();
+    executable(&root.join("game").join("launcher"));
+    for i in 0..5 {
+        fs::write(root.join("game").join(format!("level{}.dat", i)), "").unwrap();
+    }
+    executable(&root.join("onpath").join("already"));
+    // 有执行位的共享库不算命令
+    executable(&root.join("lib").join("libc.so.6"));
+    fs::write(root.join("lib").join("README"), "").unwrap();
+    let root_str = root.to_str();
+    let storage = StorageUpdater::from(Storage::default())
+        .roots(&[ScanRoot::new(root_str)])
+        .consume_subtree(root_str);
+
+    let mut env = EnvHashMap::new();
+    env.insert("PATH".to_string(), vec![root.join("onpath").to_str().unwrap().to_string()]);
+    let suggestions = suggest_path_entries(&storage, &env, &env, MAX_SUGGESTIONS);
+    let dirs: Vec<&str> = suggestions.iter().map(|s| s.dir.as_str()).collect();
+    assert_eq!(
+        dirs,
+        vec![
+            root.join("jdk").join("bin").to_str().unwrap(),
+            root.join("game").to_str().unwrap()
+        ]
+    );
+    assert_eq!(suggestions[0].new_commands, vec!["java", "javac"]);
+}
@@ -1,51 +1,173 @@
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use std::os::windows::process::CommandExt as _;
-use std::process::Command;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
 use std::time;
 use std::u8;
 
-type EnvHashMap = HashMap<String, Vec<String>>;
+use crate::backend::{
+    get_environment_variables, get_stored_environment_variables, set_environment_variables, BackendError, Scope,
+};
 
+pub type EnvHashMap = HashMap<String, Vec<String>>;
+
+
+/// scopes written back on flush, machine first since it is the one which may be rejected
+const WRITABLE_SCOPES: [Scope; 2] = [Scope::Machine, Scope::User];
 
 #[derive(Debug, Serialize, Deserialize, Clone, Default)]
 pub struct TaskManager {
-    cur_env: EnvHashMap,
+    cur_env: HashMap<Scope, EnvHashMap>,
     tasks: Vec<TaskLog>,
 }
 
 impl TaskManager {
     pub fn init(&mut self) -> Result<EnvHashMap, Box<dyn std::error::Error>> {
         dbg!("init");
-        let data = get_environment_variables();
-        self.cur_env.extend(data.clone());
+        for scope in [Scope::User, Scope::Machine, Scope::Process] {
+            self.cur_env.insert(scope, get_environment_variables(scope));
+        }
         self.add_task(TaskLog::init());
-        Ok(data)
+        Ok(self.get_committed_env())
     }
 
+    /// Process tasks are never written back, they are dropped by the flush
     pub fn flush(&mut self) -> Result<EnvHashMap, Box<dyn std::error::Error>> {
-        let _tasks = self._since_last_flush_tasks();
-        let new_env = TaskResolver::new(&self.cur_env, _tasks).forward();
-        let _ = UpdateResolver::new(&self.cur_env, &new_env).resolve();
+        let mut written: Vec<Scope> = vec![];
+        for scope in WRITABLE_SCOPES {
+            let old_env = self.get_committed_env_of(scope);
+            let new_env = self.get_cur_env_of(scope);
+            // a rejected machine write leaves everything pending,
+            // a rejected user write restores the machine scope written before it
+            if let Err(e) = UpdateResolver::new(&old_env, &new_env, scope).resolve() {
+                for done in written.iter().rev() {
+                    let old_env = self.get_committed_env_of(*done);
+                    let new_env = self.get_cur_env_of(*done);
+                    if let Err(rollback) = UpdateResolver::new(&new_env, &old_env, *done).resolve() {
+                        let msg = format!("{}, restoring {:?} variables failed as well: {}", e, done, rollback);
+                        return Err(Box::new(BackendError::Failed(msg)));
+                    }
+                }
+                return Err(Box::new(e));
+            }
+            written.push(scope);
+        }
 
         self.add_task(TaskLog::flush());
 
-        let data = get_environment_variables();
-        self.cur_env.clear();
-        self.cur_env.extend(data.clone());
-        Ok(self.cur_env.clone())
+        for scope in [Scope::User, Scope::Machine, Scope::Process] {
+            self.cur_env.insert(scope, get_environment_variables(scope));
+        }
+        Ok(self.get_committed_env())
     }
 
     pub fn add_task(&mut self, task: TaskLog) {
         self.tasks.push(task);
     }
 
+    /// every task since the app started, including flushes
+    pub fn history(&self) -> &[TaskLog] {
+        &self.tasks
+    }
+
+    /// forget the oldest tasks so that at most `max` remain,
+    /// pending tasks and the flush before them are always kept
+    pub fn trim_history(&mut self, max: usize) {
+        let keep = max.max(self.pending().len() + 1);
+        let excess = self.tasks.len().saturating_sub(keep);
+        self.tasks.drain(..excess);
+    }
+
+    /// tasks since the last flush
+    pub fn pending(&self) -> &[TaskLog] {
+        self._since_last_flush_tasks()
+    }
+
+    /// pending tasks of `scope`, tasks inside groups are counted one by one
+    pub fn pending_in(&self, scope: Scope) -> usize {
+        fn count(task: &TaskLogData, scope: Scope) -> usize {
+            match task {
+                TaskLogData::Group(log) => log.tasks.iter().map(|t| count(t, scope)).sum(),
+                _ => (task.scope() == Some(scope)) as usize,
+            }
+        }
+        self.pending().iter().map(|t| count(&t.data, scope)).sum()
+    }
+
+    /// None if the file does not exist or is broken
+    pub fn load(path: &Path) -> Option<Self> {
+        let content = std::fs::read_to_string(path).ok()?;
+        serde_json::from_str(&content).ok()
+    }
+
+    pub fn dump(&self, path: &Path) -> std::io::Result<()> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, serde_json::to_string(self)?)
+    }
+
+    /// pending env of the user scope
     pub fn get_cur_env(&self) -> EnvHashMap {
+        self.get_cur_env_of(Scope::User)
+    }
+
+    pub fn get_cur_env_of(&self, scope: Scope) -> EnvHashMap {
         let _tasks = self._since_last_flush_tasks();
-        let new_env = TaskResolver::new(&self.cur_env, _tasks).forward();
+        let env = self.get_committed_env_of(scope);
+        let new_env = TaskResolver::new(&env, _tasks).scope(scope).forward();
         new_env
     }
 
+    /// env of the last flush, without pending tasks
+    pub fn get_committed_env(&self) -> EnvHashMap {
+        self.get_committed_env_of(Scope::User)
+    }
+
+    pub fn get_committed_env_of(&self, scope: Scope) -> EnvHashMap {
+        self.cur_env.get(&scope).cloned().unwrap_or_default()
+    }
+
+    /// writable scopes which differ between `live` and the base of pending tasks
+    pub fn external_changes(&self, live: &HashMap<Scope, EnvHashMap>) -> Vec<ExternalChange> {
+        WRITABLE_SCOPES
+            .iter()
+            .filter_map(|scope| {
+                let diff = EnvDiff::between(&self.get_committed_env_of(*scope), live.get(scope)?);
+                if diff.is_empty() {
+                    return None;
+                }
+                Some(ExternalChange { scope: *scope, diff })
+            })
+            .collect()
+    }
+
+    /// replace the base of pending tasks with `live`.
+    /// tasks touching a variable which is changed externally are dropped and returned
+    pub fn rebase(&mut self, live: HashMap<Scope, EnvHashMap>) -> Vec<TaskLogData> {
+        let conflicts: HashSet<(Scope, String)> = self
+            .external_changes(&live)
+            .into_iter()
+            .flat_map(|c| c.diff.changes.into_iter().map(move |v| (c.scope, v.variable)))
+            .collect();
+        let first = self.tasks.len() - self._since_last_flush_tasks().len();
+        let pending = self.tasks.split_off(first);
+        let mut dropped = vec![];
+        for task in pending {
+            let (kept, mut conflicted) = task.data.split_conflicts(&conflicts);
+            dropped.append(&mut conflicted);
+            if let Some(data) = kept {
+                self.tasks.push(TaskLog {
+                    timestamp: task.timestamp,
+                    data,
+                });
+            }
+        }
+        for (scope, env) in live {
+            self.cur_env.insert(scope, env);
+        }
+        dropped
+    }
+
     pub fn is_dirty(&self) -> bool {
         let _tasks = self._since_last_flush_tasks();
         // dbg!(_tasks.len());
@@ -83,6 +205,15 @@ impl TaskManager {
             TaskLogData::ReorderValue(log) => {
                 Ok(format!("Undo Task: 恢复变量 '{}' 的排序", log.variable))
             },
+            TaskLogData::InsertValue(log) => {
+                Ok(format!("Undo Task: 重新删除变量 '{}' 中的 '{}'", log.variable, log.value))
+            },
+            TaskLogData::SetVariable(log) => {
+                Ok(format!("Undo Task: 恢复变量 '{}' 的值", log.variable))
+            },
+            TaskLogData::Group(log) => {
+                Ok(format!("Undo Task: 撤销 '{}' ({} 项)", log.name, log.tasks.len()))
+            },
         }
     }
 
@@ -102,104 +233,108 @@ impl TaskManager {
     }
 }
 
-// forces powershell to output UTF-8, or else it will output UTF-16, stdout cannot be decoded
-const FORCE_UTF8: &str = r#"[console]::OutputEncoding = [System.Text.Encoding]::UTF8"#;
-const GET_ENV: &str =
-    "[Environment]::GetEnvironmentVariables([EnvironmentVariableTarget]::User) | ConvertTo-Json";
+/// the live env of the backend read in every writable scope
+pub fn get_live_env() -> HashMap<Scope, EnvHashMap> {
+    get_stored_environment_variables(&WRITABLE_SCOPES)
+}
 
-fn get_environment_variables() -> EnvHashMap {
-    let output = Command::new("powershell")
-        .arg([FORCE_UTF8, GET_ENV].join(";"))
-        .creation_flags(0x08000000) // CREATE_NO_WINDOW
-        .output()
-        .expect("failed to execute process");
-    let stdout = String::from_utf8(output.stdout).expect("Failed to decode UTF-8");
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct VariableChange {
+    pub variable: String,
+    /// None if the variable is added
+    pub old: Option<Vec<String>>,
+    /// None if the variable is deleted
+    pub new: Option<Vec<String>>,
+}
 
-    let data: HashMap<String, String> =
-        serde_json::from_str(&stdout).expect("Failed to deserialize JSON");
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct EnvDiff {
+    pub changes: Vec<VariableChange>,
+}
 
-    let mut env = HashMap::new();
-    env.extend(data.into_iter().map(|(k, v)| {
-        let v = v
-            .split(";")
-            .map(|s| s.to_string())
-            .filter(|x| if x.trim().is_empty() { false } else { true })
-            .collect();
-        return (k, v);
-    }));
-    env
+impl EnvDiff {
+    pub fn between(old_env: &EnvHashMap, new_env: &EnvHashMap) -> Self {
+        let mut changes = vec![];
+        for (k, v) in new_env.iter() {
+            if old_env.get(k) != Some(v) {
+                changes.push(VariableChange {
+                    variable: k.clone(),
+                    old: old_env.get(k).cloned(),
+                    new: Some(v.clone()),
+                });
+            }
+        }
+        for (k, v) in old_env.iter() {
+            if new_env.get(k).is_none() {
+                changes.push(VariableChange {
+                    variable: k.clone(),
+                    old: Some(v.clone()),
+                    new: None,
+                });
+            }
+        }
+        changes.sort_by(|a, b| a.variable.cmp(&b.variable));
+        Self { changes }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+}
+
+/// a scope changed by other programs since the last flush, old is our base
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ExternalChange {
+    pub scope: Scope,
+    pub diff: EnvDiff,
 }
 
 // 处理环境变量更新操作
 struct UpdateResolver<'a> {
     old_env: &'a EnvHashMap,
     new_env: &'a EnvHashMap,
+    scope: Scope,
 }
 
 impl<'a> UpdateResolver<'a> {
-    pub fn new(old_env: &'a EnvHashMap, new_env: &'a EnvHashMap) -> Self {
-        Self { old_env, new_env }
+    pub fn new(old_env: &'a EnvHashMap, new_env: &'a EnvHashMap, scope: Scope) -> Self {
+        Self { old_env, new_env, scope }
     }
-    pub fn resolve(&self) {
-        self._resolve();
+    pub fn resolve(&self) -> Result<(), BackendError> {
+        self._resolve()
     }
-    fn _resolve(&self) {
+    fn _resolve(&self) -> Result<(), BackendError> {
         let (updates, deletes) = self._filter();
-        let tasks = self._create_tasks(updates, deletes);
-        Command::new("powershell")
-            .arg(tasks.join(";"))
-            .creation_flags(0x08000000) // CREATE_NO_WINDOW
-            .output()
-            .expect("failed to execute process");
+        let updates: Vec<(String, Vec<String>)> = updates
+            .into_iter()
+            .map(|k| {
+                let v = self.new_env.get(&k).unwrap().clone();
+                (k, v)
+            })
+            .collect();
+        set_environment_variables(self.scope, &updates, &deletes)
     }
 
     // 过滤出需要更新和删除的环境变量
-    fn _filter(&self) -> (Vec<&String>, Vec<&String>) {
+    fn _filter(&self) -> (Vec<String>, Vec<String>) {
         let mut updates = vec![];
         let mut deletes = vec![];
 
-        for (k, v) in self.new_env.iter() {
-            if self.old_env.get(k) != Some(v) {
-                updates.push(k);
-            }
-        }
-        for (k, _) in self.old_env.iter() {
-            if self.new_env.get(k).is_none() {
-                deletes.push(k);
+        for change in EnvDiff::between(self.old_env, self.new_env).changes {
+            match change.new {
+                Some(_) => updates.push(change.variable),
+                None => deletes.push(change.variable),
             }
         }
         (updates, deletes)
     }
-
-    // 生成更新环境变量的任务字符串
-    fn _create_tasks(&self, updates: Vec<&String>, deletes: Vec<&String>) -> Vec<String> {
-        let mut tasks = vec![];
-        for k in updates.iter() {
-            let v = self.new_env.get(*k).unwrap();
-            println!("update '{}': '{:?}'", k, v);
-            // extra ; is needed to avoid empty string, or it will be removed
-            tasks.push(format!(
-                "[Environment]::SetEnvironmentVariable(\"{}\", \";{}\", [EnvironmentVariableTarget]::User)",
-                k,
-                v.join(";")
-            ));
-        }
-        for k in deletes.iter() {
-            println!("delete '{}'", k);
-            tasks.push(format!(
-                "[Environment]::SetEnvironmentVariable(\"{}\", $null, [EnvironmentVariableTarget]::User)",
-                k
-            ));
-        }
-        tasks
-    }
 }
 
 // ========================
 // ========================
 
 #[allow(unused_variables)]
-trait ConsumeTask {
+pub(crate) trait ConsumeTask {
     fn forward(&self, map: &mut EnvHashMap) {
         ()
     }
@@ -215,6 +350,15 @@ macro_rules! declare_task_log_data {
             $( pub $item: $ty, )*
         }
     };
+    (scoped $name:ident, [ $( $item:ident: $ty:ident), * ]) => {
+        #[derive(Serialize, Deserialize, Clone, Default, Debug)]
+        pub struct $name {
+            $( pub $item: $ty, )*
+            /// User if omitted by the frontend
+            #[serde(default)]
+            pub scope: Scope,
+        }
+    };
 }
 declare_task_log_data!(InitLog, []);
 declare_task_log_data!(FlushLog, []);
@@ -226,7 +370,7 @@ impl ConsumeTask for RevertLog {}
 
 // ========================
 
-declare_task_log_data!(AddValueLog, [ variable: String, value: String ]);
+declare_task_log_data!(scoped AddValueLog, [ variable: String, value: String ]);
 impl ConsumeTask for AddValueLog {
     fn forward(&self, map: &mut EnvHashMap) {
         // 找有没有这个变量，没有直接panic
@@ -256,7 +400,7 @@ impl ConsumeTask for AddValueLog {
 
 // ========================
 
-declare_task_log_data!(DeleteValueLog, [ variable: String, index: usize, value: String ]);
+declare_task_log_data!(scoped DeleteValueLog, [ variable: String, index: usize, value: String ]);
 impl ConsumeTask for DeleteValueLog {
     fn forward(&self, map: &mut EnvHashMap) {
         if let Some(values) = map.get_mut(&self.variable) {
@@ -276,7 +420,7 @@ impl ConsumeTask for DeleteValueLog {
 
 // ========================
 
-declare_task_log_data!(UpdateValueLog, [ variable: String, index: usize, old_value: String, new_value: String ]);
+declare_task_log_data!(scoped UpdateValueLog, [ variable: String, index: usize, old_value: String, new_value: String ]);
 impl ConsumeTask for UpdateValueLog {
     fn forward(&self, map: &mut EnvHashMap) {
         if let Some(values) = map.get_mut(&self.variable) {
@@ -303,7 +447,7 @@ impl ConsumeTask for UpdateValueLog {
 
 // ========================
 
-declare_task_log_data!(OrderValueLog, [ variable: String, index_before: usize, index_after: usize, value: String ]);
+declare_task_log_data!(scoped OrderValueLog, [ variable: String, index_before: usize, index_after: usize, value: String ]);
 impl ConsumeTask for OrderValueLog {
     fn forward(&self, map: &mut EnvHashMap) {
         if let Some(values) = map.get_mut(&self.variable) {
@@ -322,7 +466,7 @@ impl ConsumeTask for OrderValueLog {
 }
 
 // ========================
-declare_task_log_data!(AddVariableLog, [ variable: String ]);
+declare_task_log_data!(scoped AddVariableLog, [ variable: String ]);
 impl ConsumeTask for AddVariableLog {
     fn forward(&self, map: &mut EnvHashMap) {
         // 如果已经存在这个变量，直接panic
@@ -348,7 +492,7 @@ impl ConsumeTask for AddVariableLog {
 
 // ========================
 type VecString = Vec<String>;
-declare_task_log_data!(DeleteVariableLog, [ variable: String, values: VecString ]);
+declare_task_log_data!(scoped DeleteVariableLog, [ variable: String, values: VecString ]);
 impl ConsumeTask for DeleteVariableLog {
     fn forward(&self, map: &mut EnvHashMap) {
         // 如果不存在这个变量，直接panic
@@ -372,6 +516,72 @@ impl ConsumeTask for DeleteVariableLog {
     }
 }
 
+// ========================
+
+declare_task_log_data!(scoped InsertValueLog, [ variable: String, index: usize, value: String ]);
+impl ConsumeTask for InsertValueLog {
+    fn forward(&self, map: &mut EnvHashMap) {
+        if let Some(values) = map.get_mut(&self.variable) {
+            values.insert(self.index, self.value.clone());
+            return;
+        }
+        panic!("[ConsumeTask InsertValueLog forward] variable not found");
+    }
+    fn backword(&self, map: &mut EnvHashMap) {
+        if let Some(values) = map.get_mut(&self.variable) {
+            if values[self.index] == self.value {
+                values.remove(self.index);
+                return;
+            }
+            panic!("[ConsumeTask InsertValueLog backword] self.value '{}' != values[self.index] '{}'", self.value, values[self.index]);
+        }
+        panic!("[ConsumeTask InsertValueLog backword] variable not found");
+    }
+}
+
+// ========================
+
+declare_task_log_data!(scoped SetVariableLog, [ variable: String, old_values: VecString, new_values: VecString ]);
+impl ConsumeTask for SetVariableLog {
+    fn forward(&self, map: &mut EnvHashMap) {
+        if let Some(values) = map.get_mut(&self.variable) {
+            // 整体替换前，确认旧值没有被改动过
+            if values == &self.old_values {
+                *values = self.new_values.clone();
+                return;
+            }
+            panic!("[ConsumeTask SetVariableLog forward] self.old_values {:?} != values {:?}", self.old_values, values);
+        }
+        panic!("[ConsumeTask SetVariableLog forward] variable not found");
+    }
+    fn backword(&self, map: &mut EnvHashMap) {
+        if let Some(values) = map.get_mut(&self.variable) {
+            if values == &self.new_values {
+                *values = self.old_values.clone();
+                return;
+            }
+            panic!("[ConsumeTask SetVariableLog backword] self.new_values {:?} != values {:?}", self.new_values, values);
+        }
+        panic!("[ConsumeTask SetVariableLog backword] variable not found");
+    }
+}
+
+// ========================
+type VecTaskLogData = Vec<TaskLogData>;
+declare_task_log_data!(GroupLog, [ name: String, tasks: VecTaskLogData ]);
+impl ConsumeTask for GroupLog {
+    fn forward(&self, map: &mut EnvHashMap) {
+        for task in self.tasks.iter() {
+            task.forward(map);
+        }
+    }
+    fn backword(&self, map: &mut EnvHashMap) {
+        for task in self.tasks.iter().rev() {
+            task.backword(map);
+        }
+    }
+}
+
 // ========================
 // ========================
 
@@ -386,6 +596,107 @@ pub enum TaskLogData {
     DeleteValue(DeleteValueLog),
     ModifyValue(UpdateValueLog),
     ReorderValue(OrderValueLog),
+    InsertValue(InsertValueLog),
+    SetVariable(SetVariableLog),
+    /// several tasks applied and undone as one
+    Group(GroupLog),
+}
+
+impl TaskLogData {
+    pub fn delete_value(variable: &str, index: usize, value: &str, scope: Scope) -> Self {
+        TaskLogData::DeleteValue(DeleteValueLog {
+            variable: variable.to_string(),
+            index,
+            value: value.to_string(),
+            scope,
+        })
+    }
+
+    pub fn insert_value(variable: &str, index: usize, value: &str, scope: Scope) -> Self {
+        TaskLogData::InsertValue(InsertValueLog {
+            variable: variable.to_string(),
+            index,
+            value: value.to_string(),
+            scope,
+        })
+    }
+
+    /// scope of a single task, None for tasks applying to every scope
+    pub fn scope(&self) -> Option<Scope> {
+        match self {
+            TaskLogData::Init(_) | TaskLogData::Flush(_) | TaskLogData::Group(_) => None,
+            TaskLogData::AddVariable(log) => Some(log.scope),
+            TaskLogData::DelVariable(log) => Some(log.scope),
+            TaskLogData::AppendValue(log) => Some(log.scope),
+            TaskLogData::DeleteValue(log) => Some(log.scope),
+            TaskLogData::ModifyValue(log) => Some(log.scope),
+            TaskLogData::ReorderValue(log) => Some(log.scope),
+            TaskLogData::InsertValue(log) => Some(log.scope),
+            TaskLogData::SetVariable(log) => Some(log.scope),
+        }
+    }
+
+    pub fn variable(&self) -> Option<&str> {
+        match self {
+            TaskLogData::Init(_) | TaskLogData::Flush(_) | TaskLogData::Group(_) => None,
+            TaskLogData::AddVariable(log) => Some(&log.variable),
+            TaskLogData::DelVariable(log) => Some(&log.variable),
+            TaskLogData::AppendValue(log) => Some(&log.variable),
+            TaskLogData::DeleteValue(log) => Some(&log.variable),
+            TaskLogData::ModifyValue(log) => Some(&log.variable),
+            TaskLogData::ReorderValue(log) => Some(&log.variable),
+            TaskLogData::InsertValue(log) => Some(&log.variable),
+            TaskLogData::SetVariable(log) => Some(&log.variable),
+        }
+    }
+
+    /// (kept, dropped), a group is dropped as a whole if any of its tasks conflicts,
+    /// so that a profile is never half applied
+    fn split_conflicts(self, conflicts: &HashSet<(Scope, String)>) -> (Option<TaskLogData>, Vec<TaskLogData>) {
+        match self {
+            TaskLogData::Group(log) => {
+                let mut kept = vec![];
+                let mut dropped = vec![];
+                for task in log.tasks {
+                    let (k, mut d) = task.split_conflicts(conflicts);
+                    kept.extend(k);
+                    dropped.append(&mut d);
+                }
+                if !dropped.is_empty() {
+                    dropped.append(&mut kept);
+                    return (None, dropped);
+                }
+                let group = GroupLog {
+                    name: log.name,
+                    tasks: kept,
+                };
+                (Some(TaskLogData::Group(group)), dropped)
+            }
+            _ => match (self.scope(), self.variable()) {
+                (Some(scope), Some(variable)) if conflicts.contains(&(scope, variable.to_string())) => {
+                    (None, vec![self])
+                }
+                _ => (Some(self), vec![]),
+            },
+        }
+    }
+
+    /// forward only the tasks of `scope`, groups are entered
+    fn forward_in(&self, map: &mut EnvHashMap, scope: Scope) {
+        match self {
+            TaskLogData::Group(log) => log.tasks.iter().for_each(|t| t.forward_in(map, scope)),
+            _ if self.scope() == Some(scope) => self.forward(map),
+            _ => (),
+        }
+    }
+
+    fn backword_in(&self, map: &mut EnvHashMap, scope: Scope) {
+        match self {
+            TaskLogData::Group(log) => log.tasks.iter().rev().for_each(|t| t.backword_in(map, scope)),
+            _ if self.scope() == Some(scope) => self.backword(map),
+            _ => (),
+        }
+    }
 }
 
 impl ConsumeTask for TaskLogData {
@@ -399,6 +710,9 @@ impl ConsumeTask for TaskLogData {
             TaskLogData::DeleteValue(log) => log.forward(map),
             TaskLogData::ModifyValue(log) => log.forward(map),
             TaskLogData::ReorderValue(log) => log.forward(map),
+            TaskLogData::InsertValue(log) => log.forward(map),
+            TaskLogData::SetVariable(log) => log.forward(map),
+            TaskLogData::Group(log) => log.forward(map),
         }
     }
     fn backword(&self, map: &mut EnvHashMap) {
@@ -411,6 +725,9 @@ impl ConsumeTask for TaskLogData {
             TaskLogData::DeleteValue(log) => log.backword(map),
             TaskLogData::ModifyValue(log) => log.backword(map),
             TaskLogData::ReorderValue(log) => log.backword(map),
+            TaskLogData::InsertValue(log) => log.backword(map),
+            TaskLogData::SetVariable(log) => log.backword(map),
+            TaskLogData::Group(log) => log.backword(map),
         }
     }
 }
@@ -448,23 +765,36 @@ impl Into<TaskLog> for TaskLogData {
     }
 }
 
+/// 任务之间存在依赖（index），边生成边应用到临时表上
+pub fn push_task(tasks: &mut Vec<TaskLogData>, map: &mut EnvHashMap, task: TaskLogData) {
+    task.forward(map);
+    tasks.push(task);
+}
+
 // ========================
 // ========================
 
 pub struct TaskResolver<'a> {
     env: &'a EnvHashMap,
     tasks: &'a [TaskLog],
+    scope: Scope,
 }
 
 impl<'r> TaskResolver<'r> {
     pub fn new(env: &'r EnvHashMap, tasks: &'r [TaskLog]) -> Self {
-        Self { env, tasks }
+        Self { env, tasks, scope: Scope::User }
+    }
+
+    /// only resolve tasks of `scope`, `env` should be the env of the same scope
+    pub fn scope(mut self, scope: Scope) -> Self {
+        self.scope = scope;
+        self
     }
 
     pub fn forward(&self) -> EnvHashMap {
         let mut map = self.env.clone();
         for task in self.tasks.iter() {
-            task.data.forward(&mut map);
+            task.data.forward_in(&mut map, self.scope);
         }
         return map;
     }
@@ -472,7 +802,7 @@ impl<'r> TaskResolver<'r> {
     pub fn backword(&self) -> EnvHashMap {
         let mut map = self.env.clone();
         for task in self.tasks.iter().rev() {
-            task.data.backword(&mut map);
+            task.data.backword_in(&mut map, self.scope);
         }
         return map;
     }
@@ -487,3 +817,130 @@ fn now() -> u128 {
         .unwrap()
         .as_millis()
 }
+
+#[test]
+fn test_resolve_scoped_tasks() {
+    let mut env = EnvHashMap::new();
+    env.insert("PATH".to_string(), vec!["a".to_string()]);
+    let tasks: Vec<TaskLog> = vec![
+        TaskLogData::AppendValue(AddValueLog {
+            variable: "PATH".to_string(),
+            value: "user".to_string(),
+            ..Default::default()
+        })
+        .into(),
+        TaskLogData::AppendValue(AddValueLog {
+            variable: "PATH".to_string(),
+            value: "machine".to_string(),
+            scope: Scope::Machine,
+        })
+        .into(),
+    ];
+    let user = TaskResolver::new(&env, &tasks).forward();
+    let machine = TaskResolver::new(&env, &tasks).scope(Scope::Machine).forward();
+    assert_eq!(user["PATH"], vec!["a", "user"]);
+    assert_eq!(machine["PATH"], vec!["a", "machine"]);
+}
+
+#[test]
+fn test_rebase_drops_conflicts() {
+    let mut tm = TaskManager::default();
+    let mut base = EnvHashMap::new();
+    base.insert("PATH".to_string(), vec!["a".to_string()]);
+    base.insert("EDITOR".to_string(), vec!["vim".to_string()]);
+    tm.cur_env.insert(Scope::User, base.clone());
+    tm.add_task(TaskLog::init());
+    for (variable, value) in [("PATH", "b"), ("EDITOR", "nano")] {
+        tm.add_task(
+            TaskLogData::AppendValue(AddValueLog {
+                variable: variable.to_string(),
+                value: value.to_string(),
+                ..Default::default()
+            })
+            .into(),
+        );
+    }
+
+    // an installer appends to PATH meanwhile
+    let mut live = HashMap::new();
+    let mut user = base.clone();
+    user.get_mut("PATH").unwrap().push("installer".to_string());
+    live.insert(Scope::User, user);
+    live.insert(Scope::Machine, EnvHashMap::new());
+    assert_eq!(tm.external_changes(&live).len(), 1);
+
+    let dropped = tm.rebase(live.clone());
+    assert_eq!(dropped.len(), 1);
+    assert_eq!(dropped[0].variable(), Some("PATH"));
+    assert!(tm.external_changes(&live).is_empty());
+    assert_eq!(tm.get_cur_env()["PATH"], vec!["a", "installer"]);
+    assert_eq!(tm.get_cur_env()["EDITOR"], vec!["vim", "nano"]);
+}
+
+#[test]
+fn test_rebase_drops_whole_group() {
+    let mut tm = TaskManager::default();
+    let mut base = EnvHashMap::new();
+    base.insert("PATH".to_string(), vec!["a".to_string()]);
+    tm.cur_env.insert(Scope::User, base.clone());
+    tm.add_task(TaskLog::init());
+    let tasks = vec![
+        TaskLogData::AppendValue(AddValueLog {
+            variable: "PATH".to_string(),
+            value: "b".to_string(),
+            ..Default::default()
+        }),
+        TaskLogData::AddVariable(AddVariableLog {
+            variable: "EDITOR".to_string(),
+            ..Default::default()
+        }),
+    ];
+    tm.add_task(TaskLogData::Group(GroupLog { name: "profile".to_string(), tasks }).into());
+
+    let mut live = HashMap::new();
+    let mut user = base.clone();
+    user.get_mut("PATH").unwrap().push("installer".to_string());
+    live.insert(Scope::User, user);
+    live.insert(Scope::Machine, EnvHashMap::new());
+
+    let dropped = tm.rebase(live);
+    assert_eq!(dropped.len(), 2);
+    assert!(!tm.is_dirty());
+    assert!(!tm.get_cur_env().contains_key("EDITOR"));
+}
+
+#[test]
+fn test_trim_history_keeps_pending() {
+    let mut tm = TaskManager::default();
+    tm.add_task(TaskLog::init());
+    for _ in 0..5 {
+        tm.add_task(TaskLog::flush());
+    }
+    for variable in ["A", "B", "C"] {
+        tm.add_task(TaskLogData::AddVariable(AddVariableLog { variable: variable.to_string(), scope: Scope::User }).into());
+    }
+    tm.trim_history(2);
+    assert_eq!(tm.history().len(), 4);
+    assert_eq!(tm.pending().len(), 3);
+    tm.trim_history(100);
+    assert_eq!(tm.history().len(), 4);
+}
+
+#[test]
+fn test_pending_in_counts_group_members() {
+    let mut tm = TaskManager::default();
+    tm.add_task(TaskLog::init());
+    tm.add_task(TaskLogData::AddVariable(AddVariableLog { variable: "A".to_string(), scope: Scope::Process }).into());
+    tm.add_task(
+        TaskLogData::Group(GroupLog {
+            name: "both".to_string(),
+            tasks: vec![
+                TaskLogData::AddVariable(AddVariableLog { variable: "B".to_string(), scope: Scope::Process }),
+                TaskLogData::AddVariable(AddVariableLog { variable: "C".to_string(), scope: Scope::User }),
+            ],
+        })
+        .into(),
+    );
+    assert_eq!(tm.pending_in(Scope::Process), 2);
+    assert_eq!(tm.pending_in(Scope::User), 1);
+}
@@ -0,0 +1,440 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::path::Path;
+
+use crate::backend::Scope;
+use crate::effective::EffectiveEnv;
+use crate::expand::expand;
+use crate::lint::{is_path_variable, normalize};
+use crate::scanner::Storage;
+use crate::suggest::command_name;
+use crate::task::{push_task, AddVariableLog, ConsumeTask, EnvHashMap, GroupLog, SetVariableLog, TaskLogData};
+use crate::which::{pathext, resolve};
+
+/// dont modify the names of these enum, compatiable with frontend
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Tool {
+    Jdk,
+    Python,
+    Node,
+    Go,
+    Rust,
+    Maven,
+    Gradle,
+    AndroidSdk,
+    Cuda,
+    Ffmpeg,
+}
+
+/// one install found on disk, nothing of it is executed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Toolchain {
+    pub tool: Tool,
+    /// the command telling which install is in use, e.g. `java`
+    pub command: String,
+    pub home: String,
+    pub version: Option<String>,
+    /// variables pointing at `home`, e.g. JAVA_HOME
+    pub variables: Vec<(String, String)>,
+    /// directories with the commands, e.g. `home/bin`
+    pub path_entries: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Install {
+    pub toolchain: Toolchain,
+    /// its variables are set and its `command` is the one PATH resolves
+    pub active: bool,
+    /// sets the variables and moves its entries to the front of the user PATH,
+    /// None if active or if it would not win, e.g. a machine PATH entry resolves `command` first
+    pub task: Option<TaskLogData>,
+}
+
+/// every install of one tool, so that users can pick a version
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ToolInstalls {
+    pub tool: Tool,
+    pub installs: Vec<Install>,
+}
+
+impl Toolchain {
+    fn new(tool: Tool, command: &str, home: &Path, version: Option<String>) -> Self {
+        Self {
+            tool,
+            command: command.to_string(),
+            home: home.to_str().unwrap_or_default().to_string(),
+            version,
+            variables: vec![],
+            path_entries: vec![],
+        }
+    }
+
+    fn variable(mut self, name: &str) -> Self {
+        self.variables.push((name.to_string(), self.home.clone()));
+        self
+    }
+
+    fn path_entry(mut self, dir: &Path) -> Self {
+        if let Some(d) = dir.to_str() {
+            self.path_entries.push(d.to_string());
+        }
+        self
+    }
+
+    /// the variables point at `home` and `command` resolves into `path_entries`
+    pub fn is_active(&self, effective: &EnvHashMap) -> bool {
+        let variables_set = self
+            .variables
+            .iter()
+            .all(|(name, value)| effective.get(name).map_or(false, |v| is_single(v, value)));
+        let winner = resolve(&self.command, effective).winner;
+        let winner_dir = winner.as_ref().and_then(|w| Path::new(&w.file).parent()?.to_str().map(normalize));
+        variables_set && winner_dir.map_or(false, |dir| self.path_entries.iter().any(|e| normalize(e) == dir))
+    }
+
+    /// the tasks making this install the one in use, `user` is edited.
+    /// its entries go to the front of PATH, so other installs listed earlier no longer win
+    pub fn tasks(&self, user: &EnvHashMap) -> Vec<TaskLogData> {
+        let scope = Scope::User;
+        let mut tasks = vec![];
+        let mut map = user.clone();
+        for (name, value) in self.variables.iter() {
+            let old = map.get(name).cloned();
+            if old.as_ref().map_or(false, |v| is_single(v, value)) {
+                continue;
+            }
+            if old.is_none() {
+                push_task(&mut tasks, &mut map, TaskLogData::AddVariable(AddVariableLog {
+                    variable: name.clone(),
+                    scope,
+                }));
+            }
+            push_task(&mut tasks, &mut map, TaskLogData::SetVariable(SetVariableLog {
+                variable: name.clone(),
+                old_values: old.unwrap_or_default(),
+                new_values: vec![value.clone()],
+                scope,
+            }));
+        }
+        let key = match map.keys().find(|k| is_path_variable(k)) {
+            Some(k) => k.clone(),
+            None => {
+                push_task(&mut tasks, &mut map, TaskLogData::AddVariable(AddVariableLog {
+                    variable: "PATH".to_string(),
+                    scope,
+                }));
+                "PATH".to_string()
+            }
+        };
+        for (index, entry) in self.path_entries.iter().enumerate() {
+            let values = map[&key].clone();
+            // 变量已经先改过了，`%JAVA_HOME%\bin` 这样的条目也能认出来
+            let same = |v: &String| expand(v, &map).map_or(false, |v| normalize(&v) == normalize(entry));
+            if values.get(index).map_or(false, same) {
+                continue;
+            }
+            // 引用变量的条目留着，只去掉写死的重复项
+            if let Some(at) = values.iter().position(|v| normalize(v) == normalize(entry)) {
+                push_task(&mut tasks, &mut map, TaskLogData::delete_value(&key, at, &values[at], scope));
+            }
+            push_task(&mut tasks, &mut map, TaskLogData::insert_value(&key, index, entry, scope));
+        }
+        tasks
+    }
+}
+
+fn is_single(values: &[String], value: &str) -> bool {
+    values.len() == 1 && normalize(&values[0]) == normalize(value)
+}
+
+/// known toolchains inside the scanned directories, grouped by tool
+pub fn detect_toolchains(storage: &Storage, machine: &EnvHashMap, user: &EnvHashMap) -> Vec<ToolInstalls> {
+    let effective = &EffectiveEnv::merge(machine, user).to_env();
+    let pathext = pathext(effective);
+    let mut found: Vec<Toolchain> = vec![];
+    for (dir, files) in storage.executables_by_dir() {
+        let names: HashSet<String> = files.iter().filter_map(|f| command_name(f, &pathext)).collect();
+        found.extend(detect(&dir, &names));
+    }
+    // 同一个安装可能被多个目录识别出来
+    found.sort_by(|a, b| (a.tool, &a.home).cmp(&(b.tool, &b.home)));
+    found.dedup_by(|a, b| a.tool == b.tool && normalize(&a.home) == normalize(&b.home));
+
+    let mut tools: BTreeMap<Tool, Vec<Install>> = BTreeMap::new();
+    for toolchain in found {
+        let active = toolchain.is_active(effective);
+        let tasks = if active { vec![] } else { toolchain.tasks(user) };
+        // 只改用户变量赢不了的话，就不要给一个点了也没用的按钮
+        let mut applied = user.clone();
+        tasks.iter().for_each(|t| t.forward(&mut applied));
+        let wins = toolchain.is_active(&EffectiveEnv::merge(machine, &applied).to_env());
+        let task = match tasks.len() {
+            0 => None,
+            _ if !wins => None,
+            _ => Some(TaskLogData::Group(GroupLog {
+                name: format!("use {:?} {}", toolchain.tool, toolchain.version.as_deref().unwrap_or(&toolchain.home)),
+                tasks,
+            })),
+        };
+        tools.entry(toolchain.tool).or_default().push(Install {
+            active,
+            toolchain,
+            task,
+        });
+    }
+    tools
+        .into_iter()
+        .map(|(tool, installs)| ToolInstalls { tool, installs })
+        .collect()
+}
+
+/// `dir` holds the executables `names`
+pub fn detect(dir: &Path, names: &HashSet<String>) -> Vec<Toolchain> {
+    let has = |n: &str| names.contains(n);
+    let dir_name = dir.file_name().and_then(|n| n.to_str()).unwrap_or_default().to_lowercase();
+    let parent = dir.parent();
+    let mut found = vec![];
+
+    if let Some(home) = parent.filter(|_| dir_name == "bin") {
+        if has("java") && home.join("release").is_file() {
+            let version = read_property(&home.join("release"), "JAVA_VERSION");
+            found.push(Toolchain::new(Tool::Jdk, "java", home, version).variable("JAVA_HOME").path_entry(dir));
+        }
+        if has("go") && home.join("VERSION").is_file() {
+            let version = read_first_line(&home.join("VERSION")).map(|v| v.trim_start_matches("go").to_string());
+            found.push(Toolchain::new(Tool::Go, "go", home, version).variable("GOROOT").path_entry(dir));
+        }
+        if has("rustc") && has("cargo") {
+            // ~/.rustup/toolchains/stable-x86_64-unknown-linux-gnu/bin
+            let version = home
+                .parent()
+                .filter(|p| p.file_name().map_or(false, |n| n == "toolchains"))
+                .and_then(|_| home.file_name()?.to_str())
+                .map(|n| n.to_string());
+            let mut toolchain = Toolchain::new(Tool::Rust, "rustc", home, version);
+            if home.file_name().map_or(false, |n| n == ".cargo") {
+                toolchain = toolchain.variable("CARGO_HOME");
+            }
+            found.push(toolchain.path_entry(dir));
+        }
+        if has("mvn") {
+            let version = jar_version(&home.join("lib"), "maven-core-");
+            found.push(Toolchain::new(Tool::Maven, "mvn", home, version).variable("MAVEN_HOME").path_entry(dir));
+        }
+        if has("gradle") {
+            let version = jar_version(&home.join("lib"), "gradle-launcher-");
+            found.push(Toolchain::new(Tool::Gradle, "gradle", home, version).variable("GRADLE_HOME").path_entry(dir));
+        }
+        if has("nvcc") {
+            let version = read_cuda_version(home);
+            let name = if cfg!(windows) { "CUDA_PATH" } else { "CUDA_HOME" };
+            found.push(Toolchain::new(Tool::Cuda, "nvcc", home, version).variable(name).path_entry(dir));
+        }
+    }
+    if let Some(home) = parent.filter(|_| dir_name == "platform-tools" && has("adb")) {
+        let version = read_property(&dir.join("source.properties"), "Pkg.Revision");
+        found.push(
+            Toolchain::new(Tool::AndroidSdk, "adb", home, version)
+                .variable("ANDROID_HOME")
+                .path_entry(dir),
+        );
+    }
+    if has("python") || has("python3") {
+        let command = if has("python") { "python" } else { "python3" };
+        found.extend(detect_python(dir, &dir_name, command));
+    }
+    if has("node") {
+        // the Windows zip has node.exe in its root, unix builds have bin/node
+        let home = if dir_name == "bin" { parent.unwrap_or(dir) } else { dir };
+        let version = read_node_version(home);
+        found.push(Toolchain::new(Tool::Node, "node", home, version).path_entry(dir));
+    }
+    if has("ffmpeg") {
+        // ffmpeg-6.1-full_build/bin
+        let home = if dir_name == "bin" { parent.unwrap_or(dir) } else { dir };
+        let version = home
+            .file_name()
+            .and_then(|n| n.to_str())
+            .and_then(|n| n.to_lowercase().strip_prefix("ffmpeg-").map(|v| v.to_string()))
+            .and_then(|v| v.split(|c: char| c != '.' && !c.is_ascii_digit()).next().map(|v| v.to_string()))
+            .filter(|v| !v.is_empty());
+        found.push(Toolchain::new(Tool::Ffmpeg, "ffmpeg", home, version).path_entry(dir));
+    }
+    found
+}
+
+// Windows 安装目录下直接有 python.exe 和 Scripts，unix 是 bin/python3 和 lib/python3.x
+fn detect_python(dir: &Path, dir_name: &str, command: &str) -> Option<Toolchain> {
+    if dir_name == "bin" {
+        let home = dir.parent()?;
+        let lib = fs::read_dir(home.join("lib")).ok()?;
+        let version = lib
+            .filter_map(|e| e.ok())
+            .filter_map(|e| e.file_name().to_str()?.strip_prefix("python").map(|v| v.to_string()))
+            .filter(|v| v.starts_with(|c: char| c.is_ascii_digit()))
+            .max();
+        return Some(Toolchain::new(Tool::Python, command, home, version).path_entry(dir));
+    }
+    // python311.dll
+    let version = fs::read_dir(dir)
+        .ok()?
+        .filter_map(|e| e.ok())
+        .filter_map(|e| {
+            let name = e.file_name().to_str()?.to_lowercase();
+            let digits = name.strip_prefix("python")?.strip_suffix(".dll")?.to_string();
+            (digits.len() >= 2 && digits.chars().all(|c| c.is_ascii_digit())).then(|| format!("{}.{}", &digits[..1], &digits[1..]))
+        })
+        .max();
+    let toolchain = Toolchain::new(Tool::Python, command, dir, version).path_entry(dir);
+    let scripts = dir.join("Scripts");
+    Some(if scripts.is_dir() { toolchain.path_entry(&scripts) } else { toolchain })
+}
+
+/// `KEY="value"` or `KEY=value`, as in the JDK `release` file and `source.properties`
+fn read_property(file: &Path, key: &str) -> Option<String> {
+    let content = fs::read_to_string(file).ok()?;
+    content.lines().find_map(|l| {
+        let (k, v) = l.split_once('=')?;
+        (k.trim() == key).then(|| v.trim().trim_matches('"').to_string())
+    })
+}
+
+fn read_first_line(file: &Path) -> Option<String> {
+    let content = fs::read_to_string(file).ok()?;
+    content.lines().next().map(|l| l.trim().to_string()).filter(|l| !l.is_empty())
+}
+
+/// `maven-core-3.9.6.jar` in `lib`
+fn jar_version(lib: &Path, prefix: &str) -> Option<String> {
+    fs::read_dir(lib).ok()?.filter_map(|e| e.ok()).find_map(|e| {
+        let name = e.file_name().to_str()?.to_string();
+        Some(name.strip_prefix(prefix)?.strip_suffix(".jar")?.to_string())
+    })
+}
+
+// version.json 从 11.1 开始有，更早的是 version.txt: `CUDA Version 10.2.89`
+fn read_cuda_version(home: &Path) -> Option<String> {
+    if let Ok(content) = fs::read_to_string(home.join("version.json")) {
+        let json: serde_json::Value = serde_json::from_str(&content).ok()?;
+        return json["cuda"]["version"].as_str().map(|v| v.to_string());
+    }
+    let line = read_first_line(&home.join("version.txt"))?;
+    line.strip_prefix("CUDA Version ").map(|v| v.to_string())
+}
+
+/// `#define NODE_MAJOR_VERSION 20` and so on in the bundled headers
+fn read_node_version(home: &Path) -> Option<String> {
+    let content = fs::read_to_string(home.join("include").join("node").join("node_version.h")).ok()?;
+    let part = |name: &str| {
+        content.lines().find_map(|l| {
+            let rest = l.trim().strip_prefix("#define ")?.strip_prefix(name)?;
+            Some(rest.trim().to_string())
+        })
+    };
+    Some(format!("{}.{}.{}", part("NODE_MAJOR_VERSION ")?, part("NODE_MINOR_VERSION ")?, part("NODE_PATCH_VERSION ")?))
+}
+
+#[test]
+fn test_detect_toolchains() {
+    let root = crate::testutil::TempDir::new("toolchain");
+    let jdk = root.join("jdk-17");
+    fs::create_dir_all(jdk.join("bin")).unwrap();
+    fs::write(jdk.join("release"), "IMPLEMENTOR=\"Eclipse Adoptium\"\nJAVA_VERSION=\"17.0.9\"\n").unwrap();
+    let go = root.join("go");
+    fs::create_dir_all(go.join("bin")).unwrap();
+    fs::write(go.join("VERSION"), "go1.22.1\ntime 2024-03-01\n").unwrap();
+    let sdk = root.join("Android").join("Sdk");
+    fs::create_dir_all(sdk.join("platform-tools")).unwrap();
+    fs::write(sdk.join("platform-tools").join("source.properties"), "Pkg.Revision=34.0.5\n").unwrap();
+
+    let names = |n: &[&str]| n.iter().map(|s| s.to_string()).collect::<HashSet<String>>();
+    let java = detect(&jdk.join("bin"), &names(&["java", "javac"]));
+    assert_eq!(java.len(), 1);
+    assert_eq!(java[0].version.as_deref(), Some("17.0.9"));
+    assert_eq!(java[0].variables, vec![("JAVA_HOME".to_string(), jdk.to_str().unwrap().to_string())]);
+    let golang = detect(&go.join("bin"), &names(&["go", "gofmt"]));
+    assert_eq!((golang[0].tool, golang[0].version.as_deref()), (Tool::Go, Some("1.22.1")));
+    let android = detect(&sdk.join("platform-tools"), &names(&["adb"]));
+    assert_eq!((android[0].tool, android[0].version.as_deref()), (Tool::AndroidSdk, Some("34.0.5")));
+    // 没有 release 文件的 java 不算 JDK
+    assert!(detect(&root.join("bin"), &names(&["java"])).is_empty());
+
+    let mut user = EnvHashMap::new();
+    user.insert("PATH".to_string(), vec![]);
+    let tasks = java[0].tasks(&user);
+    assert_eq!(tasks.len(), 3);
+    let mut env = user.clone();
+    for task in tasks.iter() {
+        crate::task::ConsumeTask::forward(task, &mut env);
+    }
+    assert!(java[0].tasks(&env).is_empty());
+}
+
+#[cfg(not(windows))]
+#[test]
+fn test_use_toolchain() {
+    use crate::task::ConsumeTask;
+    use crate::testutil::{executable, TempDir};
+
+    let root = TempDir::new("toolchain_use");
+    let names: HashSet<String> = ["java".to_string()].into_iter().collect();
+    let mut jdks = vec![];
+    for version in ["11", "17"] {
+        let home = root.join(format!("jdk-{}", version));
+        executable(&home.join("bin").join("java"));
+        fs::write(home.join("release"), format!("JAVA_VERSION=\"{}\"\n", version)).unwrap();
+        jdks.extend(detect(&home.join("bin"), &names));
+    }
+    let (jdk11, jdk17) = (&jdks[0], &jdks[1]);
+
+    let mut user = EnvHashMap::new();
+    user.insert("JAVA_HOME".to_string(), vec![jdk11.home.clone()]);
+    let system = root.join("bin").to_str().unwrap().to_string();
+    user.insert("PATH".to_string(), vec![system.clone(), "$JAVA_HOME/bin".to_string(), jdk17.path_entries[0].clone()]);
+    assert!(jdk11.is_active(&user));
+    assert!(!jdk17.is_active(&user));
+
+    // jdk17 的条目已经在 PATH 里但排在后面，要挪到最前面
+    let mut env = user.clone();
+    for task in jdk17.tasks(&user) {
+        task.forward(&mut env);
+    }
+    assert_eq!(env["PATH"], vec![jdk17.path_entries[0].as_str(), &system, "$JAVA_HOME/bin"]);
+    assert!(jdk17.is_active(&env));
+    assert!(!jdk11.is_active(&env));
+    assert!(jdk17.tasks(&env).is_empty());
+}
+
+#[cfg(windows)]
+#[test]
+fn test_detect_toolchains_skips_shadowed_installs() {
+    use crate::scanner::{ScanRoot, StorageUpdater};
+    use crate::testutil::TempDir;
+
+    let root = TempDir::new("toolchain_shadowed");
+    let home = root.join("jdk-17");
+    fs::create_dir_all(home.join("bin")).unwrap();
+    fs::write(home.join("bin").join("java.exe"), "MZ").unwrap();
+    fs::write(home.join("release"), "JAVA_VERSION=\"17\"\n").unwrap();
+    // 系统 PATH 里另有一个 java，排在所有用户条目前面
+    let system = root.join("system");
+    fs::create_dir_all(&system).unwrap();
+    fs::write(system.join("java.exe"), "MZ").unwrap();
+    let storage = StorageUpdater::from(Storage::default())
+        .roots(&[ScanRoot::new(home.to_str().unwrap())])
+        .consume_subtree(home.to_str().unwrap());
+
+    let mut machine = EnvHashMap::new();
+    machine.insert("Path".to_string(), vec![system.to_str().unwrap().to_string()]);
+    let mut user = EnvHashMap::new();
+    user.insert("Path".to_string(), vec![]);
+    let installs = detect_toolchains(&storage, &machine, &user);
+    let jdk = &installs[0].installs[0];
+    assert!(!jdk.active);
+    assert!(jdk.task.is_none());
+
+    // 用户 PATH 能赢的时候才给任务
+    let installs = detect_toolchains(&storage, &EnvHashMap::new(), &user);
+    assert!(installs[0].installs[0].task.is_some());
+}
@@ -0,0 +1,161 @@
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+use crate::expand::{expand, lookup};
+use crate::lint::is_path_variable;
+use crate::task::EnvHashMap;
+
+pub const DEFAULT_PATHEXT: &str = ".COM;.EXE;.BAT;.CMD;.VBS;.VBE;.JS;.JSE;.WSF;.WSH;.MSC";
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Candidate {
+    /// the PATH entry as written, e.g. `%JAVA_HOME%\bin`
+    pub entry: String,
+    /// index of the entry in PATH
+    pub index: usize,
+    pub file: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Resolution {
+    pub winner: Option<Candidate>,
+    pub shadowed: Vec<Candidate>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WhichReport {
+    pub command: String,
+    /// resolved against the unflushed env
+    pub pending: Resolution,
+    /// resolved against the env of the last flush
+    pub committed: Resolution,
+    /// the winner differs between pending and committed
+    pub changed: bool,
+}
+
+impl WhichReport {
+    pub fn new(command: &str, pending: &EnvHashMap, committed: &EnvHashMap) -> Self {
+        let pending = resolve(command, pending);
+        let committed = resolve(command, committed);
+        let changed = pending.winner.as_ref().map(|c| &c.file) != committed.winner.as_ref().map(|c| &c.file);
+        Self {
+            command: command.to_string(),
+            pending,
+            committed,
+            changed,
+        }
+    }
+}
+
+/// resolve `command` the way the OS does, every PATH entry in order
+pub fn resolve(command: &str, env: &EnvHashMap) -> Resolution {
+    let pathext = pathext(env);
+    let mut candidates = vec![];
+    for (index, entry) in path_entries(env).into_iter().enumerate() {
+        let dir = match expand(&entry, env) {
+            Some(d) => d,
+            None => continue,
+        };
+        for file in file_names(command, &pathext) {
+            let p = Path::new(dir.trim()).join(&file);
+            if is_executable(&p) {
+                candidates.push(Candidate {
+                    entry: entry.clone(),
+                    index,
+                    file: p.to_str().unwrap().to_string(),
+                });
+            }
+        }
+    }
+    let mut candidates = candidates.into_iter();
+    Resolution {
+        winner: candidates.next(),
+        shadowed: candidates.collect(),
+    }
+}
+
+pub fn path_entries(env: &EnvHashMap) -> Vec<String> {
+    env.iter()
+        .find(|(k, _)| is_path_variable(k))
+        .map(|(_, v)| v.clone())
+        .unwrap_or_default()
+}
+
+/// extensions tried in order, lower case, e.g. [".com", ".exe"]
+pub fn pathext(env: &EnvHashMap) -> Vec<String> {
+    let value = match lookup("PATHEXT", env) {
+        Some(v) => v,
+        None => DEFAULT_PATHEXT.to_string(),
+    };
+    split_pathext(&value)
+}
+
+/// `.COM;.EXE` -> [".com", ".exe"]
+pub fn split_pathext(value: &str) -> Vec<String> {
+    value
+        .split(';')
+        .filter(|s| !s.trim().is_empty())
+        .map(|s| s.trim().to_lowercase())
+        .collect()
+}
+
+/// file names tried in one directory, in order
+#[cfg(windows)]
+fn file_names(command: &str, pathext: &[String]) -> Vec<String> {
+    let lower = command.to_lowercase();
+    // an explicit known extension is tried as is first
+    let mut names = vec![];
+    if pathext.iter().any(|ext| lower.ends_with(ext.as_str())) {
+        names.push(command.to_string());
+    }
+    names.extend(pathext.iter().map(|ext| format!("{}{}", command, ext)));
+    names
+}
+
+#[cfg(not(windows))]
+fn file_names(command: &str, _pathext: &[String]) -> Vec<String> {
+    vec![command.to_string()]
+}
+
+#[cfg(windows)]
+pub fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
+
+#[cfg(not(windows))]
+pub fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    match std::fs::metadata(path) {
+        Ok(m) => m.is_file() && m.permissions().mode() & 0o111 != 0,
+        Err(_) => false,
+    }
+}
+
+#[cfg(not(windows))]
+#[test]
+fn test_resolve_shadowing() {
+    use std::os::unix::fs::PermissionsExt;
+    let root = crate::testutil::TempDir::new("which");
+    for d in ["a", "b", "c"] {
+        std::fs::create_dir_all(root.join(d)).unwrap();
+    }
+    for d in ["b", "c"] {
+        let f = root.join(d).join("tool");
+        std::fs::write(&f, "#!/bin/sh\n").unwrap();
+        std::fs::set_permissions(&f, std::fs::Permissions::from_mode(0o755)).unwrap();
+    }
+    let entries: Vec<String> = ["a", "b", "c"]
+        .iter()
+        .map(|d| root.join(d).to_str().unwrap().to_string())
+        .collect();
+    let mut committed = EnvHashMap::new();
+    committed.insert("PATH".to_string(), entries.clone());
+    let mut pending = committed.clone();
+    pending.get_mut("PATH").unwrap().swap(1, 2);
+
+    let report = WhichReport::new("tool", &pending, &committed);
+    assert_eq!(report.committed.winner.unwrap().index, 1);
+    assert_eq!(report.pending.winner.as_ref().unwrap().entry, entries[2]);
+    assert_eq!(report.pending.shadowed.len(), 1);
+    assert!(report.changed);
+}
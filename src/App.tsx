@@ -12,6 +12,10 @@ function backendEventResolver(n: { event: string, id: number, payload: any }) {
   switch (n.event) {
     case "notification":
       return emitter.emit("notification", n.payload);
+    case "env-changed-externally":
+      return emitter.emit("env-changed-externally", n.payload);
+    case "state-changed":
+      return emitter.emit("state-changed", null);
     default:
       return n;
   }
@@ -20,6 +24,7 @@ function backendEventResolver(n: { event: string, id: number, payload: any }) {
 import Main from "@/views/Main";
 import Setting from "@/views/Setting";
 import FSTree from "@/views/FSTree";
+import Processes from "@/views/Processes";
 
 function App() {
   useEffect(() => {
@@ -27,8 +32,17 @@ function App() {
       console.log("[App useEffect] notification", n);
       backendEventResolver(n);
     })
+    const unlistenExternal = event.listen("env-changed-externally", (n: any) => {
+      console.log("[App useEffect] env-changed-externally", n);
+      backendEventResolver(n);
+    })
+    const unlistenState = event.listen("state-changed", (n: any) => {
+      backendEventResolver(n);
+    })
     return () => {
       unlisten.then((u) => u());
+      unlistenExternal.then((u) => u());
+      unlistenState.then((u) => u());
     }
   }, []);
 
@@ -38,12 +52,14 @@ function App() {
       <div className="tab">
         <Link className={_active} href="/">Main</Link>
         <Link className={_active} href="/FSTree">FSTree</Link>
+        <Link className={_active} href="/Processes">Processes</Link>
         <Link className={_active} href="/Setting">Setting</Link>
       </div>
       <div className="tab-content">
         <Switch>
           <Route path="/"><Main /></Route>
           <Route path="/FSTree"><FSTree /></Route>
+          <Route path="/Processes"><Processes /></Route>
           <Route path="/Setting"><Setting /></Route>
           {/* Default route in a switch */}
           <Route>
@@ -2,8 +2,16 @@ import { invoke } from "@tauri-apps/api/core";
 import mitt from "mitt";
 import { INotification } from "./components/utils/Notification";
 
+interface IExternalChange {
+    scope: "User" | "Machine",
+    diff: { changes: { variable: string, old: string[] | null, new: string[] | null }[] },
+}
+
 interface IEventType {
     "notification": INotification,
+    "env-changed-externally": IExternalChange[],
+    // 通过 RPC 修改了状态，界面需要重新加载
+    "state-changed": null,
 }
 
 type IEmitter = {
@@ -72,20 +80,114 @@ type EnvHashMap = { [key: string]: string[] };
 async function flush(): Promise<void> {
     return invoke("flush");
 }
-async function receive_state(): Promise<{ env: EnvHashMap, dirty: boolean }> {
+interface IShadowChange {
+    name: string,
+    committed: { entry: string, index: number, file: string } | null,
+    pending: { entry: string, index: number, file: string } | null,
+}
+async function flush_preview(): Promise<{ shadow: { changes: IShadowChange[] } }> {
+    return invoke("flush_preview");
+}
+interface ILaunchResult {
+    program: string,
+    code: number | null,
+    stdout: string,
+    stderr: string,
+    timed_out: boolean,
+}
+// 用未提交的环境变量运行命令，失败时返回 null 并弹通知
+async function launch(command: string, args: string[] = [], cwd?: string): Promise<ILaunchResult | null> {
+    return invoke("launch", { option: { command, args, cwd } });
+}
+async function open_terminal(cwd?: string): Promise<void> {
+    return invoke("open_terminal", { cwd });
+}
+interface IProcessInfo {
+    pid: number,
+    name: string,
+}
+interface IProcessReport {
+    process: IProcessInfo,
+    // old 是进程里的值，new 是已提交的值
+    diff: { changes: { variable: string, old: string[] | null, new: string[] | null }[] },
+}
+async function processes(): Promise<IProcessInfo[]> {
+    return invoke("processes");
+}
+// 读不到进程环境时返回 null 并弹通知
+async function inspect_process(pid: number): Promise<IProcessReport | null> {
+    return invoke("inspect_process", { pid });
+}
+// 期望状态文件（TOML），check 只报告差异，apply 生成一组可撤销的任务
+async function desired_check(path: string): Promise<{ [kind: string]: any }[]> {
+    return invoke("desired_check", { path });
+}
+async function desired_apply(path: string): Promise<void> {
+    return invoke("desired_apply", { path });
+}
+// 本地 RPC 服务，返回端口，未启动时为 null
+async function rpc_start(port?: number): Promise<number | null> {
+    return invoke("rpc_start", { port });
+}
+async function rpc_stop(): Promise<void> {
+    return invoke("rpc_stop");
+}
+async function rpc_status(): Promise<number | null> {
+    return invoke("rpc_status");
+}
+async function receive_state(): Promise<{ env: EnvHashMap, dirty: boolean, active_profile: string | null }> {
     return invoke("send_state")
 }
+type IProfileEntry =
+    | { Set: { variable: string, values: string[] } }
+    | { Prepend: { variable: string, value: string } }
+    | { Append: { variable: string, value: string } };
+interface IProfile {
+    name: string;
+    entries: IProfileEntry[];
+}
+async function profile_list(): Promise<IProfile[]> {
+    return invoke("profile_list");
+}
+async function profile_save(profile: IProfile): Promise<void> {
+    return invoke("profile_save", { profile });
+}
+async function profile_delete(name: string): Promise<void> {
+    return invoke("profile_delete", { name });
+}
+// 激活和停用都是一个任务组，可以撤销
+async function profile_activate(name: string): Promise<void> {
+    return invoke("profile_activate", { name });
+}
+async function profile_deactivate(): Promise<void> {
+    return invoke("profile_deactivate");
+}
 async function undo(): Promise<void> {
     return invoke("undo")
 }
+// 把未提交的任务移到外部修改后的环境上
+async function rebase(): Promise<void> {
+    return invoke("rebase")
+}
 
+interface IVolume {
+    mount_point: string;
+    device: string;
+    fs_type: string;
+    kind: "Local" | "Pseudo" | "Network";
+    total: number;
+    free: number;
+}
 interface TreeNode {
     name: string;
     abs_path: string;
     size: number;
     scripts_count: number;
+    categories: Record<string, number>;
     is_dir: boolean;
     is_allowed: boolean;
+    is_ignored: boolean;
+    volume: IVolume | null;
 }
 async function FST_get_children(absPath?: string): Promise<TreeNode[]> {
     return invoke("FST_children", { absPath });
@@ -93,8 +195,105 @@ async function FST_get_children(absPath?: string): Promise<TreeNode[]> {
 async function FST_scan(): Promise<void> {
     return invoke("FST_scan");
 }
+async function FST_scan_subtree(path: string): Promise<void> {
+    return invoke("FST_scan_subtree", { path });
+}
+interface IScanRoot {
+    path: string;
+    include: string[];
+    exclude: string[];
+}
+interface ISettings {
+    scan_roots: IScanRoot[];
+    ignore: string[];
+    categories: Record<string, string[]>;
+}
+async function settings_get(): Promise<ISettings> {
+    return invoke("settings_get");
+}
+async function settings_save(settings: ISettings): Promise<void> {
+    return invoke("settings_save", { settings });
+}
+interface ILocation {
+    file: string;
+    dir: string;
+    on_path: boolean;
+    // 后端生成好的任务，原样交给 receive_state
+    add_to_path: object | null;
+}
+async function FST_find_executable(command: string): Promise<ILocation[]> {
+    return invoke("FST_find_executable", { command });
+}
+interface ISuggestion {
+    dir: string;
+    score: number;
+    executables: number;
+    new_commands: string[];
+    reasons: string[];
+    append: object;
+    prepend: object;
+}
+async function FST_suggest_path_entries(): Promise<ISuggestion[]> {
+    return invoke("FST_suggest_path_entries");
+}
+interface IToolchain {
+    tool: string;
+    command: string;
+    home: string;
+    version: string | null;
+    variables: [string, string][];
+    path_entries: string[];
+}
+interface IInstall {
+    toolchain: IToolchain;
+    active: boolean;
+    task: object | null;
+}
+interface IToolInstalls {
+    tool: string;
+    installs: IInstall[];
+}
+async function FST_toolchains(): Promise<IToolInstalls[]> {
+    return invoke("FST_toolchains");
+}
+interface IPathPlan {
+    dirs: string[];
+    covered: Record<string, string>;
+    available: string[];
+    missing: string[];
+    explanations: string[];
+}
+async function FST_solve_path(commands: string[]): Promise<IPathPlan> {
+    return invoke("FST_solve_path", { commands });
+}
+// 按应用时的 PATH 生成任务，避免方案过期
+async function FST_apply_path_plan(dirs: string[]): Promise<void> {
+    return invoke("FST_apply_path_plan", { dirs });
+}
+interface IShim {
+    name: string;
+    file: string;
+    target: string;
+    broken: boolean;
+}
+async function shim_list(): Promise<IShim[]> {
+    return invoke("shim_list");
+}
+async function shim_create(target: string, name?: string): Promise<void> {
+    return invoke("shim_create", { target, name });
+}
+async function shim_update(name: string, target: string): Promise<void> {
+    return invoke("shim_update", { name, target });
+}
+async function shim_remove(name: string): Promise<void> {
+    return invoke("shim_remove", { name });
+}
+// 后端先检查任务能否应用，失败时发 notification，之后都会触发 state-changed
+async function receive_task(task: object): Promise<void> {
+    return invoke("receive_task", { task });
+}
 async function FST_state(): Promise<boolean> {
     return invoke("FST_state");
 }
-export { flush, TaskAction, receive_state, undo, FST_get_children, FST_scan, FST_state };
-export type { EnvHashMap };
\ No newline at end of file
+export { flush, flush_preview, rpc_start, rpc_stop, rpc_status, desired_check, desired_apply, launch, open_terminal, processes, inspect_process, TaskAction, receive_state, undo, rebase, profile_list, profile_save, profile_delete, profile_activate, profile_deactivate, FST_get_children, FST_scan, FST_scan_subtree, FST_find_executable, FST_suggest_path_entries, FST_toolchains, FST_solve_path, FST_apply_path_plan, receive_task, FST_state, settings_get, settings_save, shim_list, shim_create, shim_update, shim_remove };
+export type { EnvHashMap, IExternalChange, IProfile, ILaunchResult, IProcessInfo, IProcessReport, IVolume, ISettings, ILocation, ISuggestion, IToolInstalls, IShim, IPathPlan };
\ No newline at end of file
@@ -1,7 +1,8 @@
 import { create } from "zustand";
 import { createRef, useEffect, useState } from "react";
 import { open as _open, ask as _ask } from '@tauri-apps/plugin-dialog';
-import { FST_get_children as _FST_get_children, FST_scan as _FST_scan, FST_state as _FST_state } from '@/core';
+import { FST_get_children as _FST_get_children, FST_scan as _FST_scan, FST_scan_subtree as _FST_scan_subtree, FST_state as _FST_state, FST_find_executable as _FST_find_executable, FST_suggest_path_entries as _FST_suggest_path_entries, FST_toolchains as _FST_toolchains, FST_solve_path as _FST_solve_path, FST_apply_path_plan as _FST_apply_path_plan, receive_task as _receive_task, shim_list as _shim_list, shim_create as _shim_create, shim_update as _shim_update, shim_remove as _shim_remove, emitter } from '@/core';
+import type { IVolume, ILocation, ISuggestion, IToolInstalls, IShim, IPathPlan } from '@/core';
 
 import '@/styles/FSTree.scss';
 
@@ -10,8 +11,11 @@ interface TreeNode {
     absPath: string;
     size: number;
     scriptsCount: number;
+    categories: Record<string, number>;
     isDir: boolean;
     isAllow: boolean;
+    isIgnored: boolean;
+    volume: IVolume | null;
     children?: TreeNode[];
 }
 
@@ -24,8 +28,11 @@ async function getChildren(path?: string): Promise<TreeNode[]> {
         absPath: child.abs_path,
         size: child.size,
         scriptsCount: child.scripts_count,
+        categories: child.categories,
         isDir: child.is_dir,
         isAllow: child.is_allowed,
+        isIgnored: child.is_ignored,
+        volume: child.volume,
     }));
 }
 
@@ -35,7 +42,11 @@ interface IStore {
     choose: (node: TreeNode) => Promise<void>;
     init: () => Promise<void>;
     scan: () => Promise<void>;
+    scanSubtree: (node: TreeNode) => Promise<void>;
     getState: () => Promise<boolean>;
+    shims: IShim[];
+    loadShims: () => Promise<void>;
+    createShim: (node: TreeNode) => Promise<void>;
 }
 
 const useStore = create<IStore>((set, _) => ({
@@ -62,8 +73,23 @@ const useStore = create<IStore>((set, _) => ({
         const children = await getChildren();
         set({ tree: children, chosen: null });
     },
+    // 只重新扫描选中的目录，结果合并回去
+    scanSubtree: async (node) => {
+        await _FST_scan_subtree(node.absPath);
+        const children = await getChildren();
+        set({ tree: children, chosen: null });
+    },
     getState: async () => {
         return await _FST_state();
+    },
+    shims: [],
+    loadShims: async () => {
+        set({ shims: await _shim_list() });
+    },
+    // 只给这一个可执行文件生成转发脚本，不把整个目录加进 PATH
+    createShim: async (node) => {
+        await _shim_create(node.absPath);
+        set({ shims: await _shim_list() });
     }
 }));
 
@@ -90,8 +116,10 @@ function TreeView({ node, style, align }: ITreeView) {
             <li ref={thisTree}
                 onDoubleClick={doubleClick}
                 onClick={click}
-                className={chosen?.name == node.name ? "chosen" : ""}>
+                className={[chosen?.name == node.name ? "chosen" : "", node.isIgnored ? "ignored" : ""].join(" ")}
+                title={node.isIgnored ? "Ignored by the scan rules" : undefined}>
                 {node.isDir ? "📁" : "📄"}{node.name}
+                {node.volume && <CapacityBar volume={node.volume} />}
             </li>
             {node.children?.map((child, i) => (
                 <TreeView key={i} style={{ display: open ? "block" : "none" }} node={child} align={align} />
@@ -100,16 +128,50 @@ function TreeView({ node, style, align }: ITreeView) {
     );
 }
 
-function Details({ chosen }: { chosen: TreeNode }) {
-    const show_size = (size: number) => {
-        const SIZES = ['B', 'KB', 'MB', 'GB', 'TB'];
-        let i = 0;
-        while (size > 1024) {
-            size /= 1024;
-            i++;
-        }
-        return `${size.toFixed(2)} ${SIZES[i]}`;
+const show_size = (size: number) => {
+    const SIZES = ['B', 'KB', 'MB', 'GB', 'TB'];
+    let i = 0;
+    while (size > 1024) {
+        size /= 1024;
+        i++;
     }
+    return `${size.toFixed(2)} ${SIZES[i]}`;
+}
+
+// 自定义分类直接显示名字
+const CATEGORY_LABELS: Record<string, string> = {
+    executable: "executables",
+    library: "libraries",
+    script: "scripts",
+    archive: "archives",
+    installer: "installers",
+};
+
+const show_categories = (categories: Record<string, number>) => {
+    return Object.entries(categories)
+        .sort(([, a], [, b]) => b - a)
+        .map(([name, count]) => `${count} ${CATEGORY_LABELS[name] ?? name}`)
+        .join(", ");
+}
+
+function CapacityBar({ volume }: { volume: IVolume }) {
+    // 容量未知（例如网络盘）就不画
+    if (volume.total == 0) return null;
+    const used = (volume.total - volume.free) / volume.total;
+    return (
+        <span className="capacity" title={`${show_size(volume.free)} free of ${show_size(volume.total)}`}>
+            <span className={used > 0.9 ? "used full" : "used"} style={{ width: `${(used * 100).toFixed(1)}%` }} />
+        </span>
+    );
+}
+
+// 同 is_command，库也计入 scriptsCount 但不能做 shim
+function isCommand(node: TreeNode) {
+    return (node.categories["executable"] ?? 0) > 0 || (node.categories["script"] ?? 0) > 0;
+}
+
+function Details({ chosen }: { chosen: TreeNode }) {
+    const { scanSubtree, createShim } = useStore();
     return (
         <div className="details">
             <h2>{chosen.name}</h2>
@@ -117,9 +179,14 @@ function Details({ chosen }: { chosen: TreeNode }) {
             <p>{chosen.size} bytes</p>
             <p>{show_size(chosen.size)}</p>
             <p>{chosen.scriptsCount} scripts</p>
+            {Object.keys(chosen.categories).length > 0 && <p>{show_categories(chosen.categories)}</p>}
             <p>{chosen.isDir ? "Directory" : "File"}</p>
             <p>{chosen.isAllow ? "Allow" : "Deny"}</p>
+            {chosen.isIgnored && <p>Ignored by the scan rules, see Settings</p>}
+            {chosen.volume && <p>{chosen.volume.fs_type} on {chosen.volume.device}, {show_size(chosen.volume.free)} free of {show_size(chosen.volume.total)}</p>}
             <div className="btn-group" data-mode="col" data-style="dark">
+                {chosen.isDir && !chosen.isIgnored && <button onClick={() => scanSubtree(chosen)}>Rescan this folder</button>}
+                {!chosen.isDir && isCommand(chosen) && <button onClick={() => createShim(chosen)}>Create shim</button>}
                 {/* <button onClick={() => { }}>Reveal in File Explorer</button>
                 <button onClick={() => { }}>Add to Path</button> */}
             </div>
@@ -127,6 +194,182 @@ function Details({ chosen }: { chosen: TreeNode }) {
     );
 }
 
+// 任务应用之后（不管成功与否）后端会发 state-changed，已经生成的任务可能过期了，重新加载
+function useStateChanged(reload: () => void, deps: React.DependencyList) {
+    useEffect(() => {
+        emitter.on("state-changed", reload);
+        return () => emitter.off("state-changed", reload);
+    }, deps);
+}
+
+// 在扫描过的目录里找命令，例如格式工厂里的 ffmpeg
+function FindExecutable() {
+    const [command, setCommand] = useState("");
+    const [found, setFound] = useState<ILocation[] | null>(null);
+    const find = async () => {
+        if (command.trim().length == 0) return;
+        setFound(await _FST_find_executable(command.trim()));
+    }
+    useStateChanged(() => {
+        if (found) find();
+    }, [found, command]);
+    return (
+        <div className="details">
+            <div className="btn-group" data-mode="row" data-style="dark">
+                <input
+                    onChange={(e) => setCommand(e.currentTarget.value)}
+                    onKeyDown={(e) => e.key == "Enter" && find()}
+                    placeholder="Find executable, e.g. ffmpeg"
+                    value={command}
+                />
+                <button onClick={find}>Find</button>
+            </div>
+            {found?.length == 0 && <p>Not found in the scanned directories</p>}
+            {found?.map((location, i) => (
+                <div key={i} className="item">
+                    <p title={location.file}>{location.dir}</p>
+                    {location.on_path
+                        ? <p>On PATH</p>
+                        : <button onClick={() => _receive_task(location.add_to_path!)}>Add to PATH</button>}
+                </div>
+            ))}
+        </div>
+    );
+}
+
+// 扫描结果里值得加进 PATH 的目录，分数高的在前
+function Suggestions() {
+    const [suggestions, setSuggestions] = useState<ISuggestion[] | null>(null);
+    const suggest = async () => {
+        setSuggestions(await _FST_suggest_path_entries());
+    }
+    useStateChanged(() => {
+        if (suggestions) suggest();
+    }, [suggestions]);
+    return (
+        <div className="details">
+            <div className="btn-group" data-mode="row" data-style="dark">
+                <button onClick={suggest}>Suggest PATH entries</button>
+            </div>
+            {suggestions?.length == 0 && <p>Nothing to suggest, try scanning first</p>}
+            {suggestions?.map((s, i) => (
+                <div key={i} className="item" title={s.reasons.join("\n")}>
+                    <p>{s.dir}</p>
+                    <p>{s.new_commands.slice(0, 5).join(", ")}{s.new_commands.length > 5 ? ", ..." : ""}</p>
+                    <div className="btn-group" data-mode="row" data-style="dark">
+                        <button onClick={() => _receive_task(s.append)}>Append</button>
+                        <button onClick={() => _receive_task(s.prepend)}>Prepend</button>
+                    </div>
+                </div>
+            ))}
+        </div>
+    );
+}
+
+// 按工具列出找到的安装，选一个版本就设置对应的 *_HOME 和 PATH
+function Toolchains() {
+    const [tools, setTools] = useState<IToolInstalls[] | null>(null);
+    const detect = async () => {
+        setTools(await _FST_toolchains());
+    }
+    useStateChanged(() => {
+        if (tools) detect();
+    }, [tools]);
+    return (
+        <div className="details">
+            <div className="btn-group" data-mode="row" data-style="dark">
+                <button onClick={detect}>Detect toolchains</button>
+            </div>
+            {tools?.length == 0 && <p>No known toolchain found, try scanning first</p>}
+            {tools?.map((t) => t.installs.map((install, i) => (
+                <div key={`${t.tool}-${i}`} className="item" title={install.toolchain.variables.map(([k, v]) => `${k}=${v}`).join("\n")}>
+                    <p>{t.tool} {install.toolchain.version ?? ""}</p>
+                    <p>{install.toolchain.home}</p>
+                    {install.active
+                        ? <p>In use</p>
+                        : install.task
+                            ? <button onClick={() => _receive_task(install.task!)}>Use</button>
+                            : <p title={`'${install.toolchain.command}' resolves to another install first`}>Shadowed by machine PATH</p>}
+                </div>
+            )))}
+        </div>
+    );
+}
+
+// 给出需要的命令，算出最少要加哪些目录
+function SolvePath() {
+    const [commands, setCommands] = useState("");
+    const [plan, setPlan] = useState<IPathPlan | null>(null);
+    const solve = async () => {
+        const list = commands.split(/[\s,]+/).filter((c) => c.length > 0);
+        if (list.length == 0) return;
+        setPlan(await _FST_solve_path(list));
+    }
+    useStateChanged(() => {
+        if (plan) solve();
+    }, [plan, commands]);
+    const apply = () => _FST_apply_path_plan(plan!.dirs);
+    return (
+        <div className="details">
+            <div className="btn-group" data-mode="row" data-style="dark">
+                <input
+                    onChange={(e) => setCommands(e.currentTarget.value)}
+                    onKeyDown={(e) => e.key == "Enter" && solve()}
+                    placeholder="Commands you need, e.g. git python ffmpeg"
+                    value={commands}
+                />
+                <button onClick={solve}>Solve</button>
+            </div>
+            {plan && <>
+                {plan.available.length > 0 && <p>Available already: {plan.available.join(", ")}</p>}
+                {plan.missing.length > 0 && <p>Not found in the scanned directories: {plan.missing.join(", ")}</p>}
+                {plan.dirs.map((dir) => (
+                    <div key={dir} className="item">
+                        <p>{dir}</p>
+                        <p>{Object.entries(plan.covered).filter(([_, d]) => d == dir).map(([c, _]) => c).join(", ")}</p>
+                    </div>
+                ))}
+                {plan.explanations.map((e, i) => <p key={i}>{e}</p>)}
+                {plan.dirs.length > 0 && <button onClick={apply}>Add {plan.dirs.length} entries to PATH</button>}
+            </>}
+        </div>
+    );
+}
+
+// 托管的 shims 目录，目标被删掉的标成 broken
+function Shims() {
+    const { shims, loadShims } = useStore();
+    useEffect(() => {
+        loadShims();
+    }, []);
+    const update = async (shim: IShim) => {
+        const target = await _open({ title: `New target of ${shim.name}`, defaultPath: shim.target });
+        if (!target) return;
+        await _shim_update(shim.name, target);
+        await loadShims();
+    }
+    const remove = async (shim: IShim) => {
+        await _shim_remove(shim.name);
+        await loadShims();
+    }
+    return (
+        <div className="details">
+            <h2>Shims</h2>
+            {shims.length == 0 && <p>No shim yet, choose an executable in the tree to create one</p>}
+            {shims.map((shim) => (
+                <div key={shim.name} className={shim.broken ? "item broken" : "item"} title={shim.file}>
+                    <p>{shim.name}</p>
+                    <p>{shim.target}{shim.broken ? " (missing)" : ""}</p>
+                    <div className="btn-group" data-mode="row" data-style="dark">
+                        <button onClick={() => update(shim)}>Update</button>
+                        <button onClick={() => remove(shim)}>Remove</button>
+                    </div>
+                </div>
+            ))}
+        </div>
+    );
+}
+
 export default function () {
     const { chosen, tree, scan: _scan, init, getState } = useStore();
     const [showMask, setShowMask] = useState(false);
@@ -184,6 +427,11 @@ export default function () {
                     <button onClick={scan}>Scan</button>
                 </div>
                 <div className="list">
+                    <FindExecutable />
+                    <Suggestions />
+                    <Toolchains />
+                    <SolvePath />
+                    <Shims />
                     {chosen && <Details chosen={chosen} />}
                 </div>
             </div>
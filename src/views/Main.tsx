@@ -1,10 +1,11 @@
-import type { EnvHashMap } from "@/core";
+import type { EnvHashMap, IExternalChange } from "@/core";
 import Modal from '@@/utils/Modal';
 import '@/styles/Main.scss';
 
 import { create } from "zustand";
 import { useEffect, useState } from "react";
-import { flush as _flush, TaskAction, receive_state as _receive_state, undo as _undo, emitter } from "@/core";
+import { flush as _flush, flush_preview as _flush_preview, TaskAction, receive_state as _receive_state, undo as _undo, rebase as _rebase, profile_list as _profile_list, profile_save as _profile_save, profile_delete as _profile_delete, profile_activate as _profile_activate, profile_deactivate as _profile_deactivate, emitter } from "@/core";
+import type { IProfile } from "@/core";
 import { open as _open, ask as _ask } from '@tauri-apps/plugin-dialog';
 import { INotification } from "@@/utils/Notification";
 import { Checkmark, Delete, Down, FromFS, Up } from "@@/utils/Icons";
@@ -13,9 +14,14 @@ type SyncState = "SYNCED" | "NOT_SYNCED" | "SYNCING";
 interface IStore {
     // EnvHashMap and its actions
     envs: EnvHashMap;
+    activeProfile: string | null;
     load: () => Promise<void>;
     flush: () => Promise<void>;
     undo: () => Promise<void>;
+    // changes made by other programs since the last load, empty once rebased
+    externalChanges: IExternalChange[];
+    setExternalChanges: (changes: IExternalChange[]) => void;
+    rebase: () => Promise<void>;
 
     addVariable: (variable: string) => void;
     deleteVariable: (variable: string) => void;
@@ -36,20 +42,32 @@ interface IStore {
 
 const useStore = create<IStore>((set, get) => ({
     envs: {},
+    externalChanges: [],
+    setExternalChanges: (changes: IExternalChange[]) => set({ externalChanges: changes }),
+    rebase: async () => {
+        await _rebase();
+        set({ externalChanges: [] });
+        await get().load();
+    },
+    activeProfile: null,
     load: async () => {
-        let { env, dirty } = await _receive_state();
-        set((state) => ({ ...state, envs: env, syncState: dirty ? 'NOT_SYNCED' : 'SYNCED' }));
+        let { env, dirty, active_profile } = await _receive_state();
+        set((state) => ({ ...state, envs: env, activeProfile: active_profile, syncState: dirty ? 'NOT_SYNCED' : 'SYNCED' }));
     },
     flush: async () => {
+        const { shadow } = await _flush_preview();
+        if (shadow.changes.length > 0) {
+            const lines = shadow.changes.map((c) => `${c.name}: ${c.committed?.file ?? "-"} -> ${c.pending?.file ?? "-"}`);
+            const ok = await _ask(`These commands will resolve differently:\n${lines.join("\n")}`, "Flush");
+            if (!ok) return;
+        }
         set({ syncState: "SYNCING" });
         await _flush();
-        let { env, dirty } = await _receive_state();
-        set((state) => ({ ...state, envs: env, syncState: dirty ? 'NOT_SYNCED' : 'SYNCED' }));
+        await get().load();
     },
     undo: async () => {
         await _undo();
-        let { env, dirty } = await _receive_state();
-        set((state) => ({ ...state, envs: env, syncState: dirty ? 'NOT_SYNCED' : 'SYNCED' }));
+        await get().load();
     },
 
     // syncState management
@@ -127,10 +145,25 @@ const useStore = create<IStore>((set, get) => ({
 
 export default function Main(props: { style?: React.CSSProperties }) {
     const { style } = props;
-    const { load } = useStore();
+    const { load, setExternalChanges, rebase } = useStore();
 
     useEffect(() => {
         load();
+        // 拒绝之后提示留在 Control 里，随时可以再 rebase
+        const onExternalChange = async (changes: IExternalChange[]) => {
+            setExternalChanges(changes);
+            if (changes.length == 0) return;
+            const ok = await _ask(`Changed by other programs:\n${changeLines(changes).join("\n")}\nRebase pending changes onto them?`, "Environment changed");
+            if (!ok) return;
+            await rebase();
+        };
+        const onStateChange = () => load();
+        emitter.on("env-changed-externally", onExternalChange);
+        emitter.on("state-changed", onStateChange);
+        return () => {
+            emitter.off("env-changed-externally", onExternalChange);
+            emitter.off("state-changed", onStateChange);
+        };
     }, []);
 
     return (
@@ -257,10 +290,83 @@ function Control() {
                 <div>当前应用状态：{stateDom}</div>
                 当前选择的环境变量是：<strong>{currentVariable}</strong>
             </div>
+            <ExternalChanges />
+            <ProfileSwitcher />
         </>
     )
 }
 
+function changeLines(changes: IExternalChange[]) {
+    return changes.flatMap((c) => c.diff.changes.map((v) => `[${c.scope}] ${v.variable}`));
+}
+
+function ExternalChanges() {
+    const { externalChanges, rebase } = useStore();
+    if (externalChanges.length == 0) return null;
+    const lines = changeLines(externalChanges);
+    return (
+        <div className="btn-group" data-mode="row" data-style="light">
+            <span title={lines.join("\n")}>Changed by other programs: {lines.length}</span>
+            <button onClick={rebase}>Rebase</button>
+        </div>
+    )
+}
+
+// 一键切换 profile，激活/停用都会进入待提交的任务里
+function ProfileSwitcher() {
+    const { envs, currentVariable, activeProfile, load } = useStore();
+    const [profiles, setProfiles] = useState<IProfile[]>([]);
+    const [chosen, setChosen] = useState("");
+    const reload = async () => setProfiles(await _profile_list());
+    useEffect(() => {
+        reload();
+    }, []);
+
+    const activate = async () => {
+        if (!chosen) return;
+        await _profile_activate(chosen);
+        await load();
+    }
+    const deactivate = async () => {
+        await _profile_deactivate();
+        await load();
+    }
+    // 用当前选中变量的值新建一个 profile
+    const saveCurrent = async () => {
+        const name = chosen.trim();
+        if (!name || !envs[currentVariable]) return;
+        const old = profiles.find((p) => p.name == name);
+        const entries = (old?.entries ?? []).filter((e) => !("Set" in e && e.Set.variable == currentVariable));
+        entries.push({ Set: { variable: currentVariable, values: envs[currentVariable] } });
+        await _profile_save({ name, entries });
+        await reload();
+    }
+    const remove = async () => {
+        const ok = await _ask(`Delete the profile '${chosen}'?`);
+        if (!ok) return;
+        await _profile_delete(chosen);
+        await reload();
+    }
+
+    return (
+        <div className="btn-group" data-mode="row" data-style="light">
+            <input
+                list="profiles"
+                onChange={(e) => setChosen(e.currentTarget.value)}
+                placeholder="Profile"
+                value={chosen}
+            />
+            <datalist id="profiles">
+                {profiles.map((p) => <option key={p.name} value={p.name} />)}
+            </datalist>
+            <button onClick={activate}>Activate</button>
+            <button onClick={deactivate} disabled={!activeProfile}>Deactivate{activeProfile ? ` '${activeProfile}'` : ""}</button>
+            <button onClick={saveCurrent} title={`Save ${currentVariable} into the profile`}>Save variable</button>
+            <button onClick={remove} disabled={!profiles.some((p) => p.name == chosen)}>Delete</button>
+        </div>
+    )
+}
+
 
 function ValueList() {
     const [buffer, setBuffer] = useState<string>("");
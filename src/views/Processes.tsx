@@ -0,0 +1,96 @@
+import { useEffect, useState } from "react";
+import { processes as _processes, inspect_process as _inspect_process, launch as _launch, open_terminal as _open_terminal } from "@/core";
+import type { IProcessInfo, IProcessReport, ILaunchResult } from "@/core";
+import "@/styles/Main.scss";
+
+// 已经在运行的进程看不到新提交的环境变量，这里列出哪些变量过时了
+export default function Processes() {
+    const [list, setList] = useState<IProcessInfo[]>([]);
+    const [filter, setFilter] = useState("");
+    const [report, setReport] = useState<IProcessReport | null>(null);
+
+    const reload = async () => setList(await _processes());
+    useEffect(() => {
+        reload();
+    }, []);
+
+    const inspect = async (pid: number) => setReport(await _inspect_process(pid));
+    const keyword = filter.trim().toLowerCase();
+    const shown = list.filter((p) => keyword.length == 0 || p.name.toLowerCase().includes(keyword) || p.pid.toString() == keyword);
+
+    return (
+        <div className="row">
+            <div className="col" style={{ '--col-width': '40%' } as React.CSSProperties}>
+                <div className="btn-group" data-mode="row" data-style="light">
+                    <input
+                        onChange={(e) => setFilter(e.currentTarget.value)}
+                        placeholder="Filter by name or pid"
+                        value={filter}
+                    />
+                    <button onClick={reload}>Refresh</button>
+                </div>
+                <div className="list">
+                    {shown.map((p) => (
+                        <div key={p.pid}
+                            className={"item " + (report?.process.pid === p.pid ? "active" : "")}
+                            onClick={() => inspect(p.pid)}>
+                            <p>{p.pid} {p.name}</p>
+                        </div>
+                    ))}
+                </div>
+            </div>
+            <div className="col" style={{ '--col-width': '60%' } as React.CSSProperties}>
+                <Launch />
+                <div className="list">
+                    {report && report.diff.changes.length == 0 && <p>{report.process.name} sees the committed environment</p>}
+                    {report && report.diff.changes.map((c) => (
+                        <div key={c.variable} className="item">
+                            <strong>{c.variable}</strong>
+                            <p>process: {c.old?.join(" ; ") ?? "(missing)"}</p>
+                            <p>committed: {c.new?.join(" ; ") ?? "(missing)"}</p>
+                        </div>
+                    ))}
+                    {report && report.diff.changes.length > 0 && <p>Restart {report.process.name} to pick up the changes</p>}
+                </div>
+            </div>
+        </div>
+    );
+}
+
+// 用未提交的环境变量试运行命令，不用先 flush
+function Launch() {
+    const [commandLine, setCommandLine] = useState("");
+    const [running, setRunning] = useState(false);
+    const [result, setResult] = useState<ILaunchResult | null>(null);
+
+    const run = async () => {
+        const [command, ...args] = commandLine.split(/\s+/).filter((w) => w.length > 0);
+        if (!command) return;
+        setRunning(true);
+        setResult(await _launch(command, args));
+        setRunning(false);
+    }
+
+    return (
+        <div className="details">
+            <div className="btn-group" data-mode="row" data-style="light">
+                <input
+                    onChange={(e) => setCommandLine(e.currentTarget.value)}
+                    onKeyDown={(e) => e.key == "Enter" && run()}
+                    placeholder="e.g. java -version"
+                    value={commandLine}
+                />
+                <button onClick={run} disabled={running}>{running ? "Running..." : "Run"}</button>
+                <button onClick={() => _open_terminal()} title="A terminal with the pending environment">Terminal</button>
+            </div>
+            {result && (
+                <div className="item">
+                    <p>{result.program}</p>
+                    <p>{result.timed_out ? "killed after the timeout" : `exit code ${result.code ?? "none"}`}</p>
+                    {result.stdout && <pre>{result.stdout}</pre>}
+                    {result.stderr && <pre style={{ color: "orange" }}>{result.stderr}</pre>}
+                </div>
+            )}
+        </div>
+    );
+}
@@ -1,14 +1,43 @@
-import { useState } from "react";
-import { flush as _flush, EasyStorage, receive_state as _receive_state, undo as _undo, emitter } from "@/core";
+import { useEffect, useState } from "react";
+import { flush as _flush, rpc_start as _rpc_start, rpc_stop as _rpc_stop, rpc_status as _rpc_status, settings_get as _settings_get, settings_save as _settings_save, EasyStorage, receive_state as _receive_state, undo as _undo, emitter } from "@/core";
 import { open as _open, ask as _ask } from '@tauri-apps/plugin-dialog';
 import { Checkmark } from "@@/utils/Icons";
 import { INotification } from "@/components/utils/Notification";
+import type { ISettings } from "@/core";
 
 export default function Setting() {
     const [buffer, setBuffer] = useState("");
     const [showIndex, setShowIndex] = useState(-1);
+    const [rpcPort, setRpcPort] = useState<number | null>(null);
+    const [settings, setSettings] = useState<ISettings | null>(null);
+    const [ignoreBuffer, setIgnoreBuffer] = useState("");
     const storage = new EasyStorage();
 
+    useEffect(() => {
+        _rpc_status().then(setRpcPort);
+        _settings_get().then(setSettings);
+    }, []);
+
+    // 一行一条规则，内置规则在前面，可以用 ! 取消
+    const saveIgnore: React.MouseEventHandler<HTMLButtonElement> = async (e) => {
+        e.stopPropagation();
+        const ignore = ignoreBuffer.split("\n").map((l) => l.trim()).filter((l) => l.length > 0);
+        const newSettings = { ...settings!, ignore };
+        await _settings_save(newSettings);
+        setSettings(newSettings);
+        setShowIndex(-1);
+    }
+
+    const toggleRpc: React.MouseEventHandler<HTMLDivElement> = async (e) => {
+        e.stopPropagation();
+        if (rpcPort === null) {
+            setRpcPort(await _rpc_start());
+        } else {
+            await _rpc_stop();
+            setRpcPort(null);
+        }
+    }
+
     const setToastTimeout: React.MouseEventHandler<HTMLButtonElement> = (e) => {
         e.stopPropagation();
         const newTimeout = parseInt(buffer);
@@ -48,6 +77,30 @@ export default function Setting() {
                             <button onClick={setToastTimeout}><Checkmark /></button>
                         </div>
                     </div>
+                    {/* 扫描时忽略的目录 */}
+                    <div onClick={() => {
+                        setShowIndex(1);
+                        setIgnoreBuffer(settings?.ignore.join("\n") ?? "");
+                    }} className="item">
+                        <p>扫描忽略规则</p>
+                        <div style={{ display: showIndex === 1 ? 'none' : 'flex' }}>
+                            <p>{settings?.ignore.length ?? 0} 条自定义规则</p>
+                        </div>
+                        <div style={{ display: showIndex === 1 ? 'flex' : 'none', width: '50%', backgroundColor: 'var(--color-dark)' }}
+                            className="btn-group">
+                            <textarea
+                                onChange={(e) => setIgnoreBuffer(e.currentTarget.value)}
+                                placeholder={"node_modules\n!/home/me/keep/node_modules"}
+                                value={ignoreBuffer}
+                            />
+                            <button onClick={saveIgnore}><Checkmark /></button>
+                        </div>
+                    </div>
+                    {/* 本地自动化接口，端口和 token 写在数据目录的 rpc.json */}
+                    <div onClick={toggleRpc} className="item">
+                        <p>本地 RPC 接口</p>
+                        <p>{rpcPort === null ? "关闭" : `127.0.0.1:${rpcPort}`}</p>
+                    </div>
                 </div>
             </div>
         </div>